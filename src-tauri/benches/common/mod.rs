@@ -0,0 +1,41 @@
+// Shared by every bench target; not all of them use every helper.
+#![allow(dead_code)]
+
+use cliper_lib::crypto::{KeyManager, KeyMode};
+use cliper_lib::db::{Database, NewItem};
+
+/// Unlocked key manager that never touches the Keychain.
+pub fn key_manager() -> KeyManager {
+    let km = KeyManager::new("bench.bundle".into()).with_key_mode(KeyMode::Ephemeral);
+    km.unlock().unwrap();
+    km
+}
+
+/// In-memory database holding `n` encrypted text items; every tenth mentions
+/// "needle" so searches have something to find.
+pub fn populated_db(km: &KeyManager, n: usize) -> Database {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    for i in 0..n {
+        let text = if i % 10 == 0 {
+            format!("item {i}: the needle is somewhere in this line of copied text")
+        } else {
+            format!("item {i}: an ordinary line of copied text, nothing to see here")
+        };
+        db.insert_item(NewItem {
+            kind: "text".into(),
+            size: text.len() as i64,
+            sha256: Database::compute_sha256(text.as_bytes()),
+            file_path: None,
+            file_path_blob: None,
+            content_blob: Some(km.encrypt(text.as_bytes()).unwrap()),
+            preview_blob: None,
+            rtf_blob: None,
+            html_blob: None,
+            source_app: None,
+            uti: None,
+        })
+        .unwrap();
+    }
+    db
+}
@@ -0,0 +1,34 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+mod common;
+
+const SIZES: &[usize] = &[64, 4 * 1024, 256 * 1024, 4 * 1024 * 1024];
+
+fn encrypt(c: &mut Criterion) {
+    let km = common::key_manager();
+    let mut group = c.benchmark_group("encrypt");
+    for &size in SIZES {
+        let data = vec![0x5au8; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, data| {
+            b.iter(|| km.encrypt(data).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn decrypt(c: &mut Criterion) {
+    let km = common::key_manager();
+    let mut group = c.benchmark_group("decrypt");
+    for &size in SIZES {
+        let blob = km.encrypt(&vec![0x5au8; size]).unwrap();
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &blob, |b, blob| {
+            b.iter(|| km.decrypt(blob).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, encrypt, decrypt);
+criterion_main!(benches);
@@ -0,0 +1,25 @@
+use cliper_lib::api::{search_items, SEARCH_SCAN_LIMIT};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+mod common;
+
+fn search(c: &mut Criterion) {
+    let km = common::key_manager();
+    let mut group = c.benchmark_group("search");
+    for n in [100, 1_000, 10_000] {
+        let db = common::populated_db(&km, n);
+        group.bench_with_input(BenchmarkId::new("match", n), &db, |b, db| {
+            b.iter(|| search_items(db, &km, "needle", None, None, 100, SEARCH_SCAN_LIMIT).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("no_match", n), &db, |b, db| {
+            b.iter(|| search_items(db, &km, "haystack", None, None, 100, SEARCH_SCAN_LIMIT).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("empty_query", n), &db, |b, db| {
+            b.iter(|| search_items(db, &km, "", None, None, 100, SEARCH_SCAN_LIMIT).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, search);
+criterion_main!(benches);
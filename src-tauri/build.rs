@@ -24,7 +24,11 @@ fn main() {
     // Tray icon small
     let _ = convert_webp_to_png(&src, &tray_path, 22);
   } else {
-    if let Err(e) = generate_rgba_icon(&icon_path) { eprintln!("warn: generate icon: {e}"); }
+    if let Err(e) = generate_rgba_icon(&icon_path) {
+      eprintln!("warn: generate icon: {e}");
+      // last resort: the icon the binary embeds as its tray fallback
+      let _ = fs::copy(icons_dir.join("tray_fallback.png"), &icon_path);
+    }
     let _ = fs::copy(&icon_path, &tray_path);
   }
   tauri_build::build()
@@ -1,6 +1,9 @@
 use crate::clipboard;
+use crate::crypto::{CryptoError, KeyManager};
+use crate::db::{is_text_kind, Database, ItemDto};
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use tauri::{Manager, State, GlobalShortcutManager};
-use image::GenericImageView;
 use base64::Engine;
 use std::path::Path;
 
@@ -13,202 +16,1865 @@ pub struct UiItemDto {
   pub sha256_hex: String,
   pub file_path: Option<String>,
   pub is_pinned: bool,
+  pub plaintext_len: i64,
+  pub stored_len: i64,
+  pub is_sensitive: bool,
+  pub uti: Option<String>,
+  pub thumbnail_only: bool,
   pub preview: Option<String>,
+  /// User note, decrypted; `None` without a note or while locked.
+  pub note: Option<String>,
+  /// Image whose stored bytes failed to decode; `preview` says so.
+  pub image_corrupt: bool,
+  /// Bundle id of the app copied from, e.g. `com.apple.Safari`.
+  pub source_app: Option<String>,
+  /// The `sticky_item_id` item, listed first by `list_recent`.
+  pub is_sticky: bool,
+  /// User labels, sorted; see `tag_item`.
+  pub tags: Vec<String>,
+  /// Whether `file_path` is still on disk; only set by `list_files`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file_exists: Option<bool>,
 }
 
-use crate::state::AppState;
+impl UiItemDto {
+  fn from_item(crypto: &KeyManager, it: ItemDto, preview: Option<String>) -> Self {
+    // sensitive content is never sent in lists, only via `reveal_item`
+    let preview = if it.is_sensitive && is_text_kind(&it.kind) { None } else { preview };
+    let preview = if it.image_corrupt { Some("corrupt image".to_string()) } else { preview };
+    let note = open_note(crypto, &it);
+    UiItemDto {
+      id: it.id,
+      created_at: it.created_at,
+      kind: it.kind,
+      size: it.size,
+      sha256_hex: it.sha256_hex,
+      file_path: it.file_path,
+      is_pinned: it.is_pinned,
+      plaintext_len: it.plaintext_len,
+      stored_len: it.stored_len,
+      is_sensitive: it.is_sensitive,
+      uti: it.uti,
+      thumbnail_only: it.thumbnail_only,
+      image_corrupt: it.image_corrupt,
+      source_app: it.source_app,
+      tags: it.tags,
+      preview,
+      note,
+      is_sticky: false,
+      file_exists: None,
+    }
+  }
+}
+
+use crate::state::{AppState, Settings};
+
+/// Runs on Tauri's worker pool rather than the main thread: with
+/// `require_biometrics` it waits on the Touch ID prompt, and blocking the
+/// main thread there would freeze every window until it is answered.
+#[tauri::command(async)]
+pub fn unlock(app: tauri::AppHandle, state: State<AppState>) -> Result<(), String> {
+    let require_biometrics = state.settings.lock().require_biometrics;
+    let unlocked = if require_biometrics { state.crypto.unlock_with_biometrics() } else { state.crypto.unlock() };
+    unlocked.map_err(|e| e.to_string())?;
+    after_unlock(&app, &state);
+    Ok(())
+}
+
+/// Turns the Touch ID gate on `unlock` on or off. Refuses to turn it on
+/// when Touch ID cannot be used, which would leave no way to unlock.
+#[tauri::command]
+pub fn set_require_biometrics(window: tauri::Window, state: State<AppState>, enabled: bool) -> Result<(), String> {
+    if enabled && !crate::crypto::biometrics_available() {
+        return Err(CryptoError::Biometrics { kind: crate::crypto::BiometricErrorKind::Unavailable }.to_string());
+    }
+    let mut s = state.settings.lock();
+    s.require_biometrics = enabled;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// `unlock` for passphrase mode (`use_passphrase`). The first call sets the
+/// passphrase; after that a mismatch fails with `wrong passphrase`.
+#[tauri::command]
+pub fn unlock_with_passphrase(app: tauri::AppHandle, state: State<AppState>, passphrase: String) -> Result<(), String> {
+    let passphrase = zeroize::Zeroizing::new(passphrase);
+    if passphrase.is_empty() {
+        return Err("the passphrase cannot be empty".into());
+    }
+    state.crypto.unlock_with_passphrase(&passphrase).map_err(|e| e.to_string())?;
+    after_unlock(&app, &state);
+    Ok(())
+}
+
+/// Whether passphrase mode is on and a passphrase has been set, so the UI
+/// can ask to choose one instead of entering it.
+#[tauri::command]
+pub fn has_passphrase(state: State<AppState>) -> Result<bool, String> {
+    if !state.crypto.uses_passphrase() {
+        return Ok(false);
+    }
+    state.crypto.has_passphrase().map_err(|e| e.to_string())
+}
+
+// Work deferred while locked, run by both unlock paths.
+fn after_unlock(app: &tauri::AppHandle, state: &AppState) {
+    state.activity.touch();
+    // store whatever was buffered while locked
+    let settings = state.settings.lock().clone();
+    // failures are logged by flush; the key is unlocked either way
+    let flushed = state.locked_captures.flush(&state.db, &state.crypto, &settings);
+    if !flushed.ids.is_empty() {
+        let _ = app.emit_all("items_updated", ());
+    }
+    run_health_check(app, state);
+    // thumbnails missing while locked can be made now
+    run_image_downgrade(app, state);
+    // as can sealing paths of files copied while locked
+    if settings.encrypt_file_paths {
+        if let Err(e) = clipboard::seal_file_paths(&state.db, &state.crypto) {
+            eprintln!("sealing file paths failed: {e}");
+        }
+    }
+}
+
+/// Kind of the Keychain failure behind the last failed `unlock` (e.g.
+/// `"denied"`, so the UI can ask the user to allow access), or `None`.
+#[tauri::command]
+pub fn get_keychain_error(state: State<AppState>) -> Result<Option<crate::crypto::KeychainErrorKind>, String> {
+    Ok(state.crypto.last_keychain_error())
+}
+
+#[tauri::command]
+pub fn lock(state: State<AppState>) -> Result<(), String> {
+    state.crypto.lock();
+    clipboard::clear_restored_files();
+    Ok(())
+}
+
+/// Seconds until the app locks itself for inactivity, for a countdown in
+/// the UI. `None` while locked or when `auto_lock_minutes` is 0.
+#[tauri::command]
+pub fn auto_lock_remaining(state: State<AppState>) -> Result<Option<u64>, String> {
+    if !state.crypto.is_unlocked() {
+        return Ok(None);
+    }
+    let minutes = state.settings.lock().auto_lock_minutes;
+    Ok(state.activity.remaining_at(crate::db::now_millis(), minutes))
+}
+
+/// Sets how many idle minutes pass before the app locks itself (0 = never)
+/// and restarts the countdown.
+#[tauri::command]
+pub fn set_auto_lock(window: tauri::Window, state: State<AppState>, minutes: u64) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.auto_lock_minutes = minutes;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    state.activity.touch();
+    Ok(())
+}
+
+/// Locks the key store once `auto_lock_minutes` pass without activity and
+/// emits `auto_locked`.
+pub fn run_auto_lock(app: &tauri::AppHandle, state: &AppState) {
+    if !state.crypto.is_unlocked() {
+        return;
+    }
+    let minutes = state.settings.lock().auto_lock_minutes;
+    if state.activity.remaining_at(crate::db::now_millis(), minutes) == Some(0) {
+        state.crypto.lock();
+        clipboard::clear_restored_files();
+        let _ = app.emit_all("auto_locked", ());
+    }
+}
+
+/// Content commands call this first so a locked key store surfaces as one
+/// consistent `locked` error instead of silently empty results.
+fn ensure_unlocked(state: &AppState) -> Result<(), String> {
+    if !state.crypto.is_unlocked() {
+        return Err(CryptoError::Locked.to_string());
+    }
+    Ok(())
+}
+
+/// Fills in `file_path` from the sealed path when there is one and the key
+/// is available; while locked such items show without a path.
+fn open_path(crypto: &KeyManager, mut it: ItemDto) -> ItemDto {
+    if it.file_path.is_none() {
+        it.file_path = it.file_path_blob.as_deref().and_then(|b| clipboard::open_file_path(crypto, b).ok());
+    }
+    it
+}
+
+fn open_note(crypto: &KeyManager, it: &ItemDto) -> Option<String> {
+    let pt = crypto.decrypt(it.note_blob.as_deref()?).ok()?;
+    Some(String::from_utf8_lossy(&pt).into_owned())
+}
+
+/// Longest note `set_item_note` accepts, in characters.
+pub const NOTE_MAX_CHARS: usize = 500;
+
+/// Encrypts and stores `note` on item `id`; a blank note removes it.
+pub fn set_note(db: &Database, crypto: &KeyManager, id: i64, note: Option<&str>) -> anyhow::Result<()> {
+    let note = note.map(str::trim).filter(|n| !n.is_empty());
+    let _sealing = crypto.sealing();
+    let blob = match note {
+        Some(n) if n.chars().count() > NOTE_MAX_CHARS => {
+            return Err(anyhow::anyhow!("notes are limited to {NOTE_MAX_CHARS} characters"));
+        }
+        Some(n) => Some(crypto.encrypt(n.as_bytes())?),
+        None => None,
+    };
+    db.set_note(id, blob.as_deref())
+}
+
+/// Builds the UI row for `it`, decrypting a short preview for text items
+/// (when unlocked) and using the basename for files.
+fn hydrate(db: &Database, crypto: &KeyManager, it: ItemDto) -> UiItemDto {
+    let it = open_path(crypto, it);
+    let mut preview = None;
+    let mut size = it.size;
+    if is_text_kind(&it.kind) {
+        if let Ok((_, Some(ct), _, _, _)) = db.get_item_raw(it.id) {
+            if let Ok(pt) = crypto.decrypt(&ct) {
+                let s = String::from_utf8_lossy(&pt);
+                let p: String = s.chars().take(100).collect();
+                preview = Some(p);
+            }
+        }
+    } else if it.kind == "file" {
+        if let Some(ref fp) = it.file_path {
+            if let Some(name) = Path::new(fp).file_name().and_then(|n| n.to_str()) {
+                preview = Some(name.to_string());
+            }
+            if size <= 0 {
+                if let Ok(meta) = std::fs::metadata(fp) { size = meta.len() as i64; }
+            }
+        }
+    }
+    UiItemDto { size, ..UiItemDto::from_item(crypto, it, preview) }
+}
+
+/// Previews for a batch of rows with one DB round-trip: text content
+/// (skipped while locked or for sensitive items) or a file's basename.
+/// Unknown ids and items without a preview are left out of the map.
+pub fn build_previews(db: &Database, crypto: &KeyManager, ids: &[i64]) -> anyhow::Result<HashMap<i64, String>> {
+    let mut out = HashMap::new();
+    for src in db.get_preview_sources(ids)? {
+        let preview = if is_text_kind(&src.kind) && !src.is_sensitive {
+            src.content_blob
+                .and_then(|ct| crypto.decrypt(&ct).ok())
+                .map(|pt| String::from_utf8_lossy(&pt).chars().take(100).collect())
+        } else if src.kind == "file" {
+            src.file_path
+                .or_else(|| src.file_path_blob.and_then(|b| clipboard::open_file_path(crypto, &b).ok()))
+                .as_deref()
+                .and_then(|fp| Path::new(fp).file_name())
+                .map(|n| n.to_string_lossy().into_owned())
+        } else {
+            None
+        };
+        if let Some(p) = preview {
+            out.insert(src.id, p);
+        }
+    }
+    Ok(out)
+}
+
+/// Encryption self-test plus a decrypt of the newest stored item, so a key
+/// that no longer matches the history (e.g. replaced in Keychain) shows up
+/// as a warning rather than as blank previews. Skipped while locked.
+pub fn health_check(db: &Database, crypto: &KeyManager) -> anyhow::Result<()> {
+    if !crypto.is_unlocked() {
+        return Ok(());
+    }
+    crypto.self_test()?;
+    if let Some(blob) = db.latest_content_blob()? {
+        crypto
+            .decrypt(&blob)
+            .map_err(|_| anyhow::anyhow!("the newest item cannot be decrypted with the current key"))?;
+    }
+    Ok(())
+}
+
+/// Runs `health_check`, emitting `health_warning` with the reason on failure.
+pub fn run_health_check(app: &tauri::AppHandle, state: &AppState) {
+    if let Err(e) = health_check(&state.db, &state.crypto) {
+        eprintln!("health check failed: {e}");
+        let _ = app.emit_all("health_warning", e.to_string());
+    }
+}
+
+/// Reduces images older than `image_full_days` to thumbnails; no-op when
+/// the setting is 0.
+pub fn run_image_downgrade(app: &tauri::AppHandle, state: &AppState) {
+    let settings = state.settings.lock().clone();
+    if settings.image_full_days == 0 {
+        return;
+    }
+    let cutoff = days_before(crate::db::now_millis(), settings.image_full_days);
+    match clipboard::downgrade_old_images(&state.db, &state.crypto, &settings, cutoff) {
+        Ok(0) => {}
+        Ok(_) => {
+            let _ = app.emit_all("items_updated", ());
+        }
+        Err(e) => eprintln!("image downgrade failed: {e}"),
+    }
+}
+
+/// Deletes unpinned items older than `retention_days` and returns how many
+/// were removed; no-op when history is kept forever.
+pub fn purge_expired(state: &AppState) -> anyhow::Result<usize> {
+    let days = match state.settings.lock().retention_days {
+        Some(days) if days > 0 => days,
+        _ => return Ok(0),
+    };
+    state.db.purge_older_than(days_before(crate::db::now_millis(), days))
+}
+
+/// Unix millis `days` before `now_ms`. Saturates rather than overflowing,
+/// so a huge setting means "long ago", never a cutoff in the future.
+pub fn days_before(now_ms: i64, days: u64) -> i64 {
+    now_ms.saturating_sub(days.saturating_mul(86_400_000).min(i64::MAX as u64) as i64)
+}
+
+/// `purge_expired` for the startup and daily timer runs.
+pub fn run_retention_purge(app: &tauri::AppHandle, state: &AppState) {
+    match purge_expired(state) {
+        Ok(0) => {}
+        Ok(_) => {
+            let _ = app.emit_all("items_updated", ());
+        }
+        Err(e) => eprintln!("retention purge failed: {e}"),
+    }
+}
+
+/// Free space on the database volume against `low_disk_bytes`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DiskSpaceInfo {
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+    pub low: bool,
+    /// Whether capture is paused until space is freed.
+    pub capture_paused: bool,
+}
+
+fn disk_space_info_of(state: &AppState) -> anyhow::Result<Option<DiskSpaceInfo>> {
+    let Some(space) = state.db.disk_space()? else { return Ok(None) };
+    let (threshold, pause) = {
+        let s = state.settings.lock();
+        (s.low_disk_bytes, s.pause_capture_on_low_disk)
+    };
+    let low = threshold > 0 && space.free_bytes < threshold;
+    Ok(Some(DiskSpaceInfo { free_bytes: space.free_bytes, total_bytes: space.total_bytes, low, capture_paused: low && pause }))
+}
+
+/// Updates `state.low_disk`, emitting `low_disk` with the numbers when free
+/// space drops under `low_disk_bytes` and `disk_space_recovered` once it is
+/// back above.
+pub fn run_disk_check(app: &tauri::AppHandle, state: &AppState) {
+    let info = match disk_space_info_of(state) {
+        Ok(Some(info)) => info,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("disk space check failed: {e}");
+            return;
+        }
+    };
+    let was_low = state.low_disk.swap(info.low, Ordering::Relaxed);
+    if info.low && !was_low {
+        eprintln!("low disk space: {} bytes free", info.free_bytes);
+        let _ = app.emit_all("low_disk", info);
+    } else if !info.low && was_low {
+        let _ = app.emit_all("disk_space_recovered", info);
+    }
+}
+
+/// Free and total bytes of the database volume, and whether capture is
+/// paused for lack of space. `None` without on-disk storage.
+#[tauri::command]
+pub fn disk_space_info(state: State<AppState>) -> Result<Option<DiskSpaceInfo>, String> {
+    disk_space_info_of(&state).map_err(|e| e.to_string())
+}
+
+/// An item's stored ciphertext, never plaintext.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ItemBlob {
+    pub id: i64,
+    pub kind: String,
+    /// Base64 of `content_blob`; `None` for items without content (file
+    /// paths that were never materialized).
+    pub content_blob: Option<String>,
+    pub format: crate::crypto::BlobLayout,
+}
+
+/// Raw encrypted content of `id` for backup verification. Reads only the
+/// ciphertext, so it works while locked.
+pub fn item_blob(db: &Database, id: i64) -> anyhow::Result<ItemBlob> {
+    let (kind, content_blob, ..) = db.get_item_raw(id)?;
+    Ok(ItemBlob {
+        id,
+        kind,
+        content_blob: content_blob.map(|b| base64::engine::general_purpose::STANDARD.encode(b)),
+        format: crate::crypto::BLOB_LAYOUT,
+    })
+}
+
+/// File items with their basename as preview and whether the path still
+/// exists, pinned first then newest first. With `only_existing`, missing
+/// files are skipped before `limit` is applied.
+pub fn file_items(db: &Database, crypto: &KeyManager, limit: u32, only_existing: bool) -> anyhow::Result<Vec<UiItemDto>> {
+    let mut out = Vec::new();
+    for it in db.list_files()? {
+        let it = open_path(crypto, it);
+        if out.len() >= limit as usize {
+            break;
+        }
+        let meta = it.file_path.as_deref().and_then(|fp| std::fs::metadata(fp).ok());
+        if only_existing && meta.is_none() {
+            continue;
+        }
+        let name = it
+            .file_path
+            .as_deref()
+            .and_then(|fp| Path::new(fp).file_name())
+            .map(|n| n.to_string_lossy().into_owned());
+        let size = match &meta {
+            Some(m) if it.size <= 0 => m.len() as i64,
+            _ => it.size,
+        };
+        out.push(UiItemDto { size, file_exists: Some(meta.is_some()), ..UiItemDto::from_item(crypto, it, name) });
+    }
+    Ok(out)
+}
+
+/// One line of `write_items_ndjson`: the list row plus the full text.
+#[derive(serde::Serialize)]
+struct ItemLine {
+    #[serde(flatten)]
+    item: UiItemDto,
+    /// Full content of text items; `None` while locked, for sensitive items
+    /// and for other kinds.
+    text: Option<String>,
+}
+
+/// Writes every item, oldest first, as newline-delimited JSON
+/// (`application/x-ndjson`). Rows are read in batches through
+/// `for_each_item` and written as they come, so memory stays flat however
+/// long the history is. Content fields are only filled in while unlocked.
+/// Returns the number of lines written.
+pub fn write_items_ndjson<W: std::io::Write>(db: &Database, crypto: &KeyManager, out: W) -> anyhow::Result<usize> {
+    use std::io::Write;
+    let mut out = std::io::BufWriter::new(out);
+    let unlocked = crypto.is_unlocked();
+    let count = db.for_each_item(500, |it| {
+        let it = open_path(crypto, it);
+        let text = if unlocked && is_text_kind(&it.kind) && !it.is_sensitive {
+            let (_, content_blob, ..) = db.get_item_raw(it.id)?;
+            content_blob
+                .and_then(|ct| crypto.decrypt(&ct).ok())
+                .map(|pt| String::from_utf8_lossy(&pt).into_owned())
+        } else {
+            None
+        };
+        let preview = match (&text, &it.file_path) {
+            (Some(t), _) => Some(t.chars().take(100).collect()),
+            (None, Some(fp)) if it.kind == "file" => Path::new(fp).file_name().map(|n| n.to_string_lossy().into_owned()),
+            _ => None,
+        };
+        serde_json::to_writer(&mut out, &ItemLine { item: UiItemDto::from_item(crypto, it, preview), text })?;
+        out.write_all(b"\n")?;
+        Ok(true)
+    })?;
+    out.flush()?;
+    Ok(count)
+}
+
+/// Streams the whole history to `dest_path` as NDJSON, one item per line,
+/// for scripting with tools like `jq`. Text content is only included
+/// while unlocked.
+#[tauri::command]
+pub fn export_items_ndjson(state: State<AppState>, dest_path: String) -> Result<usize, String> {
+    let file = std::fs::File::create(&dest_path).map_err(|e| e.to_string())?;
+    write_items_ndjson(&state.db, &state.crypto, file).map_err(|e| e.to_string())
+}
+
+/// Batched preview hydration for the visible rows, keyed by item id.
+#[tauri::command]
+pub fn get_previews(state: State<AppState>, ids: Vec<i64>) -> Result<HashMap<i64, String>, String> {
+    ensure_unlocked(&state)?;
+    build_previews(&state.db, &state.crypto, &ids).map_err(|e| e.to_string())
+}
+
+/// Newest items, pinned first, with the sticky item (if any) moved or
+/// fetched to the very top. The flag is false when `sticky` names an item
+/// that no longer exists.
+pub fn recent_items(db: &Database, sticky: Option<i64>, limit: u32) -> anyhow::Result<(Vec<ItemDto>, bool)> {
+    let mut items = db.list_recent(limit)?;
+    let Some(sticky) = sticky else { return Ok((items, true)) };
+    let top = match items.iter().position(|it| it.id == sticky) {
+        Some(i) => items.remove(i),
+        None => match db.get_item(sticky) {
+            Ok(it) => it,
+            Err(e) if matches!(e.downcast_ref(), Some(rusqlite::Error::QueryReturnedNoRows)) => return Ok((items, false)),
+            Err(e) => return Err(e),
+        },
+    };
+    items.insert(0, top);
+    items.truncate(limit as usize);
+    Ok((items, true))
+}
+
+#[tauri::command]
+pub fn list_recent(window: tauri::Window, state: State<AppState>, limit: u32) -> Result<Vec<UiItemDto>, String> {
+    let sticky = state.settings.lock().sticky_item_id;
+    let (items, sticky_found) = recent_items(&state.db, sticky, limit).map_err(|e| e.to_string())?;
+    if !sticky_found {
+        // removed some other way, e.g. pruned by a per-kind limit
+        save_sticky(&window, &state, None);
+    }
+    Ok(items
+        .into_iter()
+        .map(|it| {
+            let is_sticky = Some(it.id) == sticky;
+            UiItemDto { is_sticky, ..hydrate(&state.db, &state.crypto, it) }
+        })
+        .collect())
+}
+
+/// One page of history from `list_page`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HistoryPage {
+    pub items: Vec<UiItemDto>,
+    /// Pass back to `list_page` for the next, older page; `None` at the end.
+    pub next_cursor: Option<String>,
+}
+
+/// Items for one `list_page` call and the cursor after them. Without a
+/// cursor this is the first page: every pinned item, then the newest
+/// `limit` unpinned ones. Later pages hold only unpinned items, so none
+/// repeat. Pages follow `seq` like `list_recent`, not `created_at`, so a
+/// clock change cannot reorder them; the cursor is the last item's `seq`.
+pub fn history_page(db: &Database, cursor: Option<&str>, limit: u32) -> anyhow::Result<(Vec<ItemDto>, Option<String>)> {
+    let limit = limit.max(1);
+    let (mut items, seq) = match cursor {
+        None => (db.list_pinned()?, i64::MAX),
+        Some(c) => (Vec::new(), c.parse().map_err(|_| anyhow::anyhow!("invalid cursor {c:?}"))?),
+    };
+    // one extra row says whether another page follows
+    let mut older = db.list_before(seq, limit + 1)?;
+    let more = older.len() > limit as usize;
+    older.truncate(limit as usize);
+    let next = older.last().filter(|_| more).map(|(seq, _)| seq.to_string());
+    items.extend(older.into_iter().map(|(_, it)| it));
+    Ok((items, next))
+}
+
+/// History a page at a time for lazy loading; see `history_page`.
+#[tauri::command]
+pub fn list_page(state: State<AppState>, cursor: Option<String>, limit: u32) -> Result<HistoryPage, String> {
+    let (items, next_cursor) = history_page(&state.db, cursor.as_deref(), limit).map_err(|e| e.to_string())?;
+    let items = items.into_iter().map(|it| hydrate(&state.db, &state.crypto, it)).collect();
+    Ok(HistoryPage { items, next_cursor })
+}
+
+fn save_sticky(window: &tauri::Window, state: &AppState, id: Option<i64>) {
+    let mut s = state.settings.lock();
+    s.sticky_item_id = id;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+}
+
+/// Formats an item's `created_at` in the system timezone, so list rows,
+/// grouping and detail views all agree. Future times from clock skew read
+/// as "just now" or the plain date, never "in 5 minutes".
+#[tauri::command]
+pub fn format_timestamp(created_at: i64, style: crate::timefmt::TimestampStyle) -> Result<String, String> {
+    crate::timefmt::format_timestamp(created_at, style).map_err(|e| e.to_string())
+}
+
+/// Number of items captured since `mark_all_seen` last ran.
+#[tauri::command]
+pub fn unread_count(state: State<AppState>) -> Result<i64, String> {
+    let last_seen = state.settings.lock().last_seen_id;
+    state.db.count_after(last_seen).map_err(|e| e.to_string())
+}
+
+/// Marks every current item as seen, clearing the unread count.
+#[tauri::command]
+pub fn mark_all_seen(window: tauri::Window, state: State<AppState>) -> Result<(), String> {
+    let max_id = state.db.max_id().map_err(|e| e.to_string())?;
+    {
+        let mut s = state.settings.lock();
+        if s.last_seen_id == max_id { return Ok(()); }
+        s.last_seen_id = max_id;
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    }
+    let _ = window.app_handle().emit_all("unread_changed", 0i64);
+    Ok(())
+}
+
+/// Sends `unread_changed` with the current unread count; called after
+/// each stored capture.
+pub fn emit_unread(app: &tauri::AppHandle, state: &AppState) {
+    let last_seen = state.settings.lock().last_seen_id;
+    if let Ok(count) = state.db.count_after(last_seen) {
+        let _ = app.emit_all("unread_changed", count);
+    }
+}
+
+/// Makes `id` the one item always listed first by `list_recent`,
+/// replacing any previous one; `None` clears it.
+#[tauri::command]
+pub fn set_sticky_item(window: tauri::Window, state: State<AppState>, id: Option<i64>) -> Result<(), String> {
+    if let Some(id) = id {
+        state.db.get_item(id).map_err(|e| e.to_string())?;
+    }
+    save_sticky(&window, &state, id);
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+/// Oldest items first, for pruning the bottom of history by hand; pinned
+/// items are skipped unless `include_pinned`, then they come last.
+#[tauri::command]
+pub fn list_oldest(state: State<AppState>, limit: u32, include_pinned: bool) -> Result<Vec<UiItemDto>, String> {
+    let items = state.db.list_oldest(limit, include_pinned).map_err(|e| e.to_string())?;
+    Ok(items.into_iter().map(|it| hydrate(&state.db, &state.crypto, it)).collect())
+}
+
+/// Exactly the items `ids`, in that order, for refreshing rows after a
+/// batch change; deleted ids are left out.
+#[tauri::command]
+pub fn get_items(state: State<AppState>, ids: Vec<i64>) -> Result<Vec<UiItemDto>, String> {
+    let items = state.db.get_items(&ids).map_err(|e| e.to_string())?;
+    Ok(items.into_iter().map(|it| hydrate(&state.db, &state.crypto, it)).collect())
+}
+
+/// Items most recently copied back out of Cliper, newest first.
+#[tauri::command]
+pub fn list_recently_pasted(state: State<AppState>, limit: u32) -> Result<Vec<UiItemDto>, String> {
+    let items = state.db.list_recently_pasted(limit).map_err(|e| e.to_string())?;
+    Ok(items.into_iter().map(|it| hydrate(&state.db, &state.crypto, it)).collect())
+}
+
+/// Items newer than `after_id`, oldest first, for incremental refresh.
+#[tauri::command]
+pub fn list_since(state: State<AppState>, after_id: i64, limit: u32) -> Result<Vec<UiItemDto>, String> {
+    let items = state.db.list_since(after_id, limit).map_err(|e| e.to_string())?;
+    Ok(items.into_iter().map(|it| hydrate(&state.db, &state.crypto, it)).collect())
+}
+
+/// Whether any item is newer than `after_id`, e.g. written by another
+/// process while the window was hidden. Cheap enough to call on every focus.
+#[tauri::command]
+pub fn has_new_since(state: State<AppState>, after_id: i64) -> Result<bool, String> {
+    Ok(state.db.max_id().map_err(|e| e.to_string())? > after_id)
+}
+
+/// Items of at least `min_bytes`, largest first, for cleaning up storage.
+/// `kind` narrows the list the way the search filter does.
+#[tauri::command]
+pub fn list_large_items(state: State<AppState>, min_bytes: i64, kind: Option<String>, limit: u32) -> Result<Vec<UiItemDto>, String> {
+    let items = state.db.list_large(min_bytes, kind.as_deref(), limit).map_err(|e| e.to_string())?;
+    Ok(items.into_iter().map(|it| hydrate(&state.db, &state.crypto, it)).collect())
+}
+
+/// Just the file items, optionally only those still on disk.
+#[tauri::command]
+pub fn list_files(state: State<AppState>, limit: u32, only_existing: bool) -> Result<Vec<UiItemDto>, String> {
+    file_items(&state.db, &state.crypto, limit, only_existing).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn export_item_blob(state: State<AppState>, id: i64) -> Result<ItemBlob, String> {
+    item_blob(&state.db, id).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn size_histogram(state: State<AppState>) -> Result<Vec<crate::db::SizeHistogram>, String> {
+    state.db.size_histogram().map_err(|e| e.to_string())
+}
+
+/// How much larger stored content is than the plaintext, per kind and in
+/// total. Metadata only, so it works while locked.
+#[tauri::command]
+pub fn encryption_overhead(state: State<AppState>) -> Result<crate::db::EncryptionOverheadReport, String> {
+    state.db.encryption_overhead().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn search(
+    state: State<AppState>,
+    query: String,
+    kind: Option<String>,
+    filter_by_tag: Option<String>,
+    limit: u32,
+    scan_limit: Option<u32>,
+) -> Result<Vec<UiItemDto>, String> {
+    let (kind, tag) = (kind.as_deref(), filter_by_tag.as_deref());
+    // plaintext file paths can be matched without the key; sealed paths
+    // and notes are skipped until unlock
+    if kind != Some("file") {
+        ensure_unlocked(&state)?;
+    }
+    // searching and copying count as activity; background refreshes do not
+    state.activity.touch();
+    let scan_limit = scan_limit.unwrap_or(SEARCH_SCAN_LIMIT);
+    if !state.crypto.is_unlocked() {
+        return search_items(&state.db, &state.crypto, &query, kind, tag, limit, scan_limit).map_err(|e| e.to_string());
+    }
+    if state.settings.lock().enable_search_index {
+        return search_items_indexed(&state.db, &state.crypto, &query, kind, tag, limit, scan_limit).map_err(|e| e.to_string());
+    }
+    search_items(&state.db, &state.crypto, &query, kind, tag, limit, scan_limit).map_err(|e| e.to_string())
+}
+
+/// Labels item `id` with `tag`; returns the item's tags.
+#[tauri::command]
+pub fn tag_item(window: tauri::Window, state: State<AppState>, id: i64, tag: String) -> Result<Vec<String>, String> {
+    state.db.add_tag(id, &tag).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    state.db.list_tags(id).map_err(|e| e.to_string())
+}
+
+/// Removes `tag` from item `id`; returns the item's tags.
+#[tauri::command]
+pub fn untag_item(window: tauri::Window, state: State<AppState>, id: i64, tag: String) -> Result<Vec<String>, String> {
+    state.db.remove_tag(id, &tag).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    state.db.list_tags(id).map_err(|e| e.to_string())
+}
+
+/// Turns the full-text search index on or off. Turning it on indexes
+/// existing history in the background (emitting `search_index_built` with
+/// the count) when unlocked; turning it off deletes the index.
+#[tauri::command]
+pub fn set_enable_search_index(window: tauri::Window, state: State<AppState>, enabled: bool) -> Result<(), String> {
+    if !enabled {
+        state.db.clear_search_index().map_err(|e| e.to_string())?;
+    }
+    {
+        let mut s = state.settings.lock();
+        s.enable_search_index = enabled;
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    }
+    if enabled && state.crypto.is_unlocked() {
+        let (db, crypto, app) = (state.db.clone(), state.crypto.clone(), window.app_handle());
+        std::thread::spawn(move || match index_pending(&db, &crypto, u32::MAX) {
+            Ok(count) => {
+                let _ = app.emit_all("search_index_built", count);
+            }
+            Err(e) => eprintln!("search index build failed: {e}"),
+        });
+    }
+    Ok(())
+}
+
+/// Most unindexed items a search indexes before running; anything older
+/// is picked up by later searches or the build started when enabling.
+pub const SEARCH_INDEX_CATCH_UP: u32 = 500;
+
+/// Adds up to `max` text items missing from the search index, newest
+/// first; items that fail to decrypt are skipped. Returns how many were
+/// indexed.
+pub fn index_pending(db: &Database, crypto: &KeyManager, max: u32) -> anyhow::Result<usize> {
+    let mut indexed = 0;
+    for id in db.unindexed_text_ids(max)? {
+        let Ok((_, Some(ct), _, _, _)) = db.get_item_raw(id) else { continue };
+        let Ok(pt) = crypto.decrypt(&ct) else { continue };
+        db.index_text(id, &String::from_utf8_lossy(&pt))?;
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+/// Newest items `search` looks at by default; older ones are only searched
+/// when the caller asks for a deeper `scan_limit`.
+pub const SEARCH_SCAN_LIMIT: u32 = 200;
+/// Deepest `scan_limit` accepted; larger values are clamped.
+pub const SEARCH_SCAN_MAX: u32 = 20_000;
+/// A search stops decrypting once it has run this long and returns the hits
+/// found so far, so a deep scan over large items cannot hang the UI.
+pub const SEARCH_TIME_BUDGET: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// What `search` can do right now, so the UI can disable or qualify it.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SearchCapabilities {
+    /// Text content and notes can be matched (the key is unlocked).
+    pub content_search: bool,
+    /// Whether the full-text index backs search (`enable_search_index`), so
+    /// text content is matched across all history instead of decrypted.
+    pub fts_indexed: bool,
+    /// Items a search decrypts and scans by default, at most
+    /// `SEARCH_SCAN_LIMIT`; a deeper `scan_limit` can reach further. With
+    /// the index, only files and notes are limited to this window.
+    pub items_scanned: i64,
+    /// Items in history, including ones past the scan limit.
+    pub total_items: i64,
+}
+
+pub fn search_capabilities_of(db: &Database, crypto: &KeyManager, indexed: bool) -> anyhow::Result<SearchCapabilities> {
+    let total_items = db.count_items()?;
+    Ok(SearchCapabilities {
+        content_search: crypto.is_unlocked(),
+        fts_indexed: indexed,
+        items_scanned: total_items.min(SEARCH_SCAN_LIMIT as i64),
+        total_items,
+    })
+}
+
+/// Reports whether content search works (unlocked) and how much history it
+/// covers. Works while locked.
+#[tauri::command]
+pub fn search_capabilities(state: State<AppState>) -> Result<SearchCapabilities, String> {
+    let indexed = state.settings.lock().enable_search_index;
+    search_capabilities_of(&state.db, &state.crypto, indexed).map_err(|e| e.to_string())
+}
+
+/// Case-insensitive substring search over the newest `scan_limit` items
+/// (at most `SEARCH_SCAN_MAX`): decrypted text for text kinds, the path for
+/// files. Items past the window are never matched, so a query with no hits
+/// may still match older history at a deeper `scan_limit`. With `tag`, only
+/// items carrying it are searched, from all of history.
+pub fn search_items(db: &Database, crypto: &KeyManager, query: &str, kind: Option<&str>, tag: Option<&str>, limit: u32, scan_limit: u32) -> anyhow::Result<Vec<UiItemDto>> {
+    scan_items(db, crypto, query, kind, limit, candidates(db, tag, scan_limit)?, None)
+}
+
+// What a search looks through: the newest `scan_limit` items, or every
+// item carrying `tag`.
+fn candidates(db: &Database, tag: Option<&str>, scan_limit: u32) -> anyhow::Result<Vec<ItemDto>> {
+    match tag {
+        Some(tag) => db.list_tagged(tag, SEARCH_SCAN_MAX),
+        None => db.list_recent(scan_limit.clamp(1, SEARCH_SCAN_MAX)),
+    }
+}
+
+/// `search_items` with text content matched through the full-text index,
+/// across all history: items missing from the index are added first, text
+/// hits past the scan window follow the ones inside it. Files, notes and
+/// sensitive items are still scanned within the window.
+pub fn search_items_indexed(db: &Database, crypto: &KeyManager, query: &str, kind: Option<&str>, tag: Option<&str>, limit: u32, scan_limit: u32) -> anyhow::Result<Vec<UiItemDto>> {
+    if query.trim().is_empty() {
+        return search_items(db, crypto, query, kind, tag, limit, scan_limit);
+    }
+    index_pending(db, crypto, SEARCH_INDEX_CATCH_UP)?;
+    let items = candidates(db, tag, scan_limit)?;
+    let mut hits = db.search_fts(query, SEARCH_SCAN_MAX)?;
+    if tag.is_some() {
+        // every tagged item is scanned already; untagged hits are out
+        let tagged: std::collections::HashSet<i64> = items.iter().map(|i| i.id).collect();
+        hits.retain(|id| tagged.contains(id));
+    }
+    scan_items(db, crypto, query, kind, limit, items, Some(&hits))
+}
+
+// `indexed` holds the ids of text items whose content the index matched;
+// without it text content is decrypted and searched here.
+fn scan_items(db: &Database, crypto: &KeyManager, query: &str, kind: Option<&str>, limit: u32, mut items: Vec<ItemDto>, indexed: Option<&[i64]>) -> anyhow::Result<Vec<UiItemDto>> {
+    let of_kind = |k: &str| match kind {
+        Some(want) => k == want || (want == "text" && is_text_kind(k)),
+        None => true,
+    };
+    // Since payloads are encrypted, we retrieve recent items and filter after.
+    let scanned: std::collections::HashSet<i64> = items.iter().map(|i| i.id).collect();
+    // "text" covers the detected text kinds (url, color, code) too
+    items.retain(|i| of_kind(&i.kind));
+    if query.trim().is_empty() {
+        items.truncate(limit as usize);
+        let mapped = items.into_iter().map(|it| hydrate(db, crypto, it)).collect();
+        return Ok(mapped);
+    }
+    let q = query.to_lowercase();
+    let started = std::time::Instant::now();
+    let mut out = Vec::new();
+    for it in items {
+        if out.len() >= limit as usize { break; }
+        if started.elapsed() > SEARCH_TIME_BUDGET {
+            eprintln!("search stopped after {SEARCH_TIME_BUDGET:?} with {} hits", out.len());
+            break;
+        }
+        let it = open_path(crypto, it);
+        let note_hit = open_note(crypto, &it).is_some_and(|n| n.to_lowercase().contains(&q));
+        match it.kind.as_str() {
+            k if is_text_kind(k) => {
+                let index_hit = match indexed {
+                    Some(hits) if !it.is_sensitive => Some(hits.contains(&it.id)),
+                    _ => None,
+                };
+                if index_hit == Some(false) && !note_hit {
+                    continue;
+                }
+                if let Ok((_, Some(ct), _, _, _)) = db.get_item_raw(it.id) {
+                    if let Ok(pt) = crypto.decrypt(&ct) {
+                        let content_hit = index_hit.unwrap_or_else(|| String::from_utf8_lossy(&pt).to_lowercase().contains(&q));
+                        if note_hit || content_hit {
+                            let preview = Some(String::from_utf8_lossy(&pt).chars().take(100).collect());
+                            out.push(UiItemDto::from_item(crypto, it, preview));
+                        }
+                    }
+                }
+            }
+            "file" => {
+                if let Some(fp) = &it.file_path {
+                    if note_hit || fp.to_lowercase().contains(&q) {
+                        let name = Path::new(fp).file_name().and_then(|n| n.to_str()).map(|s| s.to_string());
+                        out.push(UiItemDto::from_item(crypto, it, name));
+                    }
+                }
+            }
+            _ => {
+                // images and blobs have no text of their own; only their note can match
+                if note_hit {
+                    out.push(UiItemDto::from_item(crypto, it, None));
+                }
+            }
+        }
+    }
+    if let Some(hits) = indexed {
+        // older text matches, past the window
+        let older: Vec<i64> = hits.iter().copied().filter(|id| !scanned.contains(id)).collect();
+        for it in db.get_items(&older)? {
+            if out.len() >= limit as usize { break; }
+            if of_kind(&it.kind) {
+                out.push(hydrate(db, crypto, it));
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Writes whatever is on the clipboard to `dest_path` without storing it,
+/// even while capture is paused or the key is locked. Returns the kind
+/// written.
+#[tauri::command]
+pub fn dump_clipboard_to(dest_path: String) -> Result<clipboard::DumpedClipboard, String> {
+    #[cfg(target_os = "macos")]
+    {
+        let snap = clipboard::current_snapshot();
+        return clipboard::dump_snapshot(&snap, Path::new(&dest_path)).map_err(|e| e.to_string());
+    }
+    #[allow(unreachable_code)]
+    {
+        let _ = dest_path;
+        Err("not supported".into())
+    }
+}
+
+/// Id of the history item matching what is on the clipboard now, if any,
+/// so the UI can say "already in history" and jump to it.
+#[tauri::command]
+pub fn find_matching_clipboard(state: State<AppState>) -> Result<Option<i64>, String> {
+    #[cfg(target_os = "macos")]
+    {
+        let snap = clipboard::current_snapshot();
+        let settings = state.settings.lock().clone();
+        return clipboard::find_matching(&state.db, &settings, &snap).map_err(|e| e.to_string());
+    }
+    #[allow(unreachable_code)]
+    {
+        let _ = state;
+        Err("not supported".into())
+    }
+}
+
+#[tauri::command]
+pub fn copy_item(state: State<AppState>, id: i64) -> Result<(), String> {
+    copy_and_record(&state, id)
+}
+
+fn copy_and_record(state: &AppState, id: i64) -> Result<(), String> {
+    if clipboard::copy_needs_key(&state.db, id).map_err(|e| e.to_string())? {
+        ensure_unlocked(state)?;
+    }
+    state.activity.touch();
+    state
+        .self_writes
+        .around(clipboard::change_count, || clipboard::copy_back(&state.db, &state.crypto, id))
+        .map_err(|e| e.to_string())?;
+    state.db.record_copy(id).map_err(|e| e.to_string())
+}
+
+/// Copies a text item as `prefix + text + suffix`; returns the copied length.
+#[tauri::command]
+pub fn copy_item_wrapped(state: State<AppState>, id: i64, prefix: String, suffix: String) -> Result<usize, String> {
+    ensure_unlocked(&state)?;
+    let len = state
+        .self_writes
+        .around(clipboard::change_count, || clipboard::copy_wrapped(&state.db, &state.crypto, id, &prefix, &suffix))
+        .map_err(|e| e.to_string())?;
+    state.db.record_copy(id).map_err(|e| e.to_string())?;
+    Ok(len)
+}
+
+/// Copies an item, hides the window, brings back the app that was in
+/// front when the window was shown and pastes into it with ⌘V. Pasting
+/// needs the Accessibility permission; without it the item is still
+/// copied and the error says what to allow.
+#[tauri::command]
+pub fn paste_and_restore_focus(window: tauri::Window, state: State<AppState>, id: i64) -> Result<(), String> {
+    copy_and_record(&state, id)?;
+    let pid = state.previous_app.lock().take().ok_or("no app to return to")?;
+    let _ = window.hide();
+    if !clipboard::paste::activate(pid) {
+        return Err("the previous app is no longer running".into());
+    }
+    // activation is asynchronous; give the app a moment to take key focus
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    clipboard::paste::post_paste().map_err(|e| e.to_string())
+}
+
+/// macOS permissions features depend on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct Permissions {
+    /// Needed by `paste_and_restore_focus` to send ⌘V.
+    pub accessibility: bool,
+}
+
+/// Updates `state.accessibility`, emitting `permissions_changed` when the
+/// permission is granted or revoked so the UI can drop its prompt without a
+/// relaunch. Paste checks the permission on every call, so nothing else
+/// needs restarting.
+pub fn run_permission_check(app: &tauri::AppHandle, state: &AppState) {
+    let trusted = clipboard::paste::accessibility_trusted();
+    if state.accessibility.swap(trusted, Ordering::Relaxed) != trusted {
+        let _ = app.emit_all("permissions_changed", Permissions { accessibility: trusted });
+    }
+}
+
+/// Current permission state, checked afresh.
+#[tauri::command]
+pub fn permissions(app: tauri::AppHandle, state: State<AppState>) -> Result<Permissions, String> {
+    run_permission_check(&app, &state);
+    Ok(Permissions { accessibility: state.accessibility.load(Ordering::Relaxed) })
+}
+
+/// Copies the text items among `ids`, in order, joined by `separator`,
+/// without creating an item; returns how many were joined and the length.
+#[tauri::command]
+pub fn copy_items_joined(state: State<AppState>, ids: Vec<i64>, separator: String) -> Result<clipboard::JoinedCopy, String> {
+    ensure_unlocked(&state)?;
+    state
+        .self_writes
+        .around(clipboard::change_count, || clipboard::copy_joined(&state.db, &state.crypto, &ids, &separator))
+        .map_err(|e| e.to_string())
+}
+
+/// Stores one allowlisted representation (e.g. `com.adobe.pdf`) of the
+/// current clipboard as a typed item.
+#[tauri::command]
+pub fn capture_type(window: tauri::Window, state: State<AppState>, uti: String) -> Result<i64, String> {
+    ensure_unlocked(&state)?;
+    #[cfg(target_os = "macos")]
+    {
+        let id = clipboard::typed::capture_type(&state.db, &state.crypto, &uti).map_err(|e| e.to_string())?;
+        let _ = window.app_handle().emit_all("items_updated", ());
+        return Ok(id);
+    }
+    #[allow(unreachable_code)]
+    {
+        let _ = (window, uti);
+        Err("not supported".into())
+    }
+}
+
+#[tauri::command]
+pub fn save_item_to(state: State<AppState>, id: i64, dest_path: String) -> Result<clipboard::SavedItem, String> {
+    ensure_unlocked(&state)?;
+    clipboard::save_item_to(&state.db, &state.crypto, id, Path::new(&dest_path)).map_err(|e| e.to_string())
+}
+
+/// Moves an item out of history into a file: export, verify, then delete.
+#[tauri::command]
+pub fn detach_item(window: tauri::Window, state: State<AppState>, id: i64, dest_path: String) -> Result<clipboard::SavedItem, String> {
+    ensure_unlocked(&state)?;
+    let saved = clipboard::detach_item(&state.db, &state.crypto, id, Path::new(&dest_path)).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(saved)
+}
+
+/// Merges file items that point at the same file through different paths;
+/// returns how many were removed.
+#[tauri::command]
+pub fn dedup_files_by_inode(window: tauri::Window, state: State<AppState>) -> Result<usize, String> {
+    let merged = clipboard::dedup_files_by_inode(&state.db, &state.crypto).map_err(|e| e.to_string())?;
+    if merged > 0 {
+        let _ = window.app_handle().emit_all("items_updated", ());
+    }
+    Ok(merged)
+}
+
+/// Stores a file item's bytes in the database so it survives the original
+/// file being deleted; needs the store unlocked.
+#[tauri::command]
+pub fn materialize_file(window: tauri::Window, state: State<AppState>, id: i64) -> Result<u64, String> {
+    ensure_unlocked(&state)?;
+    let bytes = clipboard::materialize_file(&state.db, &state.crypto, id).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(bytes)
+}
+
+/// Seeds the history from UTF-8 text files; needs the store unlocked.
+#[tauri::command]
+pub fn import_text_files(window: tauri::Window, state: State<AppState>, paths: Vec<String>, pin: bool) -> Result<clipboard::import::ImportReport, String> {
+    ensure_unlocked(&state)?;
+    let settings = state.settings.lock().clone();
+    let report = clipboard::import::import_text_files(&state.db, &state.crypto, &settings, &paths, pin).map_err(|e| e.to_string())?;
+    if report.imported > 0 {
+        let _ = window.app_handle().emit_all("items_updated", ());
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn pin_item(window: tauri::Window, state: State<AppState>, id: i64, pin: bool) -> Result<(), String> {
+    state.db.pin_item(id, pin).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+/// Overrides the detected kind of a text item, e.g. to mark it a snippet.
+#[tauri::command]
+pub fn set_item_kind(window: tauri::Window, state: State<AppState>, id: i64, kind: String) -> Result<(), String> {
+    state.db.relabel_item(id, &kind).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+/// Swaps the positions of two pinned items; both must be pinned.
+#[tauri::command]
+pub fn swap_pin_order(window: tauri::Window, state: State<AppState>, id_a: i64, id_b: i64) -> Result<(), String> {
+    state.db.swap_pin_order(id_a, id_b).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_item_sensitive(window: tauri::Window, state: State<AppState>, id: i64, sensitive: bool) -> Result<(), String> {
+    state.db.set_sensitive(id, sensitive).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+/// Attaches a short note to an item (encrypted like its content), or
+/// removes it when `note` is empty. Notes are matched by `search`.
+#[tauri::command]
+pub fn set_item_note(window: tauri::Window, state: State<AppState>, id: i64, note: Option<String>) -> Result<(), String> {
+    ensure_unlocked(&state)?;
+    set_note(&state.db, &state.crypto, id, note.as_deref()).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+/// How long the UI should show revealed content before masking it again.
+const REVEAL_MASK_AFTER_MS: u64 = 15_000;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RevealedItem {
+  pub content: String,
+  pub mask_after_ms: u64,
+  pub reveal_count: i64,
+}
+
+/// Returns a sensitive text item's content once, for a timed reveal in the
+/// UI; the item stays masked. Every reveal is counted and emitted as
+/// `item_revealed`.
+#[tauri::command]
+pub fn reveal_item(window: tauri::Window, state: State<AppState>, id: i64) -> Result<RevealedItem, String> {
+    ensure_unlocked(&state)?;
+    let (kind, content_blob, _, _, _) = state.db.get_item_raw(id).map_err(|e| e.to_string())?;
+    if !is_text_kind(&kind) { return Err("only text items can be revealed".into()); }
+    let sensitive = state.db.get_item(id).map_err(|e| e.to_string())?.is_sensitive;
+    if !sensitive { return Err("item is not marked sensitive".into()); }
+    let ct = content_blob.ok_or("no content")?;
+    let pt = state.crypto.decrypt(&ct).map_err(|e| e.to_string())?;
+    let reveal_count = state.db.record_reveal(id).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("item_revealed", serde_json::json!({ "id": id, "reveal_count": reveal_count }));
+    Ok(RevealedItem {
+        content: String::from_utf8_lossy(&pt).into_owned(),
+        mask_after_ms: REVEAL_MASK_AFTER_MS,
+        reveal_count,
+    })
+}
+
+#[tauri::command]
+pub fn delete_item(window: tauri::Window, state: State<AppState>, id: i64) -> Result<(), String> {
+    state.db.delete_item(id).map_err(|e| e.to_string())?;
+    clipboard::remove_restored_file(id);
+    if state.settings.lock().sticky_item_id == Some(id) {
+        save_sticky(&window, &state, None);
+    }
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_settings(state: State<AppState>) -> Result<crate::state::Settings, String> {
+    Ok(state.settings.lock().clone())
+}
+
+/// Filter and query to restore on open; empty unless
+/// `restore_search_on_open` is set.
+#[tauri::command]
+pub fn get_last_filter_state(app: tauri::AppHandle, state: State<AppState>) -> Result<crate::state::FilterState, String> {
+    if !state.settings.lock().restore_search_on_open {
+        return Ok(Default::default());
+    }
+    let path = crate::state::session_path(crate::state::app_data_dir(&app));
+    Ok(crate::state::load_filter_state(&path))
+}
+
+/// Remembers the current filter and query; when restoring is off any
+/// saved state is cleared instead, so queries don't linger on disk.
+#[tauri::command]
+pub fn set_last_filter_state(app: tauri::AppHandle, state: State<AppState>, kind: Option<String>, query: String) -> Result<(), String> {
+    let f = if state.settings.lock().restore_search_on_open {
+        crate::state::FilterState { kind, query }
+    } else {
+        Default::default()
+    };
+    let path = crate::state::session_path(crate::state::app_data_dir(&app));
+    crate::state::save_filter_state(&path, &f);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_restore_search_on_open(app: tauri::AppHandle, state: State<AppState>, enabled: bool) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.restore_search_on_open = enabled;
+    let dir = crate::state::app_data_dir(&app);
+    if !enabled {
+        crate::state::save_filter_state(&crate::state::session_path(dir.clone()), &Default::default());
+    }
+    crate::state::save_settings(&crate::state::settings_path(dir), &s);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_settings_schema() -> Result<Vec<crate::state::SettingField>, String> {
+    Ok(crate::state::settings_schema())
+}
+
+/// Shows the main window; with `focus == false` the frontmost app keeps
+/// keyboard focus so the history can be glanced at without interrupting typing.
+pub fn show_main_window(app: &tauri::AppHandle, focus: bool) {
+    let state = app.state::<AppState>();
+    state.activity.touch();
+    if focus {
+        // before showing, while the app the user came from is still in front
+        if let Some(pid) = clipboard::paste::frontmost_pid() {
+            *state.previous_app.lock() = Some(pid);
+        }
+    }
+    if let Some(win) = app.get_window("main") {
+        let _ = win.show();
+        let _ = win.unminimize();
+        if focus {
+            let _ = win.set_focus();
+        }
+    }
+}
+
+/// Replaces all global shortcuts with the ones described by `s`.
+pub fn register_shortcuts(app: &tauri::AppHandle, s: &Settings) -> Result<(), String> {
+    let mut gsm = app.global_shortcut_manager();
+    gsm.unregister_all().map_err(|e| e.to_string())?;
+    let app_for_cb = app.clone();
+    let focus = !s.peek_no_focus;
+    gsm.register(s.hotkey.as_str(), move || show_main_window(&app_for_cb, focus))
+        .map_err(|e| e.to_string())?;
+    if let Some(peek) = s.peek_hotkey.as_deref().filter(|p| !p.trim().is_empty()) {
+        let app_for_cb = app.clone();
+        gsm.register(peek, move || show_main_window(&app_for_cb, false))
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(wipe) = s.wipe_hotkey.as_deref().filter(|w| !w.trim().is_empty()) {
+        let app_for_cb = app.clone();
+        gsm.register(wipe, move || request_wipe_confirmation(&app_for_cb))
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// The wipe hotkey must not double as any other shortcut.
+fn validate_wipe_hotkey(s: &Settings) -> Result<(), String> {
+    let Some(wipe) = s.wipe_hotkey.as_deref().filter(|w| !w.trim().is_empty()) else { return Ok(()) };
+    let same = |other: &str| other.trim().eq_ignore_ascii_case(wipe.trim());
+    if same(&s.hotkey) || s.peek_hotkey.as_deref().is_some_and(same) {
+        return Err("the wipe hotkey must differ from the other hotkeys".into());
+    }
+    Ok(())
+}
+
+/// Shows the window and asks it to confirm a panic wipe with a fresh token.
+fn request_wipe_confirmation(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    match state.wipe_confirm.issue() {
+        Ok(token) => {
+            show_main_window(app, true);
+            let _ = app.emit_all("panic_wipe_requested", token);
+        }
+        Err(e) => eprintln!("panic wipe request failed: {e}"),
+    }
+}
+
+/// Registers the shortcuts of `next` and persists it; on failure the
+/// previous shortcuts are restored and settings are left untouched.
+fn apply_shortcut_settings(app: &tauri::AppHandle, state: &AppState, next: Settings) -> Result<(), String> {
+    validate_wipe_hotkey(&next)?;
+    let prev = state.settings.lock().clone();
+    if let Err(e) = register_shortcuts(app, &next) {
+        let _ = register_shortcuts(app, &prev);
+        return Err(e);
+    }
+    let mut s = state.settings.lock();
+    *s = next;
+    let path = crate::state::settings_path(crate::state::app_data_dir(app));
+    crate::state::save_settings(&path, &s);
+    Ok(())
+}
 
 #[tauri::command]
-pub fn unlock(state: State<AppState>) -> Result<(), String> {
-    state.crypto.unlock().map_err(|e| e.to_string())
+pub fn set_hotkey(window: tauri::Window, state: State<AppState>, hotkey: String) -> Result<(), String> {
+    let mut next = state.settings.lock().clone();
+    next.hotkey = hotkey;
+    apply_shortcut_settings(&window.app_handle(), &state, next)
 }
 
 #[tauri::command]
-pub fn lock(state: State<AppState>) -> Result<(), String> {
-    state.crypto.lock();
-    Ok(())
+pub fn set_peek(window: tauri::Window, state: State<AppState>, peek_hotkey: Option<String>, peek_no_focus: bool) -> Result<(), String> {
+    let mut next = state.settings.lock().clone();
+    next.peek_hotkey = peek_hotkey;
+    next.peek_no_focus = peek_no_focus;
+    apply_shortcut_settings(&window.app_handle(), &state, next)
 }
 
+/// Sets (or with `None` clears) the panic-wipe hotkey.
 #[tauri::command]
-pub fn list_recent(state: State<AppState>, limit: u32) -> Result<Vec<UiItemDto>, String> {
-    let items = state.db.list_recent(limit).map_err(|e| e.to_string())?;
-    let mut out = Vec::with_capacity(items.len());
-    for it in items {
-        let mut preview = None;
-        let mut size = it.size;
-        if it.kind == "text" {
-            if let Ok((_, Some(ct), _, _, _)) = state.db.get_item_raw(it.id) {
-                if let Ok(pt) = state.crypto.decrypt(&ct) {
-                    let s = String::from_utf8_lossy(&pt);
-                    let p: String = s.chars().take(100).collect();
-                    preview = Some(p);
-                }
-            }
-        } else if it.kind == "file" {
-            if let Some(ref fp) = it.file_path {
-                if let Some(name) = Path::new(fp).file_name().and_then(|n| n.to_str()) {
-                    preview = Some(name.to_string());
-                }
-                if size <= 0 {
-                    if let Ok(meta) = std::fs::metadata(fp) { size = meta.len() as i64; }
-                }
+pub fn set_wipe_hotkey(window: tauri::Window, state: State<AppState>, wipe_hotkey: Option<String>) -> Result<(), String> {
+    let mut next = state.settings.lock().clone();
+    next.wipe_hotkey = wipe_hotkey;
+    apply_shortcut_settings(&window.app_handle(), &state, next)
+}
+
+/// Smallest and largest `max` accepted by `get_image_preview`, in pixels.
+pub const PREVIEW_SIDE_RANGE: std::ops::RangeInclusive<u32> = 16..=1024;
+
+/// Longest side to render an image preview at: `max` clamped to
+/// `PREVIEW_SIDE_RANGE`, with `None` or 0 meaning the thumbnail size.
+/// Anything up to the thumbnail size is served as the thumbnail itself.
+pub fn preview_side(max: Option<u32>, thumb_max: u32) -> u32 {
+    match max {
+        None | Some(0) => thumb_max,
+        Some(m) => m.clamp(*PREVIEW_SIDE_RANGE.start(), *PREVIEW_SIDE_RANGE.end()).max(thumb_max),
+    }
+}
+
+/// Returns an image thumbnail as a data URL. Requests up to the configured
+/// thumbnail size (or without `max`) are served from, and fill, the
+/// encrypted `preview_blob` cache; larger sizes, up to 1024px, are rendered
+/// on the fly. Past `preview_max_data_url_bytes` the result is downsized and
+/// flagged as such.
+#[tauri::command]
+pub fn get_image_preview(state: State<AppState>, id: i64, max: Option<u32>) -> Result<clipboard::ImagePreview, String> {
+    ensure_unlocked(&state)?;
+    let (kind, content_blob, preview_blob, _, _) = state.db.get_item_raw(id).map_err(|e| e.to_string())?;
+    if kind != "image" { return Err("not an image".into()); }
+    let (thumb_max, format, budget) = {
+        let s = state.settings.lock();
+        (s.thumbnail_max, s.thumbnail_format, s.preview_max_data_url_bytes)
+    };
+    let max_side = preview_side(max, thumb_max);
+    let cacheable = max_side == thumb_max;
+    let cached = preview_blob.as_ref().filter(|_| cacheable).and_then(|ct| state.crypto.decrypt(ct).ok());
+    let thumb = match cached {
+        Some(t) => t,
+        // thumbnail-only: the kept thumbnail is all there is to scale
+        None if content_blob.is_none() => {
+            let pt = state.crypto.decrypt(&preview_blob.ok_or("no content")?).map_err(|e| e.to_string())?;
+            clipboard::item_thumbnail(&state.db, id, &pt, max_side, format).map_err(|e| e.to_string())?
+        }
+        None => {
+            let ct = content_blob.ok_or("no content")?;
+            let pt = state.crypto.decrypt(&ct).map_err(|e| e.to_string())?; // PNG
+            let t = clipboard::item_thumbnail(&state.db, id, &pt, max_side, format).map_err(|e| e.to_string())?;
+            if cacheable {
+                let _sealing = state.crypto.sealing();
+                if let Ok(enc) = state.crypto.encrypt(&t) { let _ = state.db.set_preview(id, Some(&enc)); }
             }
+            t
         }
-        out.push(UiItemDto {
-            id: it.id,
-            created_at: it.created_at,
-            kind: it.kind,
-            size,
-            sha256_hex: it.sha256_hex,
-            file_path: it.file_path,
-            is_pinned: it.is_pinned,
-            preview,
-        });
+    };
+    clipboard::preview_data_url(&thumb, format, budget).map_err(|e| e.to_string())
+}
+
+/// Decodes a text item holding a base64 `data:` URI or a long base64
+/// string: a thumbnail for images, the text for text. `None` when the
+/// content does not look encoded. Sensitive items must be revealed instead.
+#[tauri::command]
+pub fn decode_item(state: State<AppState>, id: i64) -> Result<Option<clipboard::decode::DecodedPreview>, String> {
+    ensure_unlocked(&state)?;
+    let item = state.db.get_item(id).map_err(|e| e.to_string())?;
+    if !is_text_kind(&item.kind) { return Err("only text items can be decoded".into()); }
+    if item.is_sensitive { return Err("item is marked sensitive".into()); }
+    let (_, content_blob, _, _, _) = state.db.get_item_raw(id).map_err(|e| e.to_string())?;
+    let pt = state.crypto.decrypt(&content_blob.ok_or("no content")?).map_err(|e| e.to_string())?;
+    let (max, format) = {
+        let s = state.settings.lock();
+        (s.thumbnail_max, s.thumbnail_format)
+    };
+    Ok(clipboard::decode::decode_preview(&String::from_utf8_lossy(&pt), max, format))
+}
+
+/// Rich preview of a text item: its captured HTML with scripts, event
+/// handlers and remote loads stripped, or the plain text when no HTML was
+/// captured. Sensitive items must be revealed instead.
+#[tauri::command]
+pub fn get_item_html_preview(state: State<AppState>, id: i64) -> Result<clipboard::html::HtmlPreview, String> {
+    ensure_unlocked(&state)?;
+    clipboard::html::html_preview(&state.db, &state.crypto, id).map_err(|e| e.to_string())
+}
+
+/// A text item's content with its encoding detected instead of assuming
+/// UTF-8, and the encoding used; `lossy` when none fit.
+#[tauri::command]
+pub fn get_item_text_decoded(state: State<AppState>, id: i64) -> Result<clipboard::charset::DecodedText, String> {
+    ensure_unlocked(&state)?;
+    clipboard::charset::item_text_decoded(&state.db, &state.crypto, id).map_err(|e| e.to_string())
+}
+
+/// On-demand preview of a file item's content; `None` when the file is
+/// missing, binary, too large, or not a supported image.
+#[tauri::command]
+pub fn get_file_preview(state: State<AppState>, id: i64) -> Result<Option<clipboard::preview::FilePreview>, String> {
+    let (kind, _, _, _, file_path) = state.db.get_item_raw(id).map_err(|e| e.to_string())?;
+    if kind != "file" { return Err("not a file".into()); }
+    let path = clipboard::resolve_file_path(&state.db, &state.crypto, id, file_path)
+        .map_err(|e| e.to_string())?
+        .ok_or("no file path")?;
+    let (max, format) = {
+        let s = state.settings.lock();
+        (s.thumbnail_max, s.thumbnail_format)
+    };
+    // a moved or unreadable file just has no preview
+    Ok(clipboard::preview::file_preview(Path::new(&path), max, format).unwrap_or(None))
+}
+
+#[tauri::command]
+pub fn set_thumbnail_options(window: tauri::Window, state: State<AppState>, max: u32, format: clipboard::ThumbnailFormat) -> Result<(), String> {
+    if !PREVIEW_SIDE_RANGE.contains(&max) { return Err("thumbnail max must be between 16 and 1024".into()); }
+    {
+        let mut s = state.settings.lock();
+        if s.thumbnail_max == max && s.thumbnail_format == format { return Ok(()); }
+        s.thumbnail_max = max;
+        s.thumbnail_format = format;
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
     }
-    Ok(out)
+    // cached thumbnails were rendered with the old options
+    state.db.clear_previews().map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+/// Caps the data URL `get_image_preview` returns; 0 removes the cap.
+#[tauri::command]
+pub fn set_preview_max_data_url_bytes(window: tauri::Window, state: State<AppState>, bytes: usize) -> Result<(), String> {
+    if bytes != 0 && bytes < 4096 { return Err("preview budget must be 0 or at least 4096 bytes".into()); }
+    let mut s = state.settings.lock();
+    s.preview_max_data_url_bytes = bytes;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_dedup_window(window: tauri::Window, state: State<AppState>, minutes: u64) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.dedup_window_minutes = minutes;
+    state.db.set_dedup_window(minutes);
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
 }
 
+/// Caps history at `max` unpinned items (`None` = no cap) and trims to it
+/// right away. Returns how many items were removed.
 #[tauri::command]
-pub fn search(state: State<AppState>, query: String, kind: Option<String>, limit: u32) -> Result<Vec<UiItemDto>, String> {
-    // Since payloads are encrypted, we retrieve recent items and filter after (if unlocked).
-    let mut items = state.db.list_recent(200).map_err(|e| e.to_string())?;
-    if let Some(k) = kind {
-        items.retain(|i| i.kind == k);
+pub fn set_max_items(window: tauri::Window, state: State<AppState>, max: Option<u64>) -> Result<usize, String> {
+    if max == Some(0) {
+        return Err("the item limit must be at least 1".into());
     }
-    if query.trim().is_empty() {
-        items.truncate(limit as usize);
-        // hydrate previews
-        let mapped = items.into_iter().map(|it| {
-            let mut preview = None;
-            let mut size = it.size;
-            if it.kind == "text" {
-                if let Ok((_, Some(ct), _, _, _)) = state.db.get_item_raw(it.id) {
-                    if let Ok(pt) = state.crypto.decrypt(&ct) {
-                        let s = String::from_utf8_lossy(&pt);
-                        preview = Some(s.chars().take(100).collect());
-                    }
-                }
-            } else if it.kind == "file" {
-                if let Some(ref fp) = it.file_path {
-                    if let Some(name) = Path::new(fp).file_name().and_then(|n| n.to_str()) {
-                        preview = Some(name.to_string());
-                    }
-                    if size <= 0 { if let Ok(meta) = std::fs::metadata(fp) { size = meta.len() as i64; } }
-                }
-            }
-            UiItemDto {
-                id: it.id,
-                created_at: it.created_at,
-                kind: it.kind,
-                size,
-                sha256_hex: it.sha256_hex,
-                file_path: it.file_path,
-                is_pinned: it.is_pinned,
-                preview,
-            }
-        }).collect();
-        return Ok(mapped);
+    let removed = state.db.set_max_items(max).map_err(|e| e.to_string())?;
+    let mut s = state.settings.lock();
+    s.max_items = max;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    if removed > 0 {
+        let _ = window.app_handle().emit_all("items_updated", ());
     }
-    let q = query.to_lowercase();
-    let mut out = Vec::new();
-    for it in items {
-        if out.len() >= limit as usize { break; }
-        match it.kind.as_str() {
-            "text" => {
-                if let Ok((_, Some(ct), _, _, _)) = state.db.get_item_raw(it.id) {
-                    if let Ok(pt) = state.crypto.decrypt(&ct) {
-                        let s_lower = String::from_utf8_lossy(&pt).to_lowercase();
-                        if s_lower.contains(&q) {
-                            let preview = Some(String::from_utf8_lossy(&pt).chars().take(100).collect());
-                            out.push(UiItemDto { id: it.id, created_at: it.created_at, kind: it.kind, size: it.size, sha256_hex: it.sha256_hex, file_path: it.file_path, is_pinned: it.is_pinned, preview });
-                        }
-                    }
-                }
-            }
-            "file" => {
-                if let Some(fp) = &it.file_path {
-                    if fp.to_lowercase().contains(&q) {
-                        let name = Path::new(fp).file_name().and_then(|n| n.to_str()).map(|s| s.to_string());
-                        out.push(UiItemDto { id: it.id, created_at: it.created_at, kind: it.kind, size: it.size, sha256_hex: it.sha256_hex, file_path: it.file_path, is_pinned: it.is_pinned, preview: name });
-                    }
-                }
-            }
-            "image" => {
-                // For images, no text — include on empty query or by kind only; if query present, skip.
-            }
-            _ => {}
-        }
+    Ok(removed)
+}
+
+/// Sets per-kind caps (e.g. `{"image": 50}`) and prunes to them right away.
+#[tauri::command]
+pub fn set_per_kind_limits(window: tauri::Window, state: State<AppState>, limits: HashMap<String, u32>) -> Result<usize, String> {
+    let removed = state.db.set_kind_limits(limits.clone()).map_err(|e| e.to_string())?;
+    let mut s = state.settings.lock();
+    s.per_kind_limits = limits;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    if removed > 0 {
+        let _ = window.app_handle().emit_all("items_updated", ());
     }
-    Ok(out)
+    Ok(removed)
 }
 
 #[tauri::command]
-pub fn copy_item(state: State<AppState>, id: i64) -> Result<(), String> {
-    clipboard::copy_back(&state.db, &state.crypto, id).map_err(|e| e.to_string())
+pub fn set_capture_transforms(window: tauri::Window, state: State<AppState>, transforms: Vec<clipboard::transform::CaptureTransform>) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.capture_transforms = transforms;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
 }
 
 #[tauri::command]
-pub fn pin_item(window: tauri::Window, state: State<AppState>, id: i64, pin: bool) -> Result<(), String> {
-    state.db.pin_item(id, pin).map_err(|e| e.to_string())?;
-    let _ = window.app_handle().emit_all("items_updated", ());
+pub fn set_health_check_interval(window: tauri::Window, state: State<AppState>, minutes: u64) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.health_check_minutes = minutes;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
     Ok(())
 }
 
+/// Sets how many days images keep their full content (0 = forever) and
+/// applies it right away.
 #[tauri::command]
-pub fn delete_item(window: tauri::Window, state: State<AppState>, id: i64) -> Result<(), String> {
-    state.db.delete_item(id).map_err(|e| e.to_string())?;
-    let _ = window.app_handle().emit_all("items_updated", ());
+pub fn set_image_full_days(window: tauri::Window, state: State<AppState>, days: u64) -> Result<(), String> {
+    if days > crate::state::MAX_IMAGE_FULL_DAYS {
+        return Err(format!("images can keep full content for at most {} days", crate::state::MAX_IMAGE_FULL_DAYS));
+    }
+    {
+        let mut s = state.settings.lock();
+        s.image_full_days = days;
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    }
+    run_image_downgrade(&window.app_handle(), &state);
     Ok(())
 }
 
+/// Sets how many days unpinned items are kept (`None` = forever) and
+/// purges older ones right away. Returns how many were removed.
 #[tauri::command]
-pub fn get_settings(state: State<AppState>) -> Result<crate::state::Settings, String> {
-    Ok(state.settings.lock().clone())
+pub fn set_retention_days(window: tauri::Window, state: State<AppState>, days: Option<u64>) -> Result<usize, String> {
+    if days == Some(0) {
+        return Err("retention must be at least 1 day".into());
+    }
+    {
+        let mut s = state.settings.lock();
+        s.retention_days = days;
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    }
+    purge_now(window, state)
 }
 
+/// Deletes unpinned items older than `retention_days` now instead of
+/// waiting for the daily run. Returns how many were removed.
 #[tauri::command]
-pub fn set_hotkey(window: tauri::Window, state: State<AppState>, hotkey: String) -> Result<(), String> {
-    // Re-register global shortcut
-    let app = window.app_handle();
-    let app_for_cb = app.clone();
-    let mut gsm = app.global_shortcut_manager();
-    gsm.unregister_all().map_err(|e| e.to_string())?;
-    gsm.register(hotkey.as_str(), move || {
-        if let Some(win) = app_for_cb.get_window("main") {
-            let _ = win.show();
-            let _ = win.unminimize();
-            let _ = win.set_focus();
-        }
-    }).map_err(|e| e.to_string())?;
+pub fn purge_now(window: tauri::Window, state: State<AppState>) -> Result<usize, String> {
+    let removed = purge_expired(&state).map_err(|e| e.to_string())?;
+    if removed > 0 {
+        let _ = window.app_handle().emit_all("items_updated", ());
+    }
+    Ok(removed)
+}
 
-    // Update and persist settings
+/// Turns file path encryption on or off. Turning it on seals existing
+/// paths right away if unlocked, else on the next unlock; turning it off
+/// leaves sealed paths sealed.
+#[tauri::command]
+pub fn set_encrypt_file_paths(window: tauri::Window, state: State<AppState>, enabled: bool) -> Result<usize, String> {
     {
         let mut s = state.settings.lock();
-        s.hotkey = hotkey.clone();
-        let app_dir = app.path_resolver().app_data_dir().ok_or("no app dir")?;
-        let path = crate::state::settings_path(app_dir);
-        crate::state::save_settings(&path, &s);
+        s.encrypt_file_paths = enabled;
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
     }
+    if !enabled || !state.crypto.is_unlocked() {
+        return Ok(0);
+    }
+    clipboard::seal_file_paths(&state.db, &state.crypto).map_err(|e| e.to_string())
+}
+
+/// Replaces the auto-pin rules; all of them are checked before any is saved.
+#[tauri::command]
+pub fn set_auto_pin_rules(window: tauri::Window, state: State<AppState>, rules: Vec<clipboard::autopin::AutoPinRule>) -> Result<(), String> {
+    clipboard::autopin::AutoPinRules::compile(&rules).map_err(|e| e.to_string())?;
+    let mut s = state.settings.lock();
+    s.auto_pin_rules = rules;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
     Ok(())
 }
 
+/// Configures clipboard storm detection: more than `max_repeats` identical
+/// captures within `window_ms` are ignored (0 turns it off).
 #[tauri::command]
-pub fn get_image_preview(state: State<AppState>, id: i64, max: Option<u32>) -> Result<String, String> {
-    let (kind, content_blob, _, _, _) = state.db.get_item_raw(id).map_err(|e| e.to_string())?;
-    if kind != "image" { return Err("not an image".into()); }
-    let ct = content_blob.ok_or("no content")?;
-    let pt = state.crypto.decrypt(&ct).map_err(|e| e.to_string())?; // PNG
-    let img = image::load_from_memory(&pt).map_err(|e| e.to_string())?;
-    let max_side = max.unwrap_or(128);
-    let (w, h) = img.dimensions();
-    let scale = (max_side as f32 / w.max(h) as f32).min(1.0);
-    let new_w = (w as f32 * scale).round() as u32;
-    let new_h = (h as f32 * scale).round() as u32;
-    let resized = if scale < 1.0 { img.thumbnail(new_w, new_h) } else { img };
-    let mut out = Vec::new();
-    resized.write_to(&mut std::io::Cursor::new(&mut out), image::ImageOutputFormat::Png).map_err(|e| e.to_string())?;
-    let b64 = base64::engine::general_purpose::STANDARD.encode(out);
-    Ok(format!("data:image/png;base64,{}", b64))
+pub fn set_storm_guard(window: tauri::Window, state: State<AppState>, max_repeats: u32, window_ms: u64) -> Result<(), String> {
+    if !(100..=60_000).contains(&window_ms) { return Err("storm window must be between 100 and 60000 ms".into()); }
+    let mut s = state.settings.lock();
+    s.storm_max_repeats = max_repeats;
+    s.storm_window_ms = window_ms;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// The list-refresh debounce, clamped in case the settings file was
+/// edited by hand.
+#[tauri::command]
+pub fn get_refresh_debounce_ms(state: State<AppState>) -> Result<u64, String> {
+    Ok(crate::state::clamp_refresh_debounce_ms(state.settings.lock().refresh_debounce_ms))
+}
+
+/// Sets how long the UI coalesces `items_updated` events before
+/// refreshing, clamped to 0–2000 ms. Returns the value applied and sends it
+/// as `refresh_debounce_changed` so the open window picks it up.
+#[tauri::command]
+pub fn set_refresh_debounce_ms(window: tauri::Window, state: State<AppState>, ms: u64) -> Result<u64, String> {
+    let ms = crate::state::clamp_refresh_debounce_ms(ms);
+    {
+        let mut s = state.settings.lock();
+        s.refresh_debounce_ms = ms;
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    }
+    let _ = window.app_handle().emit_all("refresh_debounce_changed", ms);
+    Ok(ms)
+}
+
+/// Sets how long the clipboard must stay unchanged before a change is
+/// captured; 0 captures every change.
+#[tauri::command]
+pub fn set_require_stable_ms(window: tauri::Window, state: State<AppState>, ms: u64) -> Result<(), String> {
+    if ms > 10_000 { return Err("stable time must be at most 10000 ms".into()); }
+    let mut s = state.settings.lock();
+    s.require_stable_ms = ms;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// Sets how often the clipboard is checked, in milliseconds (50–5000);
+/// the poll loop picks it up on its next pass.
+#[tauri::command]
+pub fn set_poll_interval(window: tauri::Window, state: State<AppState>, ms: u64) -> Result<(), String> {
+    let range = crate::state::POLL_INTERVAL_MS;
+    if !range.contains(&ms) {
+        return Err(format!("poll interval must be between {} and {} ms", range.start(), range.end()));
+    }
+    let mut s = state.settings.lock();
+    s.poll_interval_ms = ms;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// Sets the largest text, image or file captured, in bytes; 0 keeps only
+/// the built-in cap.
+#[tauri::command]
+pub fn set_max_capture_bytes(window: tauri::Window, state: State<AppState>, bytes: u64) -> Result<(), String> {
+    if bytes > clipboard::MAX_CAPTURE_BYTES as u64 {
+        return Err(format!("capture limit must be at most {} bytes", clipboard::MAX_CAPTURE_BYTES));
+    }
+    let mut s = state.settings.lock();
+    s.max_capture_bytes = bytes;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// Stops capturing copies made in the app `bundle_id`; returns the updated
+/// list. Adding an app already listed, in any case, changes nothing.
+#[tauri::command]
+pub fn add_excluded_app(window: tauri::Window, state: State<AppState>, bundle_id: String) -> Result<Vec<String>, String> {
+    let bundle_id = bundle_id.trim();
+    if bundle_id.is_empty() || bundle_id.contains(char::is_whitespace) {
+        return Err(format!("invalid bundle id {bundle_id:?}"));
+    }
+    let mut s = state.settings.lock();
+    if !clipboard::is_excluded_app(&s.excluded_apps, Some(bundle_id)) {
+        s.excluded_apps.push(bundle_id.to_string());
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    }
+    Ok(s.excluded_apps.clone())
+}
+
+/// Captures copies from `bundle_id` again; returns the updated list.
+#[tauri::command]
+pub fn remove_excluded_app(window: tauri::Window, state: State<AppState>, bundle_id: String) -> Result<Vec<String>, String> {
+    let mut s = state.settings.lock();
+    let before = s.excluded_apps.len();
+    s.excluded_apps.retain(|e| !clipboard::is_excluded_app(std::slice::from_ref(e), Some(&bundle_id)));
+    if s.excluded_apps.len() != before {
+        let app_dir = crate::state::app_data_dir(&window.app_handle());
+        crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    }
+    Ok(s.excluded_apps.clone())
+}
+
+/// Sets whether copies that are only whitespace are skipped.
+#[tauri::command]
+pub fn set_ignore_whitespace_only(window: tauri::Window, state: State<AppState>, enabled: bool) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.ignore_whitespace_only = enabled;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// Sets whether the image of a text-and-image copy is stored as well.
+#[tauri::command]
+pub fn set_keep_image_with_text(window: tauri::Window, state: State<AppState>, enabled: bool) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.keep_image_with_text = enabled;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// Sets whether copies marked concealed or transient by password managers
+/// are left out of history.
+#[tauri::command]
+pub fn set_respect_transient(window: tauri::Window, state: State<AppState>, enabled: bool) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.respect_transient = enabled;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// Sets the extra pasteboard types kept with captures and restored on
+/// copy; an empty list turns the feature off.
+#[tauri::command]
+pub fn set_extra_utis(window: tauri::Window, state: State<AppState>, utis: Vec<String>) -> Result<(), String> {
+    let mut cleaned: Vec<String> = Vec::new();
+    for uti in utis.iter().map(|u| u.trim()) {
+        if uti.is_empty() || uti.contains(char::is_whitespace) {
+            return Err(format!("invalid pasteboard type {uti:?}"));
+        }
+        if !cleaned.iter().any(|c| c == uti) {
+            cleaned.push(uti.to_string());
+        }
+    }
+    let mut s = state.settings.lock();
+    s.extra_utis = cleaned;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_dedup_policy(window: tauri::Window, state: State<AppState>, policy: crate::db::DedupPolicy) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.dedup_policy = policy;
+    state.db.set_dedup_policy(policy);
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_locked_capture_behavior(window: tauri::Window, state: State<AppState>, behavior: clipboard::LockedCaptureBehavior) -> Result<(), String> {
+    let mut s = state.settings.lock();
+    s.locked_capture_behavior = behavior;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// Copies skipped while locked since the last unlock (`notify` mode);
+/// also emitted as `captures_missed` after each one.
+#[tauri::command]
+pub fn get_missed_captures(state: State<AppState>) -> Result<usize, String> {
+    Ok(state.locked_captures.missed())
+}
+
+/// First-run choice between the Keychain key and an ephemeral per-launch
+/// key. Only allowed while history is empty, since switching would strand
+/// existing items; takes effect on the next launch.
+#[tauri::command]
+pub fn choose_key_mode(window: tauri::Window, state: State<AppState>, mode: crate::crypto::KeyMode) -> Result<(), String> {
+    if !state.db.list_recent(1).map_err(|e| e.to_string())?.is_empty() {
+        return Err("the key mode can only be chosen while history is empty".into());
+    }
+    let mut s = state.settings.lock();
+    s.key_mode = mode;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// First-run choice to derive the key from a master passphrase. Like
+/// `choose_key_mode`, only allowed while history is empty and applied on
+/// the next launch.
+#[tauri::command]
+pub fn choose_passphrase_mode(window: tauri::Window, state: State<AppState>, enabled: bool) -> Result<(), String> {
+    if !state.db.list_recent(1).map_err(|e| e.to_string())?.is_empty() {
+        return Err("passphrase mode can only be chosen while history is empty".into());
+    }
+    let mut s = state.settings.lock();
+    s.use_passphrase = enabled;
+    let app_dir = crate::state::app_data_dir(&window.app_handle());
+    crate::state::save_settings(&crate::state::settings_path(app_dir), &s);
+    Ok(())
+}
+
+/// Re-encrypts all history from `old`'s key to `new`'s in one transaction;
+/// `before_commit` runs last and can still roll it back. Fails, changing
+/// nothing, if any blob does not decrypt under `old`.
+pub fn reencrypt_all(db: &Database, old: &KeyManager, new: &KeyManager, before_commit: impl FnOnce() -> anyhow::Result<()>) -> anyhow::Result<usize> {
+    db.reencrypt_all(|blob| Ok(new.encrypt(&old.decrypt(blob)?)?), before_commit)
+}
+
+/// Replaces the master key with a fresh one and re-encrypts all history
+/// under it. The new key is adopted just before the commit, while the
+/// database is still held, and swapped back if the commit fails. Anything
+/// holding `KeyManager::sealing` is kept out for the whole rotation.
+pub fn rotate_key_of(db: &Database, crypto: &KeyManager) -> anyhow::Result<usize> {
+    // captures and other writes of new blobs wait until the swap is done
+    let _rotation = crypto.rotating();
+    let rotated = crypto.rotate_key()?;
+    let mut previous = None;
+    let result = reencrypt_all(db, crypto, &rotated, || {
+        previous = Some(crypto.adopt_key(&rotated)?);
+        Ok(())
+    });
+    if let (Err(_), Some(previous)) = (&result, previous) {
+        crypto.adopt_key(&previous)?;
+    }
+    result
+}
+
+/// Rotates the master key without losing history; see `rotate_key_of`.
+/// Returns how many encrypted blobs were rewritten.
+#[tauri::command]
+pub fn rotate_master_key(window: tauri::Window, state: State<AppState>) -> Result<usize, String> {
+    ensure_unlocked(&state)?;
+    let result = rotate_key_of(&state.db, &state.crypto).map_err(|e| e.to_string());
+    // copies made during the rotation were held back; store them under the new key
+    let settings = state.settings.lock().clone();
+    state.locked_captures.flush(&state.db, &state.crypto, &settings);
+    let _ = window.app_handle().emit_all("items_updated", ());
+    result
 }
 
 #[tauri::command]
@@ -216,6 +1882,100 @@ pub fn reset_master_key(state: State<AppState>) -> Result<(), String> {
     state.crypto.reset_master_key().map_err(|e| e.to_string())
 }
 
+/// Erases all history, buffered locked captures and restored file copies,
+/// then replaces the master key. Unlike `lock` there is no way back.
+pub fn wipe_everything(db: &Database, crypto: &KeyManager, locked: &clipboard::LockedCaptures) -> anyhow::Result<()> {
+    locked.discard();
+    db.clear_all()?;
+    clipboard::clear_restored_files();
+    crypto.reset_master_key()?;
+    Ok(())
+}
+
+/// First half of a panic wipe from the UI: a one-time token, valid for a
+/// minute, that `panic_wipe` must be given back.
+#[tauri::command]
+pub fn request_panic_wipe(state: State<AppState>) -> Result<String, String> {
+    state.wipe_confirm.issue().map_err(|e| e.to_string())
+}
+
+/// Destroys all history and resets the key, given the token from
+/// `request_panic_wipe` or the `panic_wipe_requested` event.
+#[tauri::command]
+pub fn panic_wipe(window: tauri::Window, state: State<AppState>, token: String) -> Result<(), String> {
+    if !state.wipe_confirm.redeem(&token) {
+        return Err("invalid or expired confirmation".into());
+    }
+    wipe_everything(&state.db, &state.crypto, &state.locked_captures).map_err(|e| e.to_string())?;
+    let _ = window.app_handle().emit_all("items_updated", ());
+    Ok(())
+}
+
+/// Re-runs kind detection over existing plain-text items on a background
+/// thread, emitting `reclassify_progress` per batch and `reclassify_done`
+/// with the final report.
+#[tauri::command]
+pub fn reclassify_items(window: tauri::Window, state: State<AppState>) -> Result<(), String> {
+    ensure_unlocked(&state)?;
+    state.reclassify_cancel.store(false, Ordering::Relaxed);
+    let app = window.app_handle();
+    let st = state.inner().clone();
+    std::thread::spawn(move || {
+        let res = clipboard::detect::reclassify_text_items(&st.db, &st.crypto, &st.reclassify_cancel, |done, total| {
+            let _ = app.emit_all("reclassify_progress", serde_json::json!({ "done": done, "total": total }));
+        });
+        match res {
+            Ok(report) => {
+                let _ = app.emit_all("reclassify_done", report);
+                let _ = app.emit_all("items_updated", ());
+            }
+            Err(e) => eprintln!("reclassify failed: {e:?}"),
+        }
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn cancel_reclassify(state: State<AppState>) -> Result<(), String> {
+    state.reclassify_cancel.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Explains a storage fallback made at startup (read-only or full data
+/// dir), if any; also emitted once as `storage_warning`.
+#[tauri::command]
+pub fn get_storage_warning(state: State<AppState>) -> Result<Option<String>, String> {
+    Ok(state.storage_warning.clone())
+}
+
+#[tauri::command]
+pub fn repair_schema(window: tauri::Window, state: State<AppState>) -> Result<Vec<String>, String> {
+    let fixes = state.db.repair_schema().map_err(|e| e.to_string())?;
+    if !fixes.is_empty() {
+        let _ = window.app_handle().emit_all("items_updated", ());
+    }
+    Ok(fixes)
+}
+
+/// Copies `id` back, reports which pasteboard types it produced and their
+/// sizes, and restores the previous clipboard; for debugging lost formatting.
+#[tauri::command]
+pub fn diagnose_copy(state: State<AppState>, id: i64) -> Result<clipboard::CopyDiagnosis, String> {
+    ensure_unlocked(&state)?;
+    #[cfg(target_os = "macos")]
+    {
+        return state
+            .self_writes
+            .around(clipboard::change_count, || clipboard::diagnose_copy(&state.db, &state.crypto, id))
+            .map_err(|e| e.to_string());
+    }
+    #[allow(unreachable_code)]
+    {
+        let _ = id;
+        Err("not supported".into())
+    }
+}
+
 #[tauri::command]
 pub fn reveal_in_finder(path: String) -> Result<(), String> {
     #[cfg(target_os = "macos")]
@@ -230,3 +1990,40 @@ pub fn reveal_in_finder(path: String) -> Result<(), String> {
     #[allow(unreachable_code)]
     Err("not supported".into())
 }
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DataPaths {
+  pub data_dir: String,
+  pub database: String,
+  pub wal: Option<String>,
+  pub settings: String,
+}
+
+/// Where history and settings live, for manual backups. `database` can
+/// differ from `data_dir` after a storage fallback at startup.
+#[tauri::command]
+pub fn get_data_paths(app: tauri::AppHandle, state: State<AppState>) -> Result<DataPaths, String> {
+  let dir = crate::state::app_data_dir(&app);
+  Ok(DataPaths {
+    data_dir: dir.display().to_string(),
+    database: state.db.path().display().to_string(),
+    wal: state.db.wal_path().map(|p| p.display().to_string()),
+    settings: crate::state::settings_path(dir).display().to_string(),
+  })
+}
+
+#[tauri::command]
+pub fn reveal_data_dir(app: tauri::AppHandle) -> Result<(), String> {
+    #[cfg(target_os = "macos")]
+    {
+        let dir = crate::state::app_data_dir(&app);
+        std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+        std::process::Command::new("open")
+            .arg(&dir)
+            .status()
+            .map_err(|e| e.to_string())?;
+        return Ok(());
+    }
+    #[allow(unreachable_code)]
+    Err("not supported".into())
+}
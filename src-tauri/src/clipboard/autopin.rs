@@ -0,0 +1,94 @@
+use crate::db::Database;
+use anyhow::{anyhow, Result};
+use regex::{Regex, RegexBuilder};
+
+/// Most rules kept; each capture is checked against all of them.
+pub const MAX_AUTO_PIN_RULES: usize = 32;
+/// Longest pattern accepted, in bytes.
+const MAX_PATTERN_LEN: usize = 500;
+/// Compiled size limit per pattern, so a pathological regex is rejected
+/// instead of slowing every capture.
+const REGEX_SIZE_LIMIT: usize = 256 * 1024;
+/// Only this much of the captured text is matched against patterns.
+const MATCH_TEXT_BYTES: usize = 64 * 1024;
+
+/// Pins captures that come from `source_app` and/or whose text matches
+/// `text_pattern`. With both set, both must match.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AutoPinRule {
+    /// Bundle id of the app copied from, e.g. `com.apple.Notes`.
+    pub source_app: Option<String>,
+    /// Regex searched for in captured text, e.g. `^TODO:`.
+    pub text_pattern: Option<String>,
+}
+
+/// Rules ready to evaluate; build once per capture with `compile`.
+pub struct AutoPinRules(Vec<(Option<String>, Option<Regex>)>);
+
+impl AutoPinRules {
+    /// Compiles `rules`, failing on the first invalid one.
+    pub fn compile(rules: &[AutoPinRule]) -> Result<Self> {
+        if rules.len() > MAX_AUTO_PIN_RULES {
+            return Err(anyhow!("at most {MAX_AUTO_PIN_RULES} auto-pin rules are allowed"));
+        }
+        let mut out = Vec::with_capacity(rules.len());
+        for rule in rules {
+            let app = rule.source_app.as_deref().map(str::trim).filter(|a| !a.is_empty());
+            let pattern = rule.text_pattern.as_deref().filter(|p| !p.is_empty());
+            if app.is_none() && pattern.is_none() {
+                return Err(anyhow!("an auto-pin rule needs a source app or a text pattern"));
+            }
+            let regex = match pattern {
+                Some(p) if p.len() > MAX_PATTERN_LEN => {
+                    return Err(anyhow!("auto-pin patterns are limited to {MAX_PATTERN_LEN} bytes"));
+                }
+                Some(p) => Some(
+                    RegexBuilder::new(p)
+                        .size_limit(REGEX_SIZE_LIMIT)
+                        .build()
+                        .map_err(|e| anyhow!("invalid pattern {p:?}: {e}"))?,
+                ),
+                None => None,
+            };
+            out.push((app.map(str::to_string), regex));
+        }
+        Ok(Self(out))
+    }
+
+    /// Whether any rule matches a capture from `source_app` with `text`.
+    /// Pattern rules never match captures without text.
+    pub fn matches(&self, source_app: Option<&str>, text: Option<&str>) -> bool {
+        let text = text.map(|t| &t[..floor_char_boundary(t, MATCH_TEXT_BYTES)]);
+        self.0.iter().any(|(app, regex)| {
+            let app_ok = app.is_none() || app.as_deref() == source_app;
+            let text_ok = match regex {
+                Some(r) => text.is_some_and(|t| r.is_match(t)),
+                None => true,
+            };
+            app_ok && text_ok
+        })
+    }
+}
+
+fn floor_char_boundary(s: &str, max: usize) -> usize {
+    if s.len() <= max {
+        return s.len();
+    }
+    (0..=max).rev().find(|&i| s.is_char_boundary(i)).unwrap_or(0)
+}
+
+/// Pins `ids`, the items just stored for one capture, when a rule matches.
+/// Returns whether they were pinned.
+pub fn apply_auto_pin(db: &Database, rules: &[AutoPinRule], source_app: Option<&str>, text: Option<&str>, ids: &[i64]) -> Result<bool> {
+    if rules.is_empty() || ids.is_empty() {
+        return Ok(false);
+    }
+    if !AutoPinRules::compile(rules)?.matches(source_app, text) {
+        return Ok(false);
+    }
+    for &id in ids {
+        db.pin_item(id, true)?;
+    }
+    Ok(true)
+}
@@ -0,0 +1,77 @@
+use crate::crypto::KeyManager;
+use crate::db::{is_text_kind, Database};
+use anyhow::{bail, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// Text decoded from bytes of unknown encoding.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DecodedText {
+    pub text: String,
+    /// WHATWG name of the encoding used, e.g. "UTF-8" or "windows-1252".
+    pub encoding: &'static str,
+    /// Set when no encoding fit and invalid bytes were replaced with U+FFFD,
+    /// the way every other command reads text.
+    pub lossy: bool,
+}
+
+/// Decodes `bytes` in the first encoding that fits: UTF-8, a byte order
+/// mark, UTF-16 without one (told apart by its zero bytes), then whatever
+/// chardetng guesses. Falls back to lossy UTF-8.
+pub fn decode_text_bytes(bytes: &[u8]) -> DecodedText {
+    let decoded = |encoding: &'static Encoding, text: String| DecodedText { text, encoding: encoding.name(), lossy: false };
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(&bytes[bom_len..]) {
+            return decoded(encoding, text.into_owned());
+        }
+    }
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return decoded(UTF_8, text.to_string());
+    }
+    if let Some(encoding) = utf16_without_bom(bytes) {
+        if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+            return decoded(encoding, text.into_owned());
+        }
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    let encoding = detector.guess(None, false);
+    if let Some(text) = encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+        return decoded(encoding, text.into_owned());
+    }
+    DecodedText { text: String::from_utf8_lossy(bytes).into_owned(), encoding: UTF_8.name(), lossy: true }
+}
+
+// Mostly-Latin UTF-16 has a zero in every other byte; which half holds
+// them gives the byte order. chardetng does not detect UTF-16 at all.
+fn utf16_without_bom(bytes: &[u8]) -> Option<&'static Encoding> {
+    let pairs = bytes.len() / 2;
+    if pairs == 0 || pairs * 2 != bytes.len() {
+        return None;
+    }
+    let zeros_at = |offset: usize| bytes.iter().skip(offset).step_by(2).filter(|&&b| b == 0).count();
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if odd * 2 > pairs && even == 0 {
+        Some(UTF_16LE)
+    } else if even * 2 > pairs && odd == 0 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// A text item's content decoded with `decode_text_bytes`, for items
+/// copied from apps that put legacy encodings on the clipboard. Sensitive
+/// items must be revealed instead.
+pub fn item_text_decoded(db: &Database, crypto: &KeyManager, id: i64) -> Result<DecodedText> {
+    let item = db.get_item(id)?;
+    if !is_text_kind(&item.kind) {
+        bail!("only text items can be decoded");
+    }
+    if item.is_sensitive {
+        bail!("item is marked sensitive");
+    }
+    let (_, content_blob, _, _, _) = db.get_item_raw(id)?;
+    let Some(blob) = content_blob else { bail!("no content") };
+    Ok(decode_text_bytes(&crypto.decrypt(&blob)?))
+}
@@ -0,0 +1,80 @@
+use super::{make_thumbnail, ThumbnailFormat};
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+
+/// Bare base64 shorter than this is left alone; short tokens are far more
+/// likely to be words or ids than encoded payloads.
+const MIN_BASE64_LEN: usize = 64;
+/// Encoded input larger than this is not decoded.
+const MAX_ENCODED_LEN: usize = 16 * 1024 * 1024;
+/// Characters of decoded text returned.
+const TEXT_PREVIEW_CHARS: usize = 4000;
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum DecodedPreview {
+    /// Decoded UTF-8 text.
+    Text { text: String, truncated: bool },
+    /// Decoded image, downscaled, as a `data:` URL.
+    Image { data_url: String },
+    /// Anything else; only the size is reported.
+    Binary { bytes: usize },
+}
+
+/// Decodes `text` if it is a base64 `data:` URI or a long bare base64
+/// string and describes the result. Returns `None` for anything that does
+/// not clearly look encoded: short strings, whitespace inside the payload,
+/// characters outside the base64 alphabet, or plain hex digests.
+pub fn decode_preview(text: &str, max: u32, format: ThumbnailFormat) -> Option<DecodedPreview> {
+    let bytes = decode_base64_text(text)?;
+    if image::guess_format(&bytes).is_ok() {
+        if let Ok(thumb) = make_thumbnail(&bytes, max, format) {
+            return Some(DecodedPreview::Image {
+                data_url: format!("data:{};base64,{}", format.mime(), STANDARD.encode(thumb)),
+            });
+        }
+    }
+    match String::from_utf8(bytes) {
+        Ok(s) if !s.contains('\0') => {
+            let truncated = s.chars().count() > TEXT_PREVIEW_CHARS;
+            Some(DecodedPreview::Text { text: s.chars().take(TEXT_PREVIEW_CHARS).collect(), truncated })
+        }
+        Ok(s) => Some(DecodedPreview::Binary { bytes: s.len() }),
+        Err(e) => Some(DecodedPreview::Binary { bytes: e.as_bytes().len() }),
+    }
+}
+
+/// Raw bytes behind a `data:...;base64,` URI or a bare base64 string.
+pub fn decode_base64_text(text: &str) -> Option<Vec<u8>> {
+    let t = text.trim();
+    if t.len() > MAX_ENCODED_LEN {
+        return None;
+    }
+    if let Some(rest) = t.strip_prefix("data:") {
+        let (header, payload) = rest.split_once(',')?;
+        if !header.ends_with(";base64") {
+            return None;
+        }
+        return decode_any(&strip_line_breaks(payload));
+    }
+    let payload = strip_line_breaks(t);
+    if payload.len() < MIN_BASE64_LEN
+        || payload.chars().any(|c| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '-' | '_' | '=')))
+        || payload.trim_end_matches('=').contains('=')
+        || payload.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    decode_any(&payload)
+}
+
+// Wrapped base64 (e.g. PEM bodies, MIME) breaks lines every 64/76 chars.
+fn strip_line_breaks(s: &str) -> String {
+    s.chars().filter(|c| !matches!(c, '\n' | '\r')).collect()
+}
+
+fn decode_any(payload: &str) -> Option<Vec<u8>> {
+    [&STANDARD, &STANDARD_NO_PAD, &URL_SAFE, &URL_SAFE_NO_PAD]
+        .iter()
+        .find_map(|engine| engine.decode(payload).ok())
+}
@@ -0,0 +1,110 @@
+use crate::crypto::{CryptoError, KeyManager};
+use crate::db::Database;
+use anyhow::Result;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const RECLASSIFY_BATCH: u32 = 200;
+
+const CODE_PREFIXES: &[&str] = &[
+    "fn ", "pub ", "let ", "const ", "def ", "class ", "function ", "import ", "from ", "#include", "return ", "if (", "for (",
+];
+
+/// Picks a specific kind for captured text: "url", "color", "code", or
+/// plain "text" when nothing matches. Detection is deliberately
+/// conservative; a miss just leaves the item as "text".
+pub fn detect_text_kind(text: &str) -> &'static str {
+    let t = text.trim();
+    if t.is_empty() {
+        return "text";
+    }
+    if is_url(t) {
+        "url"
+    } else if is_color(t) {
+        "color"
+    } else if is_code(t) {
+        "code"
+    } else {
+        "text"
+    }
+}
+
+fn is_url(t: &str) -> bool {
+    if t.chars().any(char::is_whitespace) {
+        return false;
+    }
+    match url::Url::parse(t) {
+        Ok(u) => matches!(u.scheme(), "http" | "https" | "ftp" | "mailto") && (u.has_host() || u.scheme() == "mailto"),
+        Err(_) => false,
+    }
+}
+
+fn is_color(t: &str) -> bool {
+    if let Some(hex) = t.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+    let lower = t.to_ascii_lowercase();
+    ["rgb(", "rgba(", "hsl(", "hsla("].iter().any(|p| lower.starts_with(p))
+        && lower.ends_with(')')
+        && lower.len() <= 48
+}
+
+fn is_code(t: &str) -> bool {
+    let lines: Vec<&str> = t.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+    if lines.len() < 2 {
+        return false;
+    }
+    let codeish = lines
+        .iter()
+        .filter(|l| l.ends_with(';') || l.ends_with('{') || l.ends_with('}') || CODE_PREFIXES.iter().any(|p| l.starts_with(p)))
+        .count();
+    codeish * 2 >= lines.len()
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct ReclassifyReport {
+    pub scanned: usize,
+    pub changed: usize,
+    pub cancelled: bool,
+}
+
+/// Runs the detectors over stored items still labelled plain "text" and
+/// updates their kind in place. Walks the history with `for_each_item`,
+/// checks `cancel` before each item and reports `(scanned, total)` after
+/// every batch. Items that already carry a specific kind are left alone.
+pub fn reclassify_text_items(
+    db: &Database,
+    crypto: &KeyManager,
+    cancel: &AtomicBool,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<ReclassifyReport> {
+    if !crypto.is_unlocked() {
+        return Err(CryptoError::Locked.into());
+    }
+    let total = db.count_kind("text")? as usize;
+    let mut report = ReclassifyReport::default();
+    db.for_each_item(RECLASSIFY_BATCH, |it| {
+        if cancel.load(Ordering::Relaxed) {
+            report.cancelled = true;
+            return Ok(false);
+        }
+        if it.kind != "text" {
+            return Ok(true);
+        }
+        report.scanned += 1;
+        if let Ok((_, Some(ct), _, _, _)) = db.get_item_raw(it.id) {
+            if let Ok(pt) = crypto.decrypt(&ct) {
+                let kind = detect_text_kind(&String::from_utf8_lossy(&pt));
+                if kind != "text" {
+                    db.set_kind(it.id, kind)?;
+                    report.changed += 1;
+                }
+            }
+        }
+        if report.scanned % RECLASSIFY_BATCH as usize == 0 {
+            progress(report.scanned, total);
+        }
+        Ok(true)
+    })?;
+    progress(report.scanned, total);
+    Ok(report)
+}
@@ -0,0 +1,48 @@
+use crate::crypto::KeyManager;
+use crate::db::{is_text_kind, Database};
+use anyhow::{bail, Result};
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum HtmlPreview {
+    /// Sanitized markup, safe to inject into the preview pane.
+    Html { html: String },
+    /// Plain text for items captured without HTML.
+    Text { text: String },
+}
+
+/// Cleans captured HTML for display: scripts, styles, event handlers and
+/// anything else outside ammonia's allow-list are removed, and `src`/`srcset`
+/// are dropped so the preview never loads remote images or frames. Relative
+/// links are removed since there is no page to resolve them against.
+pub fn sanitize_html(html: &str) -> String {
+    ammonia::Builder::default()
+        .url_relative(ammonia::UrlRelative::Deny)
+        .attribute_filter(|_element, attribute, value| match attribute {
+            "src" | "srcset" => None,
+            _ => Some(value.into()),
+        })
+        .clean(html)
+        .to_string()
+}
+
+/// Preview of a text item's rich content: its captured HTML, sanitized, or
+/// the plain text when none was stored. Sensitive items must be revealed
+/// instead.
+pub fn html_preview(db: &Database, crypto: &KeyManager, id: i64) -> Result<HtmlPreview> {
+    let item = db.get_item(id)?;
+    if !is_text_kind(&item.kind) {
+        bail!("only text items have an HTML preview");
+    }
+    if item.is_sensitive {
+        bail!("item is marked sensitive");
+    }
+    if let Some(blob) = db.get_html_blob(id)? {
+        let html = crypto.decrypt(&blob)?;
+        return Ok(HtmlPreview::Html { html: sanitize_html(&String::from_utf8_lossy(&html)) });
+    }
+    let (_, content_blob, _, _, _) = db.get_item_raw(id)?;
+    let Some(blob) = content_blob else { bail!("no content") };
+    let text = crypto.decrypt(&blob)?;
+    Ok(HtmlPreview::Text { text: String::from_utf8_lossy(&text).into_owned() })
+}
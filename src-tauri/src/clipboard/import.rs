@@ -0,0 +1,88 @@
+use super::{store_snapshot, Snapshot};
+use crate::crypto::{CryptoError, KeyManager};
+use crate::db::Database;
+use crate::state::Settings;
+use anyhow::Result;
+use std::path::Path;
+
+/// Files larger than this are skipped rather than imported.
+pub const MAX_IMPORT_BYTES: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ImportedFile {
+    pub path: String,
+    /// File stem, for the UI to label the result.
+    pub title: String,
+    /// New (or deduplicated) item id; `None` when skipped.
+    pub id: Option<i64>,
+    /// Why the file was skipped.
+    pub skipped: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    pub skipped: usize,
+    pub files: Vec<ImportedFile>,
+}
+
+/// Imports UTF-8 text files as text items through the normal capture path
+/// (kind detection, encryption, dedup), optionally pinning them. Files that
+/// are missing, too large or not UTF-8 are reported as skipped instead of
+/// aborting the batch. Fails up front while locked.
+pub fn import_text_files(
+    db: &Database,
+    crypto: &KeyManager,
+    settings: &Settings,
+    paths: &[String],
+    pin: bool,
+) -> Result<ImportReport> {
+    if !crypto.is_unlocked() {
+        return Err(CryptoError::Locked.into());
+    }
+    let mut report = ImportReport::default();
+    for p in paths {
+        let path = Path::new(p);
+        let title = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| p.clone());
+        let outcome = read_text(path).and_then(|text| {
+            let ids = store_snapshot(db, crypto, settings, Snapshot { text: Some(text), ..Default::default() })
+                .map_err(|e| e.to_string())?;
+            let id = *ids.first().ok_or("nothing stored")?;
+            if pin {
+                db.pin_item(id, true).map_err(|e| e.to_string())?;
+            }
+            Ok(id)
+        });
+        let (id, skipped) = match outcome {
+            Ok(id) => {
+                report.imported += 1;
+                (Some(id), None)
+            }
+            Err(reason) => {
+                report.skipped += 1;
+                (None, Some(reason))
+            }
+        };
+        report.files.push(ImportedFile { path: p.clone(), title, id, skipped });
+    }
+    Ok(report)
+}
+
+fn read_text(path: &Path) -> std::result::Result<String, String> {
+    let meta = std::fs::metadata(path).map_err(|e| e.to_string())?;
+    if !meta.is_file() {
+        return Err("not a file".into());
+    }
+    if meta.len() > MAX_IMPORT_BYTES {
+        return Err(format!("larger than {} KB", MAX_IMPORT_BYTES / 1024));
+    }
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let text = String::from_utf8(bytes).map_err(|_| "not UTF-8 text".to_string())?;
+    if text.trim().is_empty() {
+        return Err("empty file".into());
+    }
+    Ok(text)
+}
@@ -1,11 +1,28 @@
-use crate::crypto::KeyManager;
-use crate::db::{Database, NewItem};
+pub mod autopin;
+pub mod charset;
+pub mod decode;
+pub mod detect;
+pub mod html;
+pub mod import;
+pub mod paste;
+pub mod preview;
+pub mod service;
+pub mod transform;
+pub mod typed;
+
+use crate::crypto::{CryptoError, KeyManager};
+use crate::db::{is_text_kind, Database, NewItem};
+use crate::state::Settings;
 use anyhow::{anyhow, Result};
 use arboard::{Clipboard, ImageData};
-use image::ImageFormat;
+use image::{ImageFormat, ImageOutputFormat};
 use image::GenericImageView;
-use std::path::PathBuf;
-use std::sync::Arc;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, VecDeque};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 #[cfg(target_os = "macos")]
 use cocoa::base::{id, nil};
@@ -24,95 +41,571 @@ pub fn poll_pasteboard_sync(app_handle: tauri::AppHandle, state: crate::state::A
 
     unsafe {
         let mut last: NSUInteger = 0;
+        let mut settle = Settle::default();
+        // frontmost app when the pending change happened
+        let mut source_app = None;
         loop {
+            // read every pass so settings changes apply without a restart
+            let (stable, interval) = {
+                let s = state.settings.lock();
+                (Duration::from_millis(s.require_stable_ms), Duration::from_millis(crate::state::clamp_poll_interval_ms(s.poll_interval_ms)))
+            };
             let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
             let count: NSUInteger = msg_send![pb, changeCount];
             if count != last {
                 last = count;
-                if let Err(e) = handle_change(pb, state.db.clone(), state.crypto.clone()) {
-                    eprintln!("pasteboard read error: {e:?}");
-                } else {
-                    let _ = app_handle.emit_all("items_updated", ());
+                if state.self_writes.is_own(count as i64) {
+                    // our write replaced whatever was still settling
+                    settle = Settle::default();
+                    std::thread::sleep(interval);
+                    continue;
+                }
+                settle.changed(count as i64, Instant::now());
+                // taken now, not once settled: the user may have switched apps since
+                source_app = frontmost_bundle_id();
+            }
+            if settle.take_settled(Instant::now(), stable).is_some() {
+                match handle_change(&app_handle, pb, &state, source_app.take()) {
+                    Err(e) => eprintln!("pasteboard read error: {e:?}"),
+                    Ok(ChangeOutcome::Missed(missed)) => {
+                        let _ = app_handle.emit_all("captures_missed", missed);
+                    }
+                    Ok(ChangeOutcome::Stored) => {
+                        let _ = app_handle.emit_all("items_updated", ());
+                        crate::api::emit_unread(&app_handle, &state);
+                    }
+                    Ok(ChangeOutcome::Suppressed) => {}
                 }
             }
-            std::thread::sleep(Duration::from_millis(250));
+            std::thread::sleep(interval);
         }
     }
 }
 
 #[cfg(target_os = "macos")]
-fn handle_change(pb: id, db: Arc<Database>, crypto: Arc<KeyManager>) -> Result<()> {
+enum ChangeOutcome {
+    Stored,
+    /// Running missed count of a locked capture counted under
+    /// `LockedCaptureBehavior::Notify`.
+    Missed(usize),
+    /// A repeat during a clipboard storm, a copy marked concealed or
+    /// transient or made in an excluded app, or capture paused for low disk
+    /// space; nothing was stored. Also a copy held back until a key
+    /// rotation finishes.
+    Suppressed,
+}
+
+#[cfg(target_os = "macos")]
+fn handle_change(app: &tauri::AppHandle, pb: id, state: &crate::state::AppState, source_app: Option<String>) -> Result<ChangeOutcome> {
+    use tauri::Manager;
+
+    let settings = state.settings.lock().clone();
+    if settings.pause_capture_on_low_disk && state.low_disk.load(Ordering::Relaxed) {
+        // inserts could fail half way; capture resumes once space is freed
+        return Ok(ChangeOutcome::Suppressed);
+    }
+    if settings.respect_transient && has_transient_marker(pb) {
+        // a password manager asked for this copy to stay out of history
+        return Ok(ChangeOutcome::Suppressed);
+    }
+    if is_excluded_app(&settings.excluded_apps, source_app.as_deref()) {
+        return Ok(ChangeOutcome::Suppressed);
+    }
+    let mut snap = read_snapshot(pb, settings.max_capture_bytes);
+    for clip in std::mem::take(&mut snap.skipped) {
+        eprintln!("skipped {} bytes of {}: over the capture limit", clip.bytes, clip.kind);
+        let _ = app.emit_all("capture_skipped", clip);
+    }
+    let window = Duration::from_millis(settings.storm_window_ms);
+    let fingerprint = snap.fingerprint();
+    if fingerprint
+        .as_ref()
+        .is_some_and(|fp| state.storm_guard.observe(fp, Instant::now(), settings.storm_max_repeats, window))
+    {
+        return Ok(ChangeOutcome::Suppressed);
+    }
+    if fingerprint.as_ref().is_some_and(|fp| state.last_capture.is_leftover(fp)) {
+        // still on the clipboard from before the relaunch; already stored
+        return Ok(ChangeOutcome::Suppressed);
+    }
+    if snap.file_paths.is_empty() {
+        snap.extra_types = read_extra_types(pb, &settings.extra_utis);
+    }
+    snap.source_app = source_app;
+    if !state.crypto.is_unlocked() && snap.needs_key() {
+        return Ok(match state.locked_captures.hold(snap, settings.locked_capture_behavior) {
+            Some(missed) => ChangeOutcome::Missed(missed),
+            None => ChangeOutcome::Stored,
+        });
+    }
+    let Some(_sealing) = state.crypto.try_sealing() else {
+        // a key rotation is rewriting history; stored when it finishes
+        state.locked_captures.defer(snap);
+        return Ok(ChangeOutcome::Suppressed);
+    };
+    // only kept around when there are rules to check it against
+    let pin_check = (!settings.auto_pin_rules.is_empty()).then(|| (snap.source_app.clone(), snap.text.clone()));
+    let ids = store_snapshot(&state.db, &state.crypto, &settings, snap)?;
+    if let Some(fp) = fingerprint {
+        state.last_capture.record(&fp);
+    }
+    if let Some((app, text)) = pin_check {
+        if let Err(e) = autopin::apply_auto_pin(&state.db, &settings.auto_pin_rules, app.as_deref(), text.as_deref(), &ids) {
+            eprintln!("auto-pin failed: {e}");
+        }
+    }
+    Ok(ChangeOutcome::Stored)
+}
+
+/// Bundle id of the frontmost app, taken as the app a copy came from;
+/// `None` when there is none or it has no bundle id.
+#[cfg(target_os = "macos")]
+fn frontmost_bundle_id() -> Option<String> {
+    unsafe {
+        let ws: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let app: id = msg_send![ws, frontmostApplication];
+        if app == nil {
+            return None;
+        }
+        let bundle: id = msg_send![app, bundleIdentifier];
+        if bundle == nil {
+            return None;
+        }
+        let cstr: *const std::os::raw::c_char = msg_send![bundle, UTF8String];
+        if cstr.is_null() {
+            return None;
+        }
+        Some(std::ffi::CStr::from_ptr(cstr).to_string_lossy().into_owned())
+    }
+}
+
+/// What is on the general pasteboard right now.
+#[cfg(target_os = "macos")]
+pub fn current_snapshot() -> Snapshot {
+    unsafe {
+        let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+        read_snapshot(pb, 0)
+    }
+}
+
+/// Reads every representation `store_snapshot` understands from `pb`.
+/// Past `NATIVE_READ_BYTES` text and images are read natively instead of
+/// through arboard; past `MAX_CAPTURE_BYTES`, or `max_capture_bytes` when
+/// set, they are skipped and listed in `skipped`.
+#[cfg(target_os = "macos")]
+fn read_snapshot(pb: id, max_capture_bytes: u64) -> Snapshot {
+    let mut snap = Snapshot { file_paths: read_file_urls(pb), ..Default::default() };
+    if !snap.file_paths.is_empty() {
+        return snap;
+    }
+    snap.rtf = read_rtf_data(pb);
+    snap.html = read_html_data(pb);
+    let mut cb = Clipboard::new().ok();
+    let route = |len: usize| if over_capture_limit(len, max_capture_bytes) { SizeRoute::Skip } else { size_route(len) };
+
+    let text_len = type_data_len(pb, "public.utf8-plain-text");
+    let native_text = || read_type_data(pb, "public.utf8-plain-text").map(|d| String::from_utf8_lossy(&d).into_owned());
+    snap.text = match text_len.map(route) {
+        // no utf8 data, but arboard may still read another text type as an NSString
+        None => cb.as_mut().and_then(|c| c.get_text().ok()),
+        Some(SizeRoute::Skip) => {
+            snap.skipped.push(SkippedClip { kind: "text".into(), bytes: text_len.unwrap_or(0) });
+            None
+        }
+        Some(SizeRoute::Native) => native_text(),
+        // arboard can still fail on odd encodings; the raw bytes are right there
+        Some(SizeRoute::Arboard) => cb.as_mut().and_then(|c| c.get_text().ok()).or_else(native_text),
+    };
+
+    let image_len = type_data_len(pb, "public.png").or_else(|| type_data_len(pb, "public.tiff"));
+    snap.image = match image_len.map(route) {
+        None => None,
+        Some(SizeRoute::Skip) => {
+            snap.skipped.push(SkippedClip { kind: "image".into(), bytes: image_len.unwrap_or(0) });
+            None
+        }
+        Some(route) => {
+            let via_arboard = if route == SizeRoute::Arboard { cb.as_mut().and_then(|c| c.get_image().ok()) } else { None };
+            let native = |uti, format| read_type_data(pb, uti).as_deref().and_then(|d| decode_native_image(d, format));
+            let img = via_arboard
+                .or_else(|| native("public.png", image::ImageFormat::Png))
+                .or_else(|| native("public.tiff", image::ImageFormat::Tiff));
+            if img.is_none() {
+                // e.g. a format neither arboard nor we can decode
+                snap.skipped.push(SkippedClip { kind: "image".into(), bytes: image_len.unwrap_or(0) });
+            }
+            img
+        }
+    };
+    snap
+}
+
+/// Data for each of `utis` present on `pb`, skipping ones over
+/// `EXTRA_TYPE_MAX_BYTES`.
+#[cfg(target_os = "macos")]
+fn read_extra_types(pb: id, utis: &[String]) -> Vec<(String, Vec<u8>)> {
+    utis.iter()
+        .filter(|uti| type_data_len(pb, uti).is_some_and(|len| len <= EXTRA_TYPE_MAX_BYTES))
+        .filter_map(|uti| Some((uti.clone(), read_type_data(pb, uti)?)))
+        .collect()
+}
+
+#[cfg(target_os = "macos")]
+fn decode_native_image(data: &[u8], format: image::ImageFormat) -> Option<ImageData<'static>> {
+    let img = image::load_from_memory_with_format(data, format).ok()?.to_rgba8();
+    let (w, h) = img.dimensions();
+    Some(ImageData { width: w as usize, height: h as usize, bytes: std::borrow::Cow::Owned(img.into_raw()) })
+}
+
+/// Above this many bytes text and images are read straight from the
+/// pasteboard instead of through arboard, which gets unreliable with very
+/// large payloads.
+pub const NATIVE_READ_BYTES: usize = 8 * 1024 * 1024;
+/// Content larger than this is not captured at all; the poller reports it
+/// with a `capture_skipped` event instead.
+pub const MAX_CAPTURE_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeRoute {
+    Arboard,
+    Native,
+    Skip,
+}
+
+/// How pasteboard content of `len` bytes is read.
+pub fn size_route(len: usize) -> SizeRoute {
+    if len > MAX_CAPTURE_BYTES {
+        SizeRoute::Skip
+    } else if len > NATIVE_READ_BYTES {
+        SizeRoute::Native
+    } else {
+        SizeRoute::Arboard
+    }
+}
+
+/// Whether `len` bytes are over the `max_capture_bytes` setting; 0 leaves
+/// only `MAX_CAPTURE_BYTES`.
+pub fn over_capture_limit(len: usize, max_capture_bytes: u64) -> bool {
+    max_capture_bytes > 0 && len as u64 > max_capture_bytes
+}
+
+/// Custom types Chromium and Electron apps (VS Code, Slack, ...) put next
+/// to plain text to keep in-app pastes structured; the `extra_utis` default.
+pub const DEFAULT_EXTRA_UTIS: &[&str] = &["org.chromium.web-custom-data", "org.chromium.source-url"];
+
+/// Extra types larger than this are not kept.
+pub const EXTRA_TYPE_MAX_BYTES: usize = 4 * 1024 * 1024;
+
+/// Markers password managers put on a copy so clipboard tools leave it out
+/// of history (nspasteboard.org).
+pub const TRANSIENT_MARKER_UTIS: &[&str] = &["org.nspasteboard.ConcealedType", "org.nspasteboard.TransientType"];
+
+/// Whether `types`, the types of the items on the pasteboard, include a
+/// concealed or transient marker.
+pub fn is_transient<S: AsRef<str>>(types: &[S]) -> bool {
+    types.iter().any(|t| TRANSIENT_MARKER_UTIS.contains(&t.as_ref()))
+}
+
+/// Whether `source_app` is one of the `excluded_apps` bundle ids, ignoring
+/// case and surrounding whitespace. Unknown source apps are never excluded.
+pub fn is_excluded_app(excluded_apps: &[String], source_app: Option<&str>) -> bool {
+    let Some(app) = source_app.map(str::trim).filter(|a| !a.is_empty()) else { return false };
+    excluded_apps.iter().any(|e| e.trim().eq_ignore_ascii_case(app))
+}
+
+/// Whether any item on `pb` carries a concealed or transient marker.
+#[cfg(target_os = "macos")]
+fn has_transient_marker(pb: id) -> bool {
+    let mut types = Vec::new();
+    unsafe {
+        let items: id = msg_send![pb, pasteboardItems];
+        let count: u64 = if items == nil { 0 } else { msg_send![items, count] };
+        for i in 0..count {
+            let item: id = msg_send![items, objectAtIndex: i];
+            let item_types: id = msg_send![item, types];
+            let tcount: u64 = if item_types == nil { 0 } else { msg_send![item_types, count] };
+            for j in 0..tcount {
+                let ty: id = msg_send![item_types, objectAtIndex: j];
+                let cstr: *const std::os::raw::c_char = msg_send![ty, UTF8String];
+                if !cstr.is_null() {
+                    types.push(std::ffi::CStr::from_ptr(cstr).to_string_lossy().into_owned());
+                }
+            }
+        }
+    }
+    is_transient(&types)
+}
+
+/// Clipboard content seen but not captured.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct SkippedClip {
+    pub kind: String,
+    pub bytes: usize,
+}
+
+/// The stored item `snap` would dedup against, found by hashing it the way
+/// `store_snapshot` would, without encrypting or decrypting anything. For
+/// several files the first path is looked up. `None` for an empty snapshot
+/// or content not in history.
+pub fn find_matching(db: &Database, settings: &Settings, snap: &Snapshot) -> Result<Option<i64>> {
+    if let Some(p) = snap.file_paths.first() {
+        return db.find_by_hash_kind_path(&Database::compute_sha256(p.as_bytes()), "file", Some(p));
+    }
+    if let Some(text) = snap.text.as_deref().and_then(|t| captured_text(t, settings)) {
+        let sha = Database::compute_sha256(text.as_bytes());
+        if let Some(id) = db.find_by_hash_kind_path(&sha, detect::detect_text_kind(&text), None)? {
+            return Ok(Some(id));
+        }
+    }
+    match snap.image.as_ref() {
+        Some(img) => db.find_by_hash_kind_path(&Database::compute_sha256(&rgba_to_png(img)?), "image", None),
+        None => Ok(None),
+    }
+}
+
+/// All representations read from the pasteboard for a single change.
+#[derive(Default)]
+pub struct Snapshot {
+    pub file_paths: Vec<String>,
+    pub text: Option<String>,
+    pub image: Option<ImageData<'static>>,
+    pub rtf: Option<Vec<u8>>,
+    /// `public.html` that came with the text, e.g. from a browser.
+    pub html: Option<Vec<u8>>,
+    /// Bundle id of the app the content was copied from, if known.
+    pub source_app: Option<String>,
+    /// Representations too large to capture.
+    pub skipped: Vec<SkippedClip>,
+    /// Opaque (UTI, data) pairs from `extra_utis`, restored with the item.
+    pub extra_types: Vec<(String, Vec<u8>)>,
+}
+
+impl Snapshot {
+    /// Whether storing this needs the master key (file URLs do not).
+    pub fn needs_key(&self) -> bool {
+        self.file_paths.is_empty() && (self.text.is_some() || self.image.is_some())
+    }
+
+    /// Hash of the raw content, for telling repeated writes apart; `None`
+    /// for an empty snapshot. Cheaper than the stored hashes, nothing is
+    /// transformed or encoded.
+    pub fn fingerprint(&self) -> Option<Vec<u8>> {
+        let mut hasher = Sha256::new();
+        if !self.file_paths.is_empty() {
+            for p in &self.file_paths {
+                hasher.update(p.as_bytes());
+                hasher.update([0]);
+            }
+        } else if self.text.is_none() && self.image.is_none() {
+            return None;
+        }
+        if let Some(text) = &self.text {
+            hasher.update(b"text");
+            hasher.update(text.as_bytes());
+        }
+        if let Some(img) = &self.image {
+            hasher.update(b"image");
+            hasher.update((img.width as u64).to_le_bytes());
+            hasher.update(&img.bytes);
+        }
+        Some(hasher.finalize().to_vec())
+    }
+}
+
+/// `text` after the configured clean-ups, or `None` when nothing worth
+/// keeping is left: empty text, or only whitespace under
+/// `ignore_whitespace_only`.
+fn captured_text(text: &str, settings: &Settings) -> Option<String> {
+    let text = transform::apply_transforms(text, &settings.capture_transforms);
+    let ignorable = text.is_empty() || (settings.ignore_whitespace_only && text.trim().is_empty());
+    (!ignorable).then_some(text)
+}
+
+/// Stores a snapshot using a fixed priority: file URLs > text > image.
+///
+/// File URLs win outright (the text/image alongside them are just the name
+/// and icon Finder adds). Otherwise text and image are both kept when both
+/// are present, e.g. a rich email body with an inline picture: the image is
+/// stored as its own item first so that the text, the primary
+/// representation, ends up on top. That costs a second history row per such
+/// copy; with `keep_image_with_text` off the image is dropped instead.
+/// Extra types go with the text, or the image when there is no text.
+/// Content over `max_capture_bytes` is left out. Returns the ids written;
+/// nothing is stored for text/image while locked. Errors only when no
+/// representation could be stored.
+pub fn store_snapshot(db: &Database, crypto: &KeyManager, settings: &Settings, snap: Snapshot) -> Result<Vec<i64>> {
+    let _sealing = crypto.sealing();
+    let mut ids = Vec::new();
+
     // 1) File URLs
-    let file_paths = read_file_urls(pb);
-    if !file_paths.is_empty() {
-        for p in file_paths {
+    if !snap.file_paths.is_empty() {
+        for p in snap.file_paths {
             let path = PathBuf::from(&p);
             let size = std::fs::metadata(&path).map(|m| m.len() as i64).unwrap_or(0);
+            if over_capture_limit(size as usize, settings.max_capture_bytes) {
+                eprintln!("skipped a {size} byte file: over max_capture_bytes");
+                continue;
+            }
             let sha = Database::compute_sha256(p.as_bytes());
+            // while locked the path goes in as plaintext; `seal_file_paths` catches it up
+            let sealed = if settings.encrypt_file_paths && crypto.is_unlocked() {
+                Some(crypto.encrypt(p.as_bytes())?)
+            } else {
+                None
+            };
             let item = NewItem {
                 kind: "file".into(),
                 size,
                 sha256: sha,
-                file_path: Some(p),
+                file_path: if sealed.is_some() { None } else { Some(p) },
+                file_path_blob: sealed,
                 content_blob: None,
                 preview_blob: None,
                 rtf_blob: None,
+                html_blob: None,
+                source_app: snap.source_app.clone(),
+                uti: None,
             };
-            let _ = db.insert_item(item);
+            ids.push(db.insert_item(item)?);
         }
-        return Ok(());
+        return Ok(ids);
     }
 
-    // 2) RTF (optional)
-    let rtf_data = read_rtf_data(pb);
+    if !crypto.is_unlocked() {
+        return Ok(ids);
+    }
 
-    // 3) Text & Image via arboard
-    let mut cb = Clipboard::new().ok();
-    let mut captured = false;
-    if let Some(ref mut c) = cb {
-        if let Ok(text) = c.get_text() {
-            captured = true;
-            if crypto.is_unlocked() {
-                let enc = crypto.encrypt(text.as_bytes())?;
-                let sha = Database::compute_sha256(text.as_bytes());
-                let item = NewItem {
-                    kind: "text".into(),
-                    size: text.len() as i64,
-                    sha256: sha,
-                    file_path: None,
-                    content_blob: Some(enc),
-                    preview_blob: None,
-                    rtf_blob: rtf_data.as_ref().and_then(|d| crypto.encrypt(d).ok()),
-                };
-                let _ = db.insert_item(item);
-                return Ok(());
+    // 2) Image, kept next to text unless `keep_image_with_text` is off,
+    // then 3) text. A failure in
+    // one representation is logged and must not cost us the other.
+    let mut first_err = None;
+    let text = snap.text.as_deref().and_then(|t| captured_text(t, settings)).filter(|t| {
+        let over = over_capture_limit(t.len(), settings.max_capture_bytes);
+        if over {
+            eprintln!("skipped {} bytes of text: over max_capture_bytes", t.len());
+        }
+        !over
+    });
+    let image = snap.image.as_ref().filter(|_| text.is_none() || settings.keep_image_with_text);
+    if let Some(img) = image {
+        match store_image(db, crypto, settings, img, snap.source_app.clone()) {
+            Ok(Some(id)) => ids.push(id),
+            Ok(None) => {}
+            Err(e) => {
+                eprintln!("image capture failed: {e:?}");
+                first_err.get_or_insert(e);
             }
         }
-        if let Ok(img) = c.get_image() {
-            captured = true;
-            let png = rgba_to_png(&img)?;
-            if crypto.is_unlocked() {
-                let enc = crypto.encrypt(&png)?;
-                let sha = Database::compute_sha256(&png);
-                let item = NewItem {
-                    kind: "image".into(),
-                    size: png.len() as i64,
-                    sha256: sha,
-                    file_path: None,
-                    content_blob: Some(enc),
-                    preview_blob: None, // lazy thumbnails in UI
-                    rtf_blob: None,
-                };
-                let _ = db.insert_item(item);
-                return Ok(());
+    }
+    if let Some(text) = text.as_deref() {
+        match store_text(db, crypto, text, snap.rtf.as_deref(), snap.html.as_deref(), snap.source_app.clone()) {
+            Ok(id) => {
+                if settings.enable_search_index {
+                    // a dedup hit may be an item marked sensitive, which stays out
+                    let indexed = db.get_item(id).and_then(|it| if it.is_sensitive { Ok(()) } else { db.index_text(id, text) });
+                    if let Err(e) = indexed {
+                        eprintln!("search index update failed: {e:?}");
+                    }
+                }
+                ids.push(id)
             }
+            Err(e) => {
+                eprintln!("text capture failed: {e:?}");
+                first_err.get_or_insert(e);
+            }
+        }
+    }
+    if let (Some(&primary), false) = (ids.last(), snap.extra_types.is_empty()) {
+        if let Err(e) = store_extra_types(db, crypto, primary, &snap.extra_types) {
+            eprintln!("extra types capture failed: {e:?}");
         }
     }
+    match first_err {
+        Some(e) if ids.is_empty() => Err(e),
+        _ => Ok(ids),
+    }
+}
+
+fn store_extra_types(db: &Database, crypto: &KeyManager, id: i64, types: &[(String, Vec<u8>)]) -> Result<()> {
+    let sealed = types
+        .iter()
+        .map(|(uti, data)| Ok((uti.clone(), crypto.encrypt(data)?)))
+        .collect::<Result<Vec<_>>>()?;
+    db.set_extra_types(id, &sealed)
+}
+
+/// Decrypted extra pasteboard types stored with item `id`.
+pub fn extra_types(db: &Database, crypto: &KeyManager, id: i64) -> Result<Vec<(String, Vec<u8>)>> {
+    db.get_extra_types(id)?
+        .into_iter()
+        .map(|(uti, blob)| Ok((uti, crypto.decrypt(&blob)?)))
+        .collect()
+}
 
-    if !captured {
-        // Unknown types ignored
+/// Types `copy_back` adds to the first pasteboard item next to the primary
+/// data: the captured `public.html`, then any extra types.
+pub fn restored_types(db: &Database, crypto: &KeyManager, id: i64) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut types = Vec::new();
+    if let Some(blob) = db.get_html_blob(id)? {
+        types.push(("public.html".to_string(), crypto.decrypt(&blob)?));
     }
-    Ok(())
+    types.extend(extra_types(db, crypto, id)?);
+    Ok(types)
+}
+
+// `None` when the PNG is over `max_capture_bytes`.
+fn store_image(db: &Database, crypto: &KeyManager, settings: &Settings, img: &ImageData, source_app: Option<String>) -> Result<Option<i64>> {
+    let png = rgba_to_png(img)?;
+    if over_capture_limit(png.len(), settings.max_capture_bytes) {
+        eprintln!("skipped {} bytes of image: over max_capture_bytes", png.len());
+        return Ok(None);
+    }
+    let enc = crypto.encrypt(&png)?;
+    let sha = Database::compute_sha256(&png);
+    // Failing to thumbnail is not fatal; previews fall back to lazy generation.
+    let thumb = make_thumbnail(&png, settings.thumbnail_max, settings.thumbnail_format)
+        .ok()
+        .and_then(|t| crypto.encrypt(&t).ok());
+    db.insert_item(NewItem {
+        kind: "image".into(),
+        size: png.len() as i64,
+        sha256: sha,
+        file_path: None,
+        file_path_blob: None,
+        content_blob: Some(enc),
+        preview_blob: thumb,
+        rtf_blob: None,
+        html_blob: None,
+        source_app,
+        uti: None,
+    })
+    .map(Some)
+}
+
+// Text with its RTF attached, the primary representation.
+fn store_text(
+    db: &Database,
+    crypto: &KeyManager,
+    text: &str,
+    rtf: Option<&[u8]>,
+    html: Option<&[u8]>,
+    source_app: Option<String>,
+) -> Result<i64> {
+    let enc = crypto.encrypt(text.as_bytes())?;
+    let sha = Database::compute_sha256(text.as_bytes());
+    db.insert_item(NewItem {
+        kind: detect::detect_text_kind(text).into(),
+        size: text.len() as i64,
+        sha256: sha,
+        file_path: None,
+        file_path_blob: None,
+        content_blob: Some(enc),
+        preview_blob: None,
+        rtf_blob: rtf.and_then(|d| crypto.encrypt(d).ok()),
+        html_blob: html.and_then(|d| crypto.encrypt(d).ok()),
+        source_app,
+        uti: None,
+    })
 }
 
 #[cfg(target_os = "macos")]
@@ -164,29 +657,473 @@ fn read_file_urls(pb: id) -> Vec<String> {
 
 #[cfg(target_os = "macos")]
 fn read_rtf_data(pb: id) -> Option<Vec<u8>> {
+    read_type_data(pb, "public.rtf")
+}
+
+#[cfg(target_os = "macos")]
+fn read_html_data(pb: id) -> Option<Vec<u8>> {
+    read_type_data(pb, "public.html")
+}
+
+/// The first pasteboard item's data for `uti`.
+#[cfg(target_os = "macos")]
+fn read_type_data(pb: id, uti: &str) -> Option<Vec<u8>> {
     unsafe {
-        let items: id = msg_send![pb, pasteboardItems];
-        if items == nil {
+        let data = type_data(pb, uti)?;
+        let len: u64 = msg_send![data, length];
+        let bytes: *const u8 = msg_send![data, bytes];
+        if bytes.is_null() {
             return None;
         }
-        let count: u64 = msg_send![items, count];
-        if count == 0 {
-            return None;
+        Some(std::slice::from_raw_parts(bytes, len as usize).to_vec())
+    }
+}
+
+/// Size of `read_type_data(pb, uti)` from the NSData's `length`, without
+/// copying the bytes. The NSData is released before returning, so a huge
+/// clip is not held in memory while deciding whether to read it.
+#[cfg(target_os = "macos")]
+fn type_data_len(pb: id, uti: &str) -> Option<usize> {
+    unsafe {
+        let pool: id = msg_send![class!(NSAutoreleasePool), new];
+        let len = type_data(pb, uti).map(|data| {
+            let len: u64 = msg_send![data, length];
+            len as usize
+        });
+        let _: () = msg_send![pool, drain];
+        len
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn type_data(pb: id, uti: &str) -> Option<id> {
+    let items: id = msg_send![pb, pasteboardItems];
+    if items == nil {
+        return None;
+    }
+    let count: u64 = msg_send![items, count];
+    let ty: id = NSString::alloc(nil).init_str(uti);
+    for i in 0..count {
+        let item: id = msg_send![items, objectAtIndex: i];
+        let data: id = msg_send![item, dataForType: ty];
+        if data != nil {
+            let len: u64 = msg_send![data, length];
+            if len > 0 {
+                return Some(data);
+            }
         }
-        let ty: id = NSString::alloc(nil).init_str("public.rtf");
-        for i in 0..count {
-            let item: id = msg_send![items, objectAtIndex: i];
+    }
+    None
+}
+
+/// One pasteboard representation seen by `diagnose_copy`.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PasteboardEntry {
+    pub uti: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CopyDiagnosis {
+    pub id: i64,
+    pub kind: String,
+    pub entries: Vec<PasteboardEntry>,
+}
+
+/// Support tool: runs `copy_back` for `id`, records every UTI that landed
+/// on the pasteboard with its size, then writes the previous pasteboard
+/// contents back. Callers wrap it in `SelfWrites::around` so the poller
+/// skips both changes.
+#[cfg(target_os = "macos")]
+pub fn diagnose_copy(db: &Database, crypto: &KeyManager, id: i64) -> Result<CopyDiagnosis> {
+    let (kind, ..) = db.get_item_raw(id)?;
+    unsafe {
+        let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+        let saved = read_pasteboard_items(pb);
+        let copied = copy_back(db, crypto, id);
+        let entries = read_pasteboard_items(pb)
+            .into_iter()
+            .flatten()
+            .map(|(uti, data)| PasteboardEntry { uti, bytes: data.len() as u64 })
+            .collect();
+        write_pasteboard_items(pb, &saved);
+        copied?;
+        Ok(CopyDiagnosis { id, kind, entries })
+    }
+}
+
+// Every (UTI, bytes) pair of every pasteboard item.
+#[cfg(target_os = "macos")]
+unsafe fn read_pasteboard_items(pb: id) -> Vec<Vec<(String, Vec<u8>)>> {
+    let mut out = Vec::new();
+    let items: id = msg_send![pb, pasteboardItems];
+    if items == nil {
+        return out;
+    }
+    let count: u64 = msg_send![items, count];
+    for i in 0..count {
+        let item: id = msg_send![items, objectAtIndex: i];
+        let types: id = msg_send![item, types];
+        let tcount: u64 = if types == nil { 0 } else { msg_send![types, count] };
+        let mut reps = Vec::new();
+        for j in 0..tcount {
+            let ty: id = msg_send![types, objectAtIndex: j];
+            let cstr: *const std::os::raw::c_char = msg_send![ty, UTF8String];
+            if cstr.is_null() { continue; }
+            let uti = std::ffi::CStr::from_ptr(cstr).to_string_lossy().into_owned();
             let data: id = msg_send![item, dataForType: ty];
+            let mut bytes = Vec::new();
             if data != nil {
                 let len: u64 = msg_send![data, length];
-                let bytes: *const u8 = msg_send![data, bytes];
-                if !bytes.is_null() && len > 0 {
-                    let slice = std::slice::from_raw_parts(bytes, len as usize);
-                    return Some(slice.to_vec());
+                let ptr: *const u8 = msg_send![data, bytes];
+                if !ptr.is_null() && len > 0 {
+                    bytes = std::slice::from_raw_parts(ptr, len as usize).to_vec();
+                }
+            }
+            reps.push((uti, bytes));
+        }
+        out.push(reps);
+    }
+    out
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn write_pasteboard_items(pb: id, items: &[Vec<(String, Vec<u8>)>]) {
+    let _: () = msg_send![pb, clearContents];
+    if items.is_empty() {
+        return;
+    }
+    let arr: id = msg_send![class!(NSMutableArray), array];
+    for reps in items {
+        let item: id = msg_send![class!(NSPasteboardItem), new];
+        for (uti, data) in reps {
+            let nsdata: id = msg_send![class!(NSData), dataWithBytes: data.as_ptr() as *const _ length: data.len() as u64];
+            let ty: id = NSString::alloc(nil).init_str(uti);
+            let _: bool = msg_send![item, setData: nsdata forType: ty];
+        }
+        let _: () = msg_send![arr, addObject: item];
+    }
+    let _: bool = msg_send![pb, writeObjects: arr];
+}
+
+/// What to do with text/image copied while the store is locked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockedCaptureBehavior {
+    /// Discard it silently.
+    #[default]
+    Drop,
+    /// Keep it in memory (never on disk) and store it on the next unlock.
+    Buffer,
+    /// Discard it but count it so the UI can say what was missed.
+    Notify,
+}
+
+/// Pasteboard changes Cliper makes itself (copy back, wrapped copies,
+/// diagnostics), kept as `changeCount` ranges so the poller skips them
+/// whichever command wrote. Every programmatic write goes through `around`.
+#[derive(Default)]
+pub struct SelfWrites {
+    // (count before, count after); `None` while the write is in flight, so
+    // a poll in the middle of a multi-step write is skipped too.
+    ranges: parking_lot::Mutex<Vec<(usize, i64, Option<i64>)>>,
+    next_token: AtomicUsize,
+}
+
+/// Most finished self-write ranges remembered.
+const SELF_WRITES_MAX: usize = 16;
+
+impl SelfWrites {
+    /// Runs `write`, marking every pasteboard change it causes, as seen
+    /// through `change_count`, as Cliper's own.
+    pub fn around<T>(&self, change_count: impl Fn() -> i64, write: impl FnOnce() -> Result<T>) -> Result<T> {
+        let token = self.next_token.fetch_add(1, Ordering::Relaxed);
+        let before = change_count();
+        self.ranges.lock().push((token, before, None));
+        let out = write();
+        let after = change_count();
+        let mut ranges = self.ranges.lock();
+        ranges.retain(|r| r.0 != token);
+        if after > before {
+            ranges.push((token, before, Some(after)));
+            let finished = ranges.iter().filter(|r| r.2.is_some()).count();
+            if finished > SELF_WRITES_MAX {
+                if let Some(oldest) = ranges.iter().position(|r| r.2.is_some()) {
+                    ranges.remove(oldest);
+                }
+            }
+        }
+        out
+    }
+
+    /// Whether the pasteboard at `count` is the result of our own write.
+    /// Ranges the pasteboard has moved past are forgotten.
+    pub fn is_own(&self, count: i64) -> bool {
+        let mut ranges = self.ranges.lock();
+        ranges.retain(|r| !matches!(r.2, Some(after) if after < count));
+        ranges.iter().any(|r| count > r.1 && !matches!(r.2, Some(after) if count > after))
+    }
+}
+
+/// Current `changeCount` of the general pasteboard; 0 where there is none.
+pub fn change_count() -> i64 {
+    #[cfg(target_os = "macos")]
+    unsafe {
+        let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+        let count: cocoa::foundation::NSUInteger = msg_send![pb, changeCount];
+        return count as i64;
+    }
+    #[allow(unreachable_code)]
+    0
+}
+
+/// Catches apps that write the same content to the pasteboard over and
+/// over: past `max_repeats` arrivals of one fingerprint within `window`,
+/// further arrivals are ignored until it quiets down.
+#[derive(Default)]
+pub struct StormGuard {
+    // arrival times per fingerprint, oldest first
+    seen: parking_lot::Mutex<HashMap<Vec<u8>, VecDeque<Instant>>>,
+}
+
+impl StormGuard {
+    /// Records an arrival of `fingerprint` at `now`; true when it should be
+    /// suppressed. A `max_repeats` of 0 turns the guard off.
+    pub fn observe(&self, fingerprint: &[u8], now: Instant, max_repeats: u32, window: Duration) -> bool {
+        if max_repeats == 0 {
+            return false;
+        }
+        let mut seen = self.seen.lock();
+        seen.retain(|_, times| {
+            while times.front().is_some_and(|t| now.duration_since(*t) > window) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+        let times = seen.entry(fingerprint.to_vec()).or_default();
+        times.push_back(now);
+        let storm = times.len() > max_repeats as usize;
+        if times.len() == max_repeats as usize + 1 {
+            eprintln!("clipboard storm: same content {} times within {window:?}, ignoring repeats", times.len());
+        }
+        storm
+    }
+}
+
+/// Fingerprint of the last stored capture, kept in a small file next to
+/// the database. The poll loop treats the pasteboard as changed on its
+/// first pass after launch; this lets that pass skip content the previous
+/// run already stored.
+#[derive(Default)]
+pub struct LastCapture {
+    // None keeps the fingerprint in memory only
+    path: Option<PathBuf>,
+    // from the previous run, until the first capture is checked
+    previous: parking_lot::Mutex<Option<Vec<u8>>>,
+}
+
+impl LastCapture {
+    /// Reads the fingerprint the previous run left at `path`; a missing or
+    /// unreadable file just means nothing is skipped.
+    pub fn load(path: PathBuf) -> Self {
+        let previous = std::fs::read(&path).ok().filter(|fp| !fp.is_empty());
+        Self { path: Some(path), previous: parking_lot::Mutex::new(previous) }
+    }
+
+    /// True when `fingerprint` is the first capture since launch and
+    /// matches the last one stored before it. Only the first call checks;
+    /// copying the same thing again later is a real capture.
+    pub fn is_leftover(&self, fingerprint: &[u8]) -> bool {
+        self.previous.lock().take().is_some_and(|prev| prev == fingerprint)
+    }
+
+    /// Remembers `fingerprint` as the last stored capture.
+    pub fn record(&self, fingerprint: &[u8]) {
+        self.previous.lock().take();
+        let Some(path) = &self.path else { return };
+        if let Err(e) = std::fs::write(path, fingerprint) {
+            eprintln!("failed to save last capture hash: {e}");
+        }
+    }
+}
+
+/// Holds a pasteboard change back until the pasteboard has stayed the
+/// same for `require_stable_ms`, so apps that copy every selection as it
+/// changes only leave the final one behind.
+#[derive(Default)]
+pub struct Settle {
+    // change count and when it was seen
+    pending: Option<(i64, Instant)>,
+}
+
+impl Settle {
+    /// Notes a change to `count` at `now`; a newer change restarts the wait.
+    pub fn changed(&mut self, count: i64, now: Instant) {
+        self.pending = Some((count, now));
+    }
+
+    /// The pending change count once nothing has changed for `stable`, at
+    /// most once per change. A zero `stable` returns it straight away.
+    pub fn take_settled(&mut self, now: Instant, stable: Duration) -> Option<i64> {
+        match self.pending {
+            Some((count, at)) if now.duration_since(at) >= stable => {
+                self.pending = None;
+                Some(count)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Most snapshots held in memory while locked; the oldest go first.
+pub const LOCKED_BUFFER_MAX: usize = 20;
+
+/// Captures seen while locked, per `LockedCaptureBehavior`, or during a
+/// key rotation.
+#[derive(Default)]
+pub struct LockedCaptures {
+    pending: parking_lot::Mutex<Vec<Snapshot>>,
+    missed: AtomicUsize,
+}
+
+impl LockedCaptures {
+    /// Applies `behavior` to a snapshot that could not be stored. Returns
+    /// the missed count so far for `Notify`, `None` otherwise.
+    pub fn hold(&self, snap: Snapshot, behavior: LockedCaptureBehavior) -> Option<usize> {
+        match behavior {
+            LockedCaptureBehavior::Drop => None,
+            LockedCaptureBehavior::Buffer => {
+                self.defer(snap);
+                None
+            }
+            LockedCaptureBehavior::Notify => Some(self.missed.fetch_add(1, Ordering::Relaxed) + 1),
+        }
+    }
+
+    /// Buffers a snapshot to store with the next `flush`, whatever the
+    /// locked behavior; used while a key rotation keeps captures out.
+    pub fn defer(&self, snap: Snapshot) {
+        let mut pending = self.pending.lock();
+        if pending.len() >= LOCKED_BUFFER_MAX {
+            pending.remove(0);
+        }
+        pending.push(snap);
+    }
+
+    pub fn missed(&self) -> usize {
+        self.missed.load(Ordering::Relaxed)
+    }
+
+    /// Drops buffered snapshots without storing them.
+    pub fn discard(&self) {
+        self.pending.lock().clear();
+        self.missed.store(0, Ordering::Relaxed);
+    }
+
+    /// Stores buffered snapshots in capture order and resets the missed
+    /// count; call right after unlocking or a key rotation. Each snapshot is
+    /// stored on its own, so one that fails is logged and dropped without
+    /// costing the ones after it.
+    pub fn flush(&self, db: &Database, crypto: &KeyManager, settings: &Settings) -> FlushReport {
+        self.missed.store(0, Ordering::Relaxed);
+        let mut report = FlushReport::default();
+        if !crypto.is_unlocked() {
+            return report;
+        }
+        let pending = std::mem::take(&mut *self.pending.lock());
+        for snap in pending {
+            match store_snapshot(db, crypto, settings, snap) {
+                Ok(ids) => report.ids.extend(ids),
+                Err(e) => {
+                    eprintln!("storing a held capture failed: {e:?}");
+                    report.errors.push(e);
                 }
             }
         }
-        None
+        report
+    }
+}
+
+/// What `LockedCaptures::flush` stored, and why any held snapshot was not.
+#[derive(Debug, Default)]
+pub struct FlushReport {
+    pub ids: Vec<i64>,
+    pub errors: Vec<anyhow::Error>,
+}
+
+/// Encoding used for cached image thumbnails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailFormat {
+    /// Lossless, crisp for screenshots and UI captures.
+    Png,
+    /// Smaller for photos; alpha is dropped.
+    Jpeg,
+}
+
+impl ThumbnailFormat {
+    pub fn mime(self) -> &'static str {
+        match self {
+            ThumbnailFormat::Png => "image/png",
+            ThumbnailFormat::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+/// Downscales encoded image bytes so the longest side is at most `max`
+/// (never upscaling) and re-encodes them in `format`.
+pub fn make_thumbnail(bytes: &[u8], max: u32, format: ThumbnailFormat) -> Result<Vec<u8>> {
+    thumbnail_of(image::load_from_memory(bytes)?, max, format)
+}
+
+fn thumbnail_of(img: image::DynamicImage, max: u32, format: ThumbnailFormat) -> Result<Vec<u8>> {
+    let (w, h) = img.dimensions();
+    let scale = (max as f32 / w.max(h) as f32).min(1.0);
+    let new_w = ((w as f32 * scale).round() as u32).max(1);
+    let new_h = ((h as f32 * scale).round() as u32).max(1);
+    let resized = if scale < 1.0 { img.thumbnail(new_w, new_h) } else { img };
+    let mut out = Vec::new();
+    match format {
+        ThumbnailFormat::Png => resized.write_to(&mut std::io::Cursor::new(&mut out), ImageOutputFormat::Png)?,
+        ThumbnailFormat::Jpeg => image::DynamicImage::ImageRgb8(resized.to_rgb8())
+            .write_to(&mut std::io::Cursor::new(&mut out), ImageOutputFormat::Jpeg(85))?,
+    }
+    Ok(out)
+}
+
+/// An image preview as sent to the webview.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ImagePreview {
+    pub data_url: String,
+    /// Re-encoded smaller or as JPEG to fit the data URL budget.
+    pub downsized: bool,
+}
+
+/// Smallest side `preview_data_url` shrinks to; the result is returned
+/// even if it still exceeds the budget.
+const MIN_DOWNSIZED_SIDE: u32 = 16;
+
+fn data_url(bytes: &[u8], format: ThumbnailFormat) -> String {
+    use base64::Engine;
+    format!("data:{};base64,{}", format.mime(), base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// Wraps an encoded thumbnail in a data URL of at most `max_bytes` (0 = no
+/// limit). Oversized ones are re-encoded as JPEG, then halved until they
+/// fit, so one huge preview cannot bloat an IPC message.
+pub fn preview_data_url(thumb: &[u8], format: ThumbnailFormat, max_bytes: usize) -> Result<ImagePreview> {
+    let url = data_url(thumb, format);
+    if max_bytes == 0 || url.len() <= max_bytes {
+        return Ok(ImagePreview { data_url: url, downsized: false });
+    }
+    let (w, h) = image::load_from_memory(thumb)?.dimensions();
+    let mut side = w.max(h);
+    loop {
+        let url = data_url(&make_thumbnail(thumb, side, ThumbnailFormat::Jpeg)?, ThumbnailFormat::Jpeg);
+        if url.len() <= max_bytes || side <= MIN_DOWNSIZED_SIDE {
+            return Ok(ImagePreview { data_url: url, downsized: true });
+        }
+        side = (side / 2).max(MIN_DOWNSIZED_SIDE);
     }
 }
 
@@ -200,10 +1137,382 @@ fn rgba_to_png(img: &ImageData) -> Result<Vec<u8>> {
     Ok(out)
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SavedItem {
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// Writes an item's content to `dest`: text as UTF-8, images as PNG, files
+/// as a copy of the original. The data goes to a temp file next to `dest`
+/// that is synced and renamed into place, so `dest` is never left partial.
+pub fn save_item_to(db: &Database, crypto: &KeyManager, id: i64, dest: &Path) -> Result<SavedItem> {
+    let (kind, content_blob, _, _, file_path) = db.get_item_raw(id)?;
+    let file_path = resolve_file_path(db, crypto, id, file_path)?;
+    let data = match kind.as_str() {
+        k if is_text_kind(k) => {
+            let ct = content_blob.ok_or_else(|| anyhow!("no content"))?;
+            crypto.decrypt(&ct)?
+        }
+        "image" => crypto.decrypt(&content_blob.ok_or(CopyError::OriginalNotStored)?)?,
+        "file" => match content_blob {
+            Some(ct) => crypto.decrypt(&ct)?,
+            None => std::fs::read(file_path.ok_or_else(|| anyhow!("no file path"))?)?,
+        },
+        other => return Err(anyhow!("unsupported kind: {other}")),
+    };
+    let bytes = write_synced(dest, &data)?;
+    Ok(SavedItem { path: dest.to_string_lossy().into_owned(), bytes })
+}
+
+/// Writes `data` to a temp file next to `dest`, syncs it and renames it
+/// into place, so `dest` is never left partial. Returns the bytes written.
+fn write_synced(dest: &Path, data: &[u8]) -> Result<u64> {
+    let tmp = dest.with_extension("cliper-tmp");
+    {
+        let mut f = std::fs::File::create(&tmp)?;
+        f.write_all(data)?;
+        f.sync_all()?;
+    }
+    if let Err(e) = std::fs::rename(&tmp, dest) {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    let bytes = std::fs::metadata(dest)?.len();
+    if bytes != data.len() as u64 {
+        return Err(anyhow!("short write: {bytes} of {} bytes", data.len()));
+    }
+    Ok(bytes)
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DumpedClipboard {
+    /// "files", "text" or "image".
+    pub kind: String,
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// Writes what is in `snap` to `dest` without touching history, picking
+/// the representation the way `store_snapshot` does: file URLs as a list
+/// of paths, one per line, else text as UTF-8, else the image as PNG.
+pub fn dump_snapshot(snap: &Snapshot, dest: &Path) -> Result<DumpedClipboard> {
+    let (kind, data) = if !snap.file_paths.is_empty() {
+        ("files", (snap.file_paths.join("\n") + "\n").into_bytes())
+    } else if let Some(text) = &snap.text {
+        ("text", text.as_bytes().to_vec())
+    } else if let Some(img) = &snap.image {
+        ("image", rgba_to_png(img)?)
+    } else {
+        return Err(anyhow!("clipboard is empty"));
+    };
+    let bytes = write_synced(dest, &data)?;
+    Ok(DumpedClipboard { kind: kind.into(), path: dest.to_string_lossy().into_owned(), bytes })
+}
+
+/// Saves the item to `dest` and deletes it from history only once the write
+/// has been verified; a failed save leaves the item untouched.
+pub fn detach_item(db: &Database, crypto: &KeyManager, id: i64, dest: &Path) -> Result<SavedItem> {
+    let saved = save_item_to(db, crypto, id, dest)?;
+    db.delete_item(id)?;
+    remove_restored_file(id);
+    Ok(saved)
+}
+
+/// Largest file `materialize_file` will pull into the database.
+pub const MAX_MATERIALIZE_BYTES: u64 = 50 * 1024 * 1024;
+
+/// Copies a file item's current bytes into its (encrypted) `content_blob`,
+/// so the item keeps working after the original is moved or deleted. The
+/// item stays a "file"; stored content takes precedence over the path.
+/// Returns the number of bytes stored.
+pub fn materialize_file(db: &Database, crypto: &KeyManager, id: i64) -> Result<u64> {
+    if !crypto.is_unlocked() {
+        return Err(CryptoError::Locked.into());
+    }
+    let (kind, _, _, _, file_path) = db.get_item_raw(id)?;
+    if kind != "file" {
+        return Err(anyhow!("not a file item"));
+    }
+    let path = resolve_file_path(db, crypto, id, file_path)?.ok_or_else(|| anyhow!("no file path"))?;
+    let meta = std::fs::metadata(&path)?;
+    if !meta.is_file() {
+        return Err(anyhow!("only regular files can be stored"));
+    }
+    if meta.len() > MAX_MATERIALIZE_BYTES {
+        return Err(anyhow!("file is larger than {} MB", MAX_MATERIALIZE_BYTES / (1024 * 1024)));
+    }
+    let data = std::fs::read(&path)?;
+    let _sealing = crypto.sealing();
+    let enc = crypto.encrypt(&data)?;
+    db.set_file_content(id, &enc, data.len() as i64)?;
+    Ok(data.len() as u64)
+}
+
+/// Identity of the file behind a path: device and inode where there are
+/// inodes, else the canonical path. `None` for missing files.
+fn file_identity(path: &str) -> Option<String> {
+    let canonical = std::fs::canonicalize(path).ok()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let meta = std::fs::metadata(&canonical).ok()?;
+        return Some(format!("{}:{}", meta.dev(), meta.ino()));
+    }
+    #[allow(unreachable_code)]
+    Some(canonical.to_string_lossy().into_owned())
+}
+
+/// Merges file items whose paths differ as strings but lead to the same
+/// file (symlinks, `/private/tmp` vs `/tmp`, hard links). The earliest item
+/// of each group is kept and takes over a pin, a note and stored content
+/// from the ones removed. Missing files, and sealed paths while locked, are
+/// skipped. Returns how many items were merged away.
+pub fn dedup_files_by_inode(db: &Database, crypto: &KeyManager) -> Result<usize> {
+    // moves notes and content between items
+    let _sealing = crypto.sealing();
+    let mut groups: std::collections::BTreeMap<String, Vec<crate::db::ItemDto>> = Default::default();
+    for it in db.list_files()? {
+        let Ok(Some(path)) = resolve_file_path(db, crypto, it.id, it.file_path.clone()) else { continue };
+        if let Some(key) = file_identity(&path) {
+            groups.entry(key).or_default().push(it);
+        }
+    }
+    let mut merged = 0;
+    for mut items in groups.into_values().filter(|g| g.len() > 1) {
+        items.sort_by_key(|it| (it.created_at, it.id));
+        let keeper = items.remove(0);
+        let mut has_note = keeper.note_blob.is_some();
+        let mut has_content = keeper.stored_len > 0;
+        for dup in items {
+            if dup.is_pinned {
+                db.pin_item(keeper.id, true)?;
+            }
+            if !has_note {
+                if let Some(note) = dup.note_blob.as_deref() {
+                    db.set_note(keeper.id, Some(note))?;
+                    has_note = true;
+                }
+            }
+            if !has_content && dup.stored_len > 0 {
+                if let (_, Some(blob), ..) = db.get_item_raw(dup.id)? {
+                    db.set_file_content(keeper.id, &blob, dup.plaintext_len)?;
+                    has_content = true;
+                }
+            }
+            db.delete_item(dup.id)?;
+            remove_restored_file(dup.id);
+            merged += 1;
+        }
+    }
+    Ok(merged)
+}
+
+/// Plaintext path of a file item whose `file_path` column held `plain`:
+/// that value, or else its sealed path decrypted (which needs the key).
+pub fn resolve_file_path(db: &Database, crypto: &KeyManager, id: i64, plain: Option<String>) -> Result<Option<String>> {
+    if plain.is_some() {
+        return Ok(plain);
+    }
+    match db.get_file_path_blob(id)? {
+        Some(blob) => Ok(Some(open_file_path(crypto, &blob)?)),
+        None => Ok(None),
+    }
+}
+
+/// Decrypts a sealed file path.
+pub fn open_file_path(crypto: &KeyManager, blob: &[u8]) -> Result<String> {
+    Ok(String::from_utf8(crypto.decrypt(blob)?)?)
+}
+
+/// Encrypts every file path still stored in plaintext, e.g. after turning
+/// on `encrypt_file_paths` or for files copied while locked. Returns how
+/// many were sealed.
+pub fn seal_file_paths(db: &Database, crypto: &KeyManager) -> Result<usize> {
+    if !crypto.is_unlocked() {
+        return Err(CryptoError::Locked.into());
+    }
+    let _sealing = crypto.sealing();
+    let pending = db.plaintext_file_paths()?;
+    for (id, path) in &pending {
+        db.seal_file_path(*id, &crypto.encrypt(path.as_bytes())?)?;
+    }
+    Ok(pending.len())
+}
+
+/// Writes a materialized file's content to a per-item temp location under
+/// its original name, for pasting as a file. Returns the written path. The
+/// copy is plaintext, so it is readable by the user only and removed with
+/// the item, on lock and at launch.
+pub fn restore_file(crypto: &KeyManager, id: i64, file_path: &str, content_blob: &[u8]) -> Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+
+    let name = Path::new(file_path).file_name().ok_or_else(|| anyhow!("no file name"))?;
+    let plain = crypto.decrypt(content_blob)?;
+    let dir = restored_files_dir().join(id.to_string());
+    std::fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    let dest = dir.join(name);
+    let mut file = std::fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(&dest)?;
+    // `mode` only applies on create; a copy left by an older version may be wider
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(&plain)?;
+    Ok(dest)
+}
+
+/// Where `restore_file` puts decrypted copies, one directory per item.
+pub fn restored_files_dir() -> PathBuf {
+    std::env::temp_dir().join("cliper-files")
+}
+
+/// Removes the decrypted copy `restore_file` made for item `id`, if any.
+pub fn remove_restored_file(id: i64) {
+    let _ = std::fs::remove_dir_all(restored_files_dir().join(id.to_string()));
+}
+
+/// Removes every decrypted copy; run on lock, at launch and by a wipe.
+pub fn clear_restored_files() {
+    let _ = std::fs::remove_dir_all(restored_files_dir());
+}
+
+/// Decrypted text of `id` with `prefix` and `suffix` around it, for the
+/// "copy wrapped" action. Text kinds only.
+pub fn wrapped_text(db: &Database, crypto: &KeyManager, id: i64, prefix: &str, suffix: &str) -> Result<String> {
+    if !crypto.is_unlocked() {
+        return Err(CryptoError::Locked.into());
+    }
+    let (kind, content_blob, ..) = db.get_item_raw(id)?;
+    if !is_text_kind(&kind) {
+        return Err(anyhow!("only text items can be copied wrapped"));
+    }
+    let pt = crypto.decrypt(&content_blob.ok_or_else(|| anyhow!("no content"))?)?;
+    Ok(format!("{prefix}{}{suffix}", String::from_utf8_lossy(&pt)))
+}
+
+/// Puts `wrapped_text` on the clipboard as plain text and returns its length
+/// in bytes.
+pub fn copy_wrapped(db: &Database, crypto: &KeyManager, id: i64, prefix: &str, suffix: &str) -> Result<usize> {
+    let text = wrapped_text(db, crypto, id, prefix, suffix)?;
+    let len = text.len();
+    Clipboard::new()?.set_text(text)?;
+    Ok(len)
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct JoinedCopy {
+    /// Text items joined; others in the selection are skipped.
+    pub count: usize,
+    /// Length of the joined text in bytes.
+    pub len: usize,
+}
+
+/// Decrypted text of the text items among `ids`, in the given order, with
+/// `separator` between them. Images, files and unknown ids are skipped.
+pub fn joined_text(db: &Database, crypto: &KeyManager, ids: &[i64], separator: &str) -> Result<(String, usize)> {
+    if !crypto.is_unlocked() {
+        return Err(CryptoError::Locked.into());
+    }
+    let mut parts = Vec::with_capacity(ids.len());
+    for &id in ids {
+        let Ok((kind, Some(ct), ..)) = db.get_item_raw(id) else { continue };
+        if is_text_kind(&kind) {
+            parts.push(String::from_utf8_lossy(&crypto.decrypt(&ct)?).into_owned());
+        }
+    }
+    Ok((parts.join(separator), parts.len()))
+}
+
+/// Puts `joined_text` on the clipboard as plain text without storing
+/// anything. Fails when none of `ids` is a text item.
+pub fn copy_joined(db: &Database, crypto: &KeyManager, ids: &[i64], separator: &str) -> Result<JoinedCopy> {
+    let (text, count) = joined_text(db, crypto, ids, separator)?;
+    if count == 0 {
+        return Err(anyhow!("no text items selected"));
+    }
+    let len = text.len();
+    Clipboard::new()?.set_text(text)?;
+    Ok(JoinedCopy { count, len })
+}
+
+/// Failures from `copy_back` the UI reacts to. `Display` strings are stable.
+#[derive(Debug, thiserror::Error)]
+pub enum CopyError {
+    /// A file item's original path is gone and no content was stored.
+    #[error("file missing")]
+    FileMissing,
+    /// An image reduced to its thumbnail by `downgrade_old_images`.
+    #[error("original not stored")]
+    OriginalNotStored,
+    /// Stored image bytes that are corrupt or in a format we can't read.
+    #[error("image {id} could not be decoded ({reason}); delete the item and copy the image again")]
+    ImageDecode { id: i64, reason: String },
+}
+
+/// Decodes the stored image bytes of item `id`. On failure the item is
+/// flagged with `set_image_corrupt` and a `CopyError::ImageDecode` is
+/// returned.
+pub fn decode_image(db: &Database, id: i64, bytes: &[u8]) -> Result<image::DynamicImage> {
+    image::load_from_memory(bytes).map_err(|e| {
+        if let Err(flag_err) = db.set_image_corrupt(id, true) {
+            eprintln!("failed to flag image {id} as corrupt: {flag_err}");
+        }
+        CopyError::ImageDecode { id, reason: e.to_string() }.into()
+    })
+}
+
+/// `make_thumbnail` for the stored image of item `id`, failing with
+/// `CopyError::ImageDecode` when the bytes can't be read.
+pub fn item_thumbnail(db: &Database, id: i64, bytes: &[u8], max: u32, format: ThumbnailFormat) -> Result<Vec<u8>> {
+    thumbnail_of(decode_image(db, id, bytes)?, max, format)
+}
+
+/// Drops the full content of unpinned images captured before `cutoff` (ms
+/// since epoch), keeping only the encrypted thumbnail. Images without a
+/// cached thumbnail get one made first, which needs the key; while locked
+/// those are left for a later run. Returns how many were downgraded.
+pub fn downgrade_old_images(db: &Database, crypto: &KeyManager, settings: &Settings, cutoff: i64) -> Result<usize> {
+    // writes back thumbnails, both kept and freshly sealed
+    let _sealing = crypto.sealing();
+    let mut done = 0;
+    for id in db.images_to_downgrade(cutoff)? {
+        let (_, content_blob, preview_blob, _, _) = db.get_item_raw(id)?;
+        let thumb = match (preview_blob, content_blob) {
+            (Some(p), _) => p,
+            (None, Some(ct)) if crypto.is_unlocked() => {
+                let t = make_thumbnail(&crypto.decrypt(&ct)?, settings.thumbnail_max, settings.thumbnail_format)?;
+                crypto.encrypt(&t)?
+            }
+            _ => continue,
+        };
+        db.downgrade_image(id, &thumb)?;
+        done += 1;
+    }
+    Ok(done)
+}
+
+/// The path to put on the pasteboard for a file item: the restored copy of
+/// stored content if there is any, else the original path if it still
+/// exists.
+pub fn file_to_paste(crypto: &KeyManager, id: i64, file_path: Option<&str>, content_blob: Option<&[u8]>) -> Result<PathBuf> {
+    match (content_blob, file_path) {
+        (Some(ct), Some(fp)) => restore_file(crypto, id, fp, ct),
+        (_, Some(fp)) if Path::new(fp).exists() => Ok(PathBuf::from(fp)),
+        _ => Err(CopyError::FileMissing.into()),
+    }
+}
+
+/// Whether copying item `id` back has to decrypt anything. A file with a
+/// plaintext path and no stored content is pasted by path alone, so it can
+/// be copied while locked.
+pub fn copy_needs_key(db: &Database, id: i64) -> Result<bool> {
+    let (kind, content_blob, _, _, file_path) = db.get_item_raw(id)?;
+    Ok(kind != "file" || content_blob.is_some() || file_path.is_none())
+}
+
 pub fn copy_back(db: &Database, crypto: &KeyManager, id: i64) -> Result<()> {
     let (kind, content_blob, _preview_blob, rtf_blob, file_path) = db.get_item_raw(id)?;
+    let file_path = resolve_file_path(db, crypto, id, file_path)?;
     match kind.as_str() {
-        "text" => {
+        k if is_text_kind(k) => {
             if let Some(ct) = content_blob {
                 let pt = crypto.decrypt(&ct)?;
                 let mut cb = Clipboard::new()?;
@@ -211,33 +1520,44 @@ pub fn copy_back(db: &Database, crypto: &KeyManager, id: i64) -> Result<()> {
             }
         }
         "image" => {
-            if let Some(ct) = content_blob {
-                let pt = crypto.decrypt(&ct)?; // PNG bytes
-                let img = image::load_from_memory(&pt)?;
-                let rgba = img.to_rgba8();
-                let (w, h) = img.dimensions();
-                let data = ImageData {
-                    width: w as usize,
-                    height: h as usize,
-                    bytes: std::borrow::Cow::Owned(rgba.into_raw()),
-                };
-                let mut cb = Clipboard::new()?;
-                cb.set_image(data)?;
-            }
+            let ct = content_blob.ok_or(CopyError::OriginalNotStored)?;
+            let pt = crypto.decrypt(&ct)?; // PNG bytes
+            let img = decode_image(db, id, &pt)?;
+            let rgba = img.to_rgba8();
+            let (w, h) = img.dimensions();
+            let data = ImageData {
+                width: w as usize,
+                height: h as usize,
+                bytes: std::borrow::Cow::Owned(rgba.into_raw()),
+            };
+            let mut cb = Clipboard::new()?;
+            cb.set_image(data)?;
         }
         "file" => {
+            // stored content wins; the original may be gone
+            let path = file_to_paste(crypto, id, file_path.as_deref(), content_blob.as_deref())?;
             #[cfg(target_os = "macos")]
-            {
-                if let Some(path) = file_path {
-                    unsafe {
-                        let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
-                        let _: () = msg_send![pb, clearContents];
-                        let ns_path: id = NSString::alloc(nil).init_str(&path);
-                        let url: id = msg_send![class!(NSURL), fileURLWithPath: ns_path];
-                        let arr: id = msg_send![class!(NSArray), arrayWithObject: url];
-                        let _: bool = msg_send![pb, writeObjects: arr];
-                    }
+            unsafe {
+                let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+                let _: () = msg_send![pb, clearContents];
+                let ns_path: id = NSString::alloc(nil).init_str(&path.to_string_lossy());
+                let url: id = msg_send![class!(NSURL), fileURLWithPath: ns_path];
+                let arr: id = msg_send![class!(NSArray), arrayWithObject: url];
+                let _: bool = msg_send![pb, writeObjects: arr];
+            }
+            #[cfg(not(target_os = "macos"))]
+            let _ = path;
+        }
+        "blob" => {
+            if let (Some(ct), Some(uti)) = (content_blob, db.get_item(id)?.uti) {
+                let pt = crypto.decrypt(&ct)?;
+                #[cfg(target_os = "macos")]
+                unsafe {
+                    let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+                    write_pasteboard_items(pb, &[vec![(uti, pt)]]);
                 }
+                #[cfg(not(target_os = "macos"))]
+                let _ = (uti, pt);
             }
         }
         _ => {}
@@ -259,5 +1579,24 @@ pub fn copy_back(db: &Database, crypto: &KeyManager, id: i64) -> Result<()> {
             }
         }
     }
+
+    // HTML and extra types join the first pasteboard item, next to the
+    // primary data, so rich editors keep links and formatting
+    #[cfg(target_os = "macos")]
+    {
+        let extras = restored_types(db, crypto, id)?;
+        if !extras.is_empty() {
+            unsafe {
+                let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+                let mut items = read_pasteboard_items(pb);
+                if items.is_empty() {
+                    items.push(Vec::new());
+                }
+                items[0].retain(|(uti, _)| !extras.iter().any(|(x, _)| x == uti));
+                items[0].extend(extras);
+                write_pasteboard_items(pb, &items);
+            }
+        }
+    }
     Ok(())
 }
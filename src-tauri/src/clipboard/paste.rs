@@ -0,0 +1,104 @@
+use anyhow::{anyhow, Result};
+
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, nil};
+#[cfg(target_os = "macos")]
+use objc::{class, msg_send, sel, sel_impl};
+#[cfg(target_os = "macos")]
+use std::ffi::c_void;
+
+#[cfg(target_os = "macos")]
+#[cfg_attr(target_vendor = "apple", link(name = "CoreGraphics", kind = "framework"))]
+extern "C" {
+    fn CGEventCreateKeyboardEvent(source: *const c_void, keycode: u16, keydown: bool) -> *mut c_void;
+    fn CGEventSetFlags(event: *mut c_void, flags: u64);
+    fn CGEventPost(tap: u32, event: *mut c_void);
+}
+
+#[cfg(target_os = "macos")]
+#[cfg_attr(target_vendor = "apple", link(name = "ApplicationServices", kind = "framework"))]
+extern "C" {
+    fn AXIsProcessTrusted() -> bool;
+}
+
+#[cfg(target_os = "macos")]
+#[cfg_attr(target_vendor = "apple", link(name = "CoreFoundation", kind = "framework"))]
+extern "C" {
+    fn CFRelease(cf: *const c_void);
+}
+
+#[cfg(target_os = "macos")]
+const KEY_V: u16 = 9; // kVK_ANSI_V
+#[cfg(target_os = "macos")]
+const FLAG_COMMAND: u64 = 0x0010_0000; // kCGEventFlagMaskCommand
+#[cfg(target_os = "macos")]
+const HID_EVENT_TAP: u32 = 0; // kCGHIDEventTap
+#[cfg(target_os = "macos")]
+const ACTIVATE_IGNORING_OTHER_APPS: u64 = 1 << 1;
+
+/// Process id of the frontmost app, unless that is Cliper itself.
+pub fn frontmost_pid() -> Option<i32> {
+    #[cfg(target_os = "macos")]
+    unsafe {
+        let ws: id = msg_send![class!(NSWorkspace), sharedWorkspace];
+        let app: id = msg_send![ws, frontmostApplication];
+        if app == nil {
+            return None;
+        }
+        let pid: i32 = msg_send![app, processIdentifier];
+        return (pid > 0 && pid as u32 != std::process::id()).then_some(pid);
+    }
+    #[allow(unreachable_code)]
+    None
+}
+
+/// Brings the app with `pid` to the front. False when it has quit.
+pub fn activate(pid: i32) -> bool {
+    #[cfg(target_os = "macos")]
+    unsafe {
+        let app: id = msg_send![class!(NSRunningApplication), runningApplicationWithProcessIdentifier: pid];
+        if app == nil {
+            return false;
+        }
+        return msg_send![app, activateWithOptions: ACTIVATE_IGNORING_OTHER_APPS];
+    }
+    #[allow(unreachable_code)]
+    {
+        let _ = pid;
+        false
+    }
+}
+
+/// Whether Cliper has the Accessibility permission. Cheap enough to poll;
+/// macOS grants it while the app runs, so it can turn true at any time.
+pub fn accessibility_trusted() -> bool {
+    #[cfg(target_os = "macos")]
+    unsafe {
+        return AXIsProcessTrusted();
+    }
+    #[allow(unreachable_code)]
+    false
+}
+
+/// Sends ⌘V to whatever app is frontmost. Needs the Accessibility
+/// permission; without it macOS drops the events, so this fails up front.
+pub fn post_paste() -> Result<()> {
+    #[cfg(target_os = "macos")]
+    unsafe {
+        if !accessibility_trusted() {
+            return Err(anyhow!("allow Cliper under Privacy & Security > Accessibility to paste"));
+        }
+        for keydown in [true, false] {
+            let event = CGEventCreateKeyboardEvent(std::ptr::null(), KEY_V, keydown);
+            if event.is_null() {
+                return Err(anyhow!("could not create the paste key event"));
+            }
+            CGEventSetFlags(event, FLAG_COMMAND);
+            CGEventPost(HID_EVENT_TAP, event);
+            CFRelease(event);
+        }
+        return Ok(());
+    }
+    #[allow(unreachable_code)]
+    Err(anyhow!("not supported"))
+}
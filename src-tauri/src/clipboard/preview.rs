@@ -0,0 +1,70 @@
+use super::{make_thumbnail, ThumbnailFormat};
+use anyhow::Result;
+use base64::Engine;
+use std::io::Read;
+use std::path::Path;
+
+/// Bytes read from a file when looking for a text snippet.
+const TEXT_READ_CAP: u64 = 16 * 1024;
+/// Lines and characters kept in a text snippet.
+const TEXT_PREVIEW_LINES: usize = 12;
+const TEXT_PREVIEW_CHARS: usize = 1200;
+/// Image files larger than this are not decoded for a thumbnail.
+const IMAGE_SIZE_CAP: u64 = 25 * 1024 * 1024;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp"];
+
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum FilePreview {
+    /// First lines of a UTF-8 text file.
+    Text { snippet: String, truncated: bool },
+    /// Downscaled image as a `data:` URL.
+    Image { data_url: String },
+}
+
+/// Builds a preview for a captured file path: a thumbnail for image files,
+/// the first lines for text files. Returns `None` for directories, binary
+/// or oversized files, and images that fail to decode.
+pub fn file_preview(path: &Path, max: u32, format: ThumbnailFormat) -> Result<Option<FilePreview>> {
+    let meta = std::fs::metadata(path)?;
+    if !meta.is_file() {
+        return Ok(None);
+    }
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+    if IMAGE_EXTENSIONS.contains(&ext.as_str()) {
+        if meta.len() > IMAGE_SIZE_CAP {
+            return Ok(None);
+        }
+        let bytes = std::fs::read(path)?;
+        return Ok(make_thumbnail(&bytes, max, format).ok().map(|thumb| FilePreview::Image {
+            data_url: format!(
+                "data:{};base64,{}",
+                format.mime(),
+                base64::engine::general_purpose::STANDARD.encode(thumb)
+            ),
+        }));
+    }
+
+    let mut head = Vec::new();
+    std::fs::File::open(path)?.take(TEXT_READ_CAP).read_to_end(&mut head)?;
+    if head.contains(&0) {
+        return Ok(None);
+    }
+    let text = match std::str::from_utf8(&head) {
+        Ok(t) => t,
+        // the cap may cut a multi-byte character in half
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()])?,
+        Err(_) => return Ok(None),
+    };
+    let lines: Vec<&str> = text.lines().collect();
+    let head_text = lines[..lines.len().min(TEXT_PREVIEW_LINES)].join("\n");
+    let snippet: String = head_text.chars().take(TEXT_PREVIEW_CHARS).collect();
+    let truncated = meta.len() > head.len() as u64
+        || lines.len() > TEXT_PREVIEW_LINES
+        || snippet.len() < head_text.len();
+    Ok(Some(FilePreview::Text { snippet, truncated }))
+}
@@ -0,0 +1,119 @@
+use super::{store_snapshot, Snapshot};
+use crate::crypto::{CryptoError, KeyManager};
+use crate::db::Database;
+use crate::state::Settings;
+use anyhow::{anyhow, Result};
+
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, nil};
+#[cfg(target_os = "macos")]
+use cocoa::foundation::NSString;
+#[cfg(target_os = "macos")]
+use objc::declare::ClassDecl;
+#[cfg(target_os = "macos")]
+use objc::runtime::{Object, Sel};
+#[cfg(target_os = "macos")]
+use objc::{class, msg_send, sel, sel_impl};
+#[cfg(target_os = "macos")]
+use std::ffi::c_void;
+
+/// Stores text handed over by the Services menu as a pinned item, going
+/// through the normal capture path (kind detection, encryption, dedup).
+/// Text that was already in history is pinned in place.
+pub fn save_service_text(db: &Database, crypto: &KeyManager, settings: &Settings, text: &str) -> Result<i64> {
+    if text.trim().is_empty() {
+        return Err(anyhow!("no text to save"));
+    }
+    if !crypto.is_unlocked() {
+        return Err(CryptoError::Locked.into());
+    }
+    let ids = store_snapshot(db, crypto, settings, Snapshot { text: Some(text.to_string()), ..Default::default() })?;
+    let id = *ids.first().ok_or_else(|| anyhow!("nothing stored"))?;
+    db.pin_item(id, true)?;
+    Ok(id)
+}
+
+#[cfg(target_os = "macos")]
+static PROVIDER_APP: once_cell::sync::OnceCell<tauri::AppHandle> = once_cell::sync::OnceCell::new();
+
+/// Registers the object that answers the "Save to Cliper" service; the
+/// selector matches `NSMessage` in Info.plist. Must run on the main thread,
+/// during setup.
+#[cfg(target_os = "macos")]
+pub fn register_service_provider(app: tauri::AppHandle) {
+    if PROVIDER_APP.set(app).is_err() {
+        return;
+    }
+    unsafe {
+        let mut decl = match ClassDecl::new("CliperServiceProvider", class!(NSObject)) {
+            Some(d) => d,
+            None => return,
+        };
+        decl.add_method(
+            sel!(saveToCliper:userData:error:),
+            save_to_cliper as extern "C" fn(&Object, Sel, id, id, *mut c_void),
+        );
+        let cls = decl.register();
+        let provider: id = msg_send![cls, new];
+        let ns_app: id = msg_send![class!(NSApplication), sharedApplication];
+        let _: () = msg_send![ns_app, setServicesProvider: provider];
+    }
+}
+
+/// Runs on the main thread, so it only reads the text; unlocking (which may
+/// wait on Touch ID) and saving happen on a worker thread. Failures there
+/// are emitted as `service_save_failed` with the error message.
+#[cfg(target_os = "macos")]
+extern "C" fn save_to_cliper(_this: &Object, _cmd: Sel, pboard: id, _user_data: id, error: *mut c_void) {
+    use tauri::Manager;
+
+    let Some(app) = PROVIDER_APP.get() else { return };
+    let Some(text) = (unsafe { read_service_text(pboard) }) else {
+        eprintln!("save to cliper failed: no text to save");
+        if !error.is_null() {
+            unsafe {
+                *(error as *mut id) = NSString::alloc(nil).init_str("no text to save");
+            }
+        }
+        return;
+    };
+    let app = app.clone();
+    std::thread::spawn(move || {
+        let state = app.state::<crate::state::AppState>();
+        // The user asked for this explicitly, so unlock like opening the overlay does.
+        let unlocked = if state.crypto.is_unlocked() {
+            Ok(())
+        } else if state.settings.lock().require_biometrics {
+            state.crypto.unlock_with_biometrics()
+        } else {
+            state.crypto.unlock()
+        };
+        let result = unlocked.map_err(anyhow::Error::from).and_then(|()| {
+            let settings = state.settings.lock().clone();
+            save_service_text(&state.db, &state.crypto, &settings, &text)
+        });
+        match result {
+            Ok(_) => {
+                let _ = app.emit_all("items_updated", ());
+            }
+            Err(e) => {
+                eprintln!("save to cliper failed: {e:?}");
+                let _ = app.emit_all("service_save_failed", e.to_string());
+            }
+        }
+    });
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn read_service_text(pboard: id) -> Option<String> {
+    let ty = NSString::alloc(nil).init_str("public.utf8-plain-text");
+    let s: id = msg_send![pboard, stringForType: ty];
+    if s == nil {
+        return None;
+    }
+    let cstr: *const std::os::raw::c_char = msg_send![s, UTF8String];
+    if cstr.is_null() {
+        return None;
+    }
+    Some(std::ffi::CStr::from_ptr(cstr).to_string_lossy().into_owned())
+}
@@ -0,0 +1,48 @@
+/// Built-in clean-ups applied, in the configured order, to captured text
+/// before it is hashed and encrypted. They change what is stored, and so
+/// what dedups against what.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureTransform {
+    /// Leading and trailing whitespace.
+    Trim,
+    /// `\r\n` and lone `\r` become `\n`.
+    NormalizeEol,
+    /// Zero-width spaces/joiners, word joiners and BOMs.
+    StripZeroWidth,
+    /// Runs of spaces and tabs become one space; line breaks are kept.
+    CollapseWhitespace,
+}
+
+const ZERO_WIDTH: &[char] = &['\u{200B}', '\u{200C}', '\u{200D}', '\u{2060}', '\u{FEFF}'];
+
+impl CaptureTransform {
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            Self::Trim => text.trim().to_string(),
+            Self::NormalizeEol => text.replace("\r\n", "\n").replace('\r', "\n"),
+            Self::StripZeroWidth => text.chars().filter(|c| !ZERO_WIDTH.contains(c)).collect(),
+            Self::CollapseWhitespace => {
+                let mut out = String::with_capacity(text.len());
+                let mut in_run = false;
+                for c in text.chars() {
+                    if c.is_whitespace() && c != '\n' && c != '\r' {
+                        if !in_run {
+                            out.push(' ');
+                        }
+                        in_run = true;
+                    } else {
+                        out.push(c);
+                        in_run = false;
+                    }
+                }
+                out
+            }
+        }
+    }
+}
+
+/// Runs `transforms` over `text` in order.
+pub fn apply_transforms(text: &str, transforms: &[CaptureTransform]) -> String {
+    transforms.iter().fold(text.to_string(), |t, tr| tr.apply(&t))
+}
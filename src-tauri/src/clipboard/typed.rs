@@ -0,0 +1,65 @@
+use crate::crypto::{CryptoError, KeyManager};
+use crate::db::{Database, NewItem};
+use anyhow::{anyhow, Result};
+
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, nil};
+#[cfg(target_os = "macos")]
+use cocoa::foundation::NSString;
+#[cfg(target_os = "macos")]
+use objc::{class, msg_send, sel, sel_impl};
+
+/// Pasteboard types `capture_type` may store. Kept to passive document
+/// formats; anything that could carry app-private state stays out.
+pub const CAPTURABLE_UTIS: &[&str] = &["com.adobe.pdf", "public.html", "public.tiff", "public.svg-image"];
+
+/// Stores `data` as a "blob" item that remembers its pasteboard type, so
+/// `copy_back` can put it back under the same UTI.
+pub fn store_typed(db: &Database, crypto: &KeyManager, uti: &str, data: &[u8]) -> Result<i64> {
+    if !CAPTURABLE_UTIS.contains(&uti) {
+        return Err(anyhow!("{uti} is not a capturable type"));
+    }
+    if data.is_empty() {
+        return Err(anyhow!("no {uti} data on the clipboard"));
+    }
+    if !crypto.is_unlocked() {
+        return Err(CryptoError::Locked.into());
+    }
+    let _sealing = crypto.sealing();
+    let enc = crypto.encrypt(data)?;
+    db.insert_item(NewItem {
+        kind: "blob".into(),
+        size: data.len() as i64,
+        sha256: Database::compute_sha256(data),
+        file_path: None,
+        file_path_blob: None,
+        content_blob: Some(enc),
+        preview_blob: None,
+        rtf_blob: None,
+        html_blob: None,
+        source_app: None,
+        uti: Some(uti.to_string()),
+    })
+}
+
+/// Reads the `uti` representation of the current pasteboard contents and
+/// stores it with `store_typed`.
+#[cfg(target_os = "macos")]
+pub fn capture_type(db: &Database, crypto: &KeyManager, uti: &str) -> Result<i64> {
+    if !CAPTURABLE_UTIS.contains(&uti) {
+        return Err(anyhow!("{uti} is not a capturable type"));
+    }
+    let data = unsafe {
+        let pb: id = msg_send![class!(NSPasteboard), generalPasteboard];
+        let ty: id = NSString::alloc(nil).init_str(uti);
+        let data: id = msg_send![pb, dataForType: ty];
+        if data == nil {
+            Vec::new()
+        } else {
+            let len: u64 = msg_send![data, length];
+            let ptr: *const u8 = msg_send![data, bytes];
+            if ptr.is_null() { Vec::new() } else { std::slice::from_raw_parts(ptr, len as usize).to_vec() }
+        }
+    };
+    store_typed(db, crypto, uti, &data)
+}
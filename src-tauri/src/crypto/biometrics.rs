@@ -0,0 +1,121 @@
+#[cfg(target_os = "macos")]
+use block::ConcreteBlock;
+#[cfg(target_os = "macos")]
+use cocoa::base::{id, nil, BOOL, NO};
+#[cfg(target_os = "macos")]
+use cocoa::foundation::NSString;
+#[cfg(target_os = "macos")]
+use objc::{class, msg_send, sel, sel_impl};
+
+#[cfg(target_os = "macos")]
+#[cfg_attr(target_vendor = "apple", link(name = "LocalAuthentication", kind = "framework"))]
+extern "C" {}
+
+#[cfg(target_os = "macos")]
+const POLICY_BIOMETRICS: i64 = 1; // LAPolicyDeviceOwnerAuthenticationWithBiometrics
+
+/// Touch ID failures the UI handles differently, mapped from `LAError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BiometricErrorKind {
+    /// The user, the system or another app dismissed the prompt, or the
+    /// user chose the fallback button.
+    Cancelled,
+    /// The finger was not recognized.
+    Failed,
+    /// No Touch ID sensor, no enrolled fingers, or not macOS; the UI should
+    /// fall back to a plain unlock.
+    Unavailable,
+    /// Too many failed attempts; the login password re-enables Touch ID.
+    LockedOut,
+    Other(i64),
+}
+
+impl BiometricErrorKind {
+    pub fn from_code(code: i64) -> Self {
+        match code {
+            // LAErrorAuthenticationFailed
+            -1 => Self::Failed,
+            // LAErrorUserCancel, LAErrorUserFallback, LAErrorSystemCancel, LAErrorAppCancel
+            -2 | -3 | -4 | -9 => Self::Cancelled,
+            // LAErrorPasscodeNotSet, LAErrorBiometryNotAvailable, LAErrorBiometryNotEnrolled
+            -7..=-5 => Self::Unavailable,
+            // LAErrorBiometryLockout
+            -8 => Self::LockedOut,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl std::fmt::Display for BiometricErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cancelled => f.write_str("cancelled"),
+            Self::Failed => f.write_str("failed"),
+            Self::Unavailable => f.write_str("unavailable"),
+            Self::LockedOut => f.write_str("locked out"),
+            Self::Other(code) => write!(f, "error {code}"),
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn error_code(error: id) -> i64 {
+    if error == nil {
+        return 0;
+    }
+    msg_send![error, code]
+}
+
+/// Whether Touch ID can be used right now (sensor present, fingers
+/// enrolled, not locked out).
+pub fn biometrics_available() -> bool {
+    #[cfg(target_os = "macos")]
+    unsafe {
+        let ctx: id = msg_send![class!(LAContext), new];
+        let mut error: id = nil;
+        let ok: BOOL = msg_send![ctx, canEvaluatePolicy: POLICY_BIOMETRICS error: &mut error];
+        let _: () = msg_send![ctx, release];
+        return ok != NO;
+    }
+    #[allow(unreachable_code)]
+    false
+}
+
+/// Shows the Touch ID prompt with `reason` and blocks until it is answered.
+pub fn authenticate(reason: &str) -> Result<(), BiometricErrorKind> {
+    #[cfg(target_os = "macos")]
+    unsafe {
+        let ctx: id = msg_send![class!(LAContext), new];
+        let mut error: id = nil;
+        let can: BOOL = msg_send![ctx, canEvaluatePolicy: POLICY_BIOMETRICS error: &mut error];
+        if can == NO {
+            let code = error_code(error);
+            let _: () = msg_send![ctx, release];
+            return Err(match BiometricErrorKind::from_code(code) {
+                BiometricErrorKind::Other(_) => BiometricErrorKind::Unavailable,
+                kind => kind,
+            });
+        }
+        // the reply arrives on a private queue, never the calling thread
+        let (tx, rx) = std::sync::mpsc::channel();
+        let reply = ConcreteBlock::new(move |success: BOOL, error: id| {
+            let _ = tx.send(if success != NO { 0 } else { error_code(error) });
+        })
+        .copy();
+        let reason = NSString::alloc(nil).init_str(reason);
+        let _: () = msg_send![ctx, evaluatePolicy: POLICY_BIOMETRICS localizedReason: reason reply: &*reply];
+        let code = rx.recv().unwrap_or(-4);
+        let _: () = msg_send![reason, release];
+        let _: () = msg_send![ctx, release];
+        return match code {
+            0 => Ok(()),
+            code => Err(BiometricErrorKind::from_code(code)),
+        };
+    }
+    #[allow(unreachable_code)]
+    {
+        let _ = reason;
+        Err(BiometricErrorKind::Unavailable)
+    }
+}
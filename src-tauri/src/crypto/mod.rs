@@ -1,19 +1,175 @@
-use anyhow::{anyhow, Result};
 use ring::aead::{Aad, LessSafeKey, UnboundKey, AES_256_GCM, Nonce};
 use ring::rand::{SecureRandom, SystemRandom};
 use security_framework::passwords::{get_generic_password, set_generic_password, delete_generic_password};
 use zeroize::{Zeroize, Zeroizing};
 
+mod biometrics;
+pub use biometrics::{biometrics_available, BiometricErrorKind};
+
 const SERVICE_SUFFIX: &str = ".masterkey";
 const ACCOUNT: &str = "default";
 const KEY_LEN: usize = 32; // 256-bit
 const NONCE_LEN: usize = 12; // 96-bit IV for AES-GCM
+const NONCE_PREFIX_LEN: usize = 4;
+const NONCE_ACCOUNT: &str = "nonce-counter";
+// Counter values are reserved in blocks so the Keychain is written once per
+// block rather than per encryption; a restart skips the rest of the block.
+const NONCE_BLOCK: u64 = 1024;
+const SALT_ACCOUNT: &str = "passphrase-salt";
+const VERIFIER_ACCOUNT: &str = "passphrase-verifier";
+const SALT_LEN: usize = 16;
+// Sealed under the derived key and kept next to the salt, so a wrong
+// passphrase is told apart from a corrupt item.
+const VERIFIER_PLAINTEXT: &[u8] = b"cliper passphrase verifier";
+// Completes "Cliper is trying to …" in the Touch ID prompt.
+const BIOMETRIC_REASON: &str = "unlock your clipboard history";
+
+/// How `encrypt` lays out a sealed blob, for external tooling that checks
+/// backups decrypt with a known key.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlobLayout {
+    pub cipher: &'static str,
+    pub layout: &'static str,
+    pub nonce_len: usize,
+    pub tag_len: usize,
+    /// Associated data passed to the AEAD; none is used.
+    pub aad: &'static str,
+    /// Bytes before the nonce; blobs carry no version prefix.
+    pub version_prefix_len: usize,
+}
+
+pub const BLOB_LAYOUT: BlobLayout = BlobLayout {
+    cipher: "AES-256-GCM",
+    layout: "nonce || ciphertext || tag",
+    nonce_len: NONCE_LEN,
+    tag_len: 16,
+    aad: "empty",
+    version_prefix_len: 0,
+};
+
+/// Failures from `KeyManager`, distinct so callers can tell a locked store
+/// from a corrupt blob. `Display` strings are stable and shown in the UI.
+#[derive(Debug, thiserror::Error)]
+pub enum CryptoError {
+    #[error("locked")]
+    Locked,
+    #[error("bad key")]
+    BadKey,
+    /// Blob is truncated, tampered with, or sealed under another key.
+    #[error("decrypt failed")]
+    Decrypt,
+    #[error("encrypt failed")]
+    Encrypt,
+    #[error("rng failed")]
+    Rng,
+    #[error("keychain {kind}")]
+    Keychain { kind: KeychainErrorKind },
+    /// Passphrase mode is on, so `unlock` cannot load a key by itself.
+    #[error("passphrase required")]
+    PassphraseRequired,
+    #[error("wrong passphrase")]
+    WrongPassphrase,
+    /// `unlock_with_passphrase` was called without passphrase mode.
+    #[error("passphrase not enabled")]
+    PassphraseDisabled,
+    /// The key comes from the passphrase, so a random one cannot replace it.
+    #[error("key is derived from the passphrase")]
+    PassphraseKey,
+    #[error("biometrics {kind}")]
+    Biometrics { kind: BiometricErrorKind },
+}
+
+/// Keychain failures the UI handles differently, mapped from `OSStatus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeychainErrorKind {
+    /// The user denied access, cancelled the prompt, or the item's ACL
+    /// does not include Cliper.
+    Denied,
+    NotFound,
+    /// A prompt was needed but could not be shown (e.g. the login keychain
+    /// is locked and there is no UI session).
+    InteractionNotAllowed,
+    Other(i32),
+}
+
+impl KeychainErrorKind {
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            // errSecAuthFailed, errSecUserCanceled, errSecNoAccessForItem
+            -25293 | -128 | -25243 => Self::Denied,
+            // errSecItemNotFound
+            -25300 => Self::NotFound,
+            // errSecInteractionNotAllowed
+            -25308 => Self::InteractionNotAllowed,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl std::fmt::Display for KeychainErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Denied => f.write_str("access denied"),
+            Self::NotFound => f.write_str("item not found"),
+            Self::InteractionNotAllowed => f.write_str("interaction not allowed"),
+            Self::Other(code) => write!(f, "error {code}"),
+        }
+    }
+}
+
+impl From<security_framework::base::Error> for CryptoError {
+    fn from(e: security_framework::base::Error) -> Self {
+        CryptoError::Keychain { kind: KeychainErrorKind::from_code(e.code()) }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, CryptoError>;
+
+/// How `encrypt` picks its 96-bit nonce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NonceMode {
+    /// 96 random bits per message (default).
+    Random,
+    /// 32-bit random prefix fixed per key || 64-bit counter persisted next
+    /// to the key, so nonces never repeat for the key's lifetime.
+    Counter,
+}
+
+/// Where the master key comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyMode {
+    /// Created once and kept in the Keychain (default).
+    #[default]
+    Keychain,
+    /// Random key generated in memory at launch and never stored anywhere,
+    /// for kiosk or shared machines; history does not survive a restart.
+    Ephemeral,
+}
+
+struct NonceCounter {
+    prefix: [u8; NONCE_PREFIX_LEN],
+    next: u64,
+    reserved: u64,
+}
 
 pub struct KeyManager {
     bundle_id: String,
     // Raw key bytes stored when unlocked; zeroized on lock.
     key: parking_lot::Mutex<Option<Zeroizing<Vec<u8>>>>,
     rng: SystemRandom,
+    nonce_mode: NonceMode,
+    counter: parking_lot::Mutex<Option<NonceCounter>>,
+    key_mode: KeyMode,
+    // Ephemeral mode only: the launch key, kept so lock/unlock work.
+    ephemeral: parking_lot::Mutex<Option<Zeroizing<Vec<u8>>>>,
+    last_keychain_error: parking_lot::Mutex<Option<KeychainErrorKind>>,
+    // Derive the key from a passphrase instead of storing it.
+    passphrase: bool,
+    // Shared by every encrypt-then-store, exclusive for a key rotation.
+    sealing: parking_lot::RwLock<()>,
 }
 
 impl KeyManager {
@@ -22,7 +178,57 @@ impl KeyManager {
             bundle_id,
             key: parking_lot::Mutex::new(None),
             rng: SystemRandom::new(),
+            nonce_mode: NonceMode::Random,
+            counter: parking_lot::Mutex::new(None),
+            key_mode: KeyMode::Keychain,
+            ephemeral: parking_lot::Mutex::new(None),
+            last_keychain_error: parking_lot::Mutex::new(None),
+            passphrase: false,
+            sealing: parking_lot::RwLock::new(()),
+        }
+    }
+
+    pub fn with_key_mode(mut self, mode: KeyMode) -> Self {
+        self.key_mode = mode;
+        self
+    }
+
+    /// Derives the key from a passphrase (`unlock_with_passphrase`) instead
+    /// of keeping it in the Keychain. Ignored for ephemeral keys.
+    pub fn with_passphrase(mut self, enabled: bool) -> Self {
+        self.passphrase = enabled;
+        self
+    }
+
+    pub fn uses_passphrase(&self) -> bool {
+        self.passphrase && self.uses_keychain()
+    }
+
+    // Ephemeral keys and their nonce counters never touch the Keychain.
+    fn uses_keychain(&self) -> bool {
+        self.key_mode == KeyMode::Keychain
+    }
+
+    fn random_key(&self) -> Result<Vec<u8>> {
+        let mut key = vec![0u8; KEY_LEN];
+        self.rng.fill(&mut key).map_err(|_| CryptoError::Rng)?;
+        Ok(key)
+    }
+
+    pub fn with_nonce_mode(mut self, mode: NonceMode) -> Self {
+        self.nonce_mode = mode;
+        self
+    }
+
+    /// Next counter value in `Counter` mode (loading persisted state if
+    /// needed); `None` in `Random` mode.
+    #[allow(dead_code)]
+    pub fn nonce_counter(&self) -> Result<Option<u64>> {
+        if self.nonce_mode != NonceMode::Counter {
+            return Ok(None);
         }
+        let mut guard = self.counter.lock();
+        Ok(Some(self.load_counter(&mut guard)?.next))
     }
 
     fn service_name(&self) -> String {
@@ -40,13 +246,29 @@ impl KeyManager {
         }
     }
 
+    /// Replaces the key with a new one. In passphrase mode the salt and
+    /// verifier are deleted and the store locks; the next
+    /// `unlock_with_passphrase` sets a new passphrase.
     pub fn reset_master_key(&self) -> Result<()> {
+        if self.uses_passphrase() {
+            let service = self.service_name();
+            let _ = delete_generic_password(&service, SALT_ACCOUNT);
+            let _ = delete_generic_password(&service, VERIFIER_ACCOUNT);
+            self.forget_counter();
+            self.lock();
+            return Ok(());
+        }
+        if !self.uses_keychain() {
+            self.forget_counter();
+            let key = Zeroizing::from(self.random_key()?);
+            *self.ephemeral.lock() = Some(key.clone());
+            *self.key.lock() = Some(key);
+            return Ok(());
+        }
         let service = self.service_name();
         let _ = delete_generic_password(&service, ACCOUNT); // ignore error if not exists
-        let mut key = vec![0u8; KEY_LEN];
-        self.rng
-            .fill(&mut key)
-            .map_err(|_| anyhow!("rng failed"))?;
+        self.forget_counter();
+        let key = self.random_key()?;
         set_generic_password(&service, ACCOUNT, &key)?;
         let z = Zeroizing::from(key);
         *self.key.lock() = Some(z);
@@ -54,68 +276,299 @@ impl KeyManager {
     }
 
     pub fn unlock(&self) -> Result<()> {
-        // Try to load from Keychain; if missing, generate and store.
+        if self.uses_passphrase() {
+            return Err(CryptoError::PassphraseRequired);
+        }
+        if !self.uses_keychain() {
+            let mut launch_key = self.ephemeral.lock();
+            if launch_key.is_none() {
+                *launch_key = Some(Zeroizing::from(self.random_key()?));
+            }
+            *self.key.lock() = launch_key.clone();
+            return Ok(());
+        }
+        let key = self.load_or_create_key().map_err(|e| {
+            if let CryptoError::Keychain { kind } = e {
+                *self.last_keychain_error.lock() = Some(kind);
+            }
+            e
+        })?;
+        *self.last_keychain_error.lock() = None;
+        *self.key.lock() = Some(Zeroizing::from(key));
+        Ok(())
+    }
+
+    /// Hold from encrypting until the result is written to the database, so
+    /// a key rotation cannot commit in between and strand the blob under the
+    /// old key. Waits while a rotation runs; a thread may take it again
+    /// while already holding it.
+    pub fn sealing(&self) -> parking_lot::RwLockReadGuard<'_, ()> {
+        self.sealing.read_recursive()
+    }
+
+    /// `sealing` without waiting; `None` while a rotation runs.
+    pub fn try_sealing(&self) -> Option<parking_lot::RwLockReadGuard<'_, ()>> {
+        self.sealing.try_read_recursive()
+    }
+
+    /// Keeps every `sealing` caller out until dropped; held for a whole
+    /// rotation. Must not be taken while holding `sealing`.
+    pub fn rotating(&self) -> parking_lot::RwLockWriteGuard<'_, ()> {
+        self.sealing.write()
+    }
+
+    /// A fresh random key for re-encrypting history, held by a detached
+    /// manager that never touches the Keychain; `adopt_key` makes it this
+    /// manager's key. Needs the current key loaded.
+    pub fn rotate_key(&self) -> Result<KeyManager> {
+        if self.uses_passphrase() {
+            return Err(CryptoError::PassphraseKey);
+        }
+        if !self.is_unlocked() {
+            return Err(CryptoError::Locked);
+        }
+        Ok(self.detached(Zeroizing::from(self.random_key()?)))
+    }
+
+    /// Replaces the key with `rotated`'s, storing it in the Keychain first
+    /// so nothing changes if that fails. Returns a detached manager holding
+    /// the replaced key, to undo the swap with.
+    pub fn adopt_key(&self, rotated: &KeyManager) -> Result<KeyManager> {
+        if self.uses_passphrase() {
+            return Err(CryptoError::PassphraseKey);
+        }
+        let key = rotated.key.lock().clone().ok_or(CryptoError::Locked)?;
+        let previous = self.key.lock().clone().ok_or(CryptoError::Locked)?;
+        if self.uses_keychain() {
+            set_generic_password(&self.service_name(), ACCOUNT, &key)?;
+        } else {
+            *self.ephemeral.lock() = Some(key.clone());
+        }
+        self.forget_counter();
+        *self.key.lock() = Some(key);
+        Ok(self.detached(previous))
+    }
+
+    // In-memory manager holding `key`, with random nonces.
+    fn detached(&self, key: Zeroizing<Vec<u8>>) -> KeyManager {
+        let km = KeyManager::new(self.bundle_id.clone()).with_key_mode(KeyMode::Ephemeral);
+        *km.ephemeral.lock() = Some(key.clone());
+        *km.key.lock() = Some(key);
+        km
+    }
+
+    /// Whether a passphrase has been set, i.e. whether the next
+    /// `unlock_with_passphrase` checks it rather than setting it.
+    pub fn has_passphrase(&self) -> Result<bool> {
+        match get_generic_password(&self.service_name(), SALT_ACCOUNT) {
+            Ok(_) => Ok(true),
+            Err(e) if KeychainErrorKind::from_code(e.code()) == KeychainErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Unlocks with a key derived from `passphrase` by Argon2id. Only the
+    /// salt and a verifier are kept in the Keychain; the first call sets the
+    /// passphrase, later ones fail with `WrongPassphrase` on a mismatch.
+    pub fn unlock_with_passphrase(&self, passphrase: &str) -> Result<()> {
+        if !self.uses_passphrase() {
+            return Err(CryptoError::PassphraseDisabled);
+        }
+        let key = self.derive_passphrase_key(passphrase).map_err(|e| {
+            if let CryptoError::Keychain { kind } = e {
+                *self.last_keychain_error.lock() = Some(kind);
+            }
+            e
+        })?;
+        *self.last_keychain_error.lock() = None;
+        *self.key.lock() = Some(key);
+        Ok(())
+    }
+
+    fn derive_passphrase_key(&self, passphrase: &str) -> Result<Zeroizing<Vec<u8>>> {
+        let service = self.service_name();
+        if self.has_passphrase()? {
+            let salt = get_generic_password(&service, SALT_ACCOUNT)?;
+            let verifier = get_generic_password(&service, VERIFIER_ACCOUNT)?;
+            let key = derive_key(passphrase, &salt)?;
+            return match open_with(&aead_key(&key)?, &verifier) {
+                Ok(plain) if plain == VERIFIER_PLAINTEXT => Ok(key),
+                _ => Err(CryptoError::WrongPassphrase),
+            };
+        }
+        let mut salt = [0u8; SALT_LEN];
+        self.rng.fill(&mut salt).map_err(|_| CryptoError::Rng)?;
+        let key = derive_key(passphrase, &salt)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| CryptoError::Rng)?;
+        let verifier = seal_with(&aead_key(&key)?, nonce, VERIFIER_PLAINTEXT)?;
+        set_generic_password(&service, VERIFIER_ACCOUNT, &verifier)?;
+        set_generic_password(&service, SALT_ACCOUNT, &salt)?;
+        self.forget_counter();
+        Ok(key)
+    }
+
+    /// `unlock`, but only after Touch ID succeeds, so the key is never read
+    /// from the Keychain silently. Fails with `Biometrics` when the prompt is
+    /// cancelled or Touch ID is unavailable (always, outside macOS).
+    pub fn unlock_with_biometrics(&self) -> Result<()> {
+        biometrics::authenticate(BIOMETRIC_REASON).map_err(|kind| CryptoError::Biometrics { kind })?;
+        self.unlock()
+    }
+
+    /// Why the last `unlock` failed to use the Keychain, if it did; cleared
+    /// by a successful unlock.
+    pub fn last_keychain_error(&self) -> Option<KeychainErrorKind> {
+        *self.last_keychain_error.lock()
+    }
+
+    // Load from Keychain; only a missing item means "first run, create one".
+    // Anything else (denied, no UI) must not be papered over with a new key.
+    fn load_or_create_key(&self) -> Result<Vec<u8>> {
         let service = self.service_name();
-        let existing = get_generic_password(&service, ACCOUNT).ok();
-        let key = match existing {
-            Some(bytes) => bytes,
-            None => {
-                let mut key = vec![0u8; KEY_LEN];
-                self.rng
-                    .fill(&mut key)
-                    .map_err(|_| anyhow!("rng failed"))?;
+        match get_generic_password(&service, ACCOUNT) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) if KeychainErrorKind::from_code(e.code()) == KeychainErrorKind::NotFound => {
+                let key = self.random_key()?;
                 set_generic_password(&service, ACCOUNT, &key)?;
-                key
+                self.forget_counter();
+                Ok(key)
             }
-        };
-        let z = Zeroizing::from(key);
-        *self.key.lock() = Some(z);
-        Ok(())
+            Err(e) => Err(e.into()),
+        }
     }
 
     fn less_safe_key(&self) -> Result<LessSafeKey> {
         let guard = self.key.lock();
-        let key = guard.as_ref().ok_or_else(|| anyhow!("locked"))?;
-        let unbound = UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow!("bad key"))?;
-        Ok(LessSafeKey::new(unbound))
+        aead_key(guard.as_ref().ok_or(CryptoError::Locked)?)
+    }
+
+    // Counter state belongs to a key; drop it whenever a new key is made.
+    fn forget_counter(&self) {
+        if self.uses_keychain() {
+            let _ = delete_generic_password(&self.service_name(), NONCE_ACCOUNT);
+        }
+        *self.counter.lock() = None;
+    }
+
+    fn load_counter<'a>(&self, slot: &'a mut Option<NonceCounter>) -> Result<&'a mut NonceCounter> {
+        if slot.is_none() {
+            let stored = if self.uses_keychain() {
+                get_generic_password(&self.service_name(), NONCE_ACCOUNT).ok()
+            } else {
+                None
+            };
+            let counter = match stored {
+                Some(b) if b.len() == NONCE_PREFIX_LEN + 8 => {
+                    let prefix: [u8; NONCE_PREFIX_LEN] = b[..NONCE_PREFIX_LEN].try_into().expect("prefix length");
+                    let reserved = u64::from_be_bytes(b[NONCE_PREFIX_LEN..].try_into().expect("counter length"));
+                    // Resume past everything a previous run may have used.
+                    NonceCounter { prefix, next: reserved, reserved }
+                }
+                _ => {
+                    let mut prefix = [0u8; NONCE_PREFIX_LEN];
+                    self.rng.fill(&mut prefix).map_err(|_| CryptoError::Rng)?;
+                    NonceCounter { prefix, next: 0, reserved: 0 }
+                }
+            };
+            *slot = Some(counter);
+        }
+        Ok(slot.as_mut().expect("counter loaded"))
+    }
+
+    fn next_nonce(&self) -> Result<[u8; NONCE_LEN]> {
+        let mut nonce = [0u8; NONCE_LEN];
+        if self.nonce_mode == NonceMode::Random {
+            self.rng
+                .fill(&mut nonce)
+                .map_err(|_| CryptoError::Rng)?;
+            return Ok(nonce);
+        }
+        let mut guard = self.counter.lock();
+        let c = self.load_counter(&mut guard)?;
+        if c.next >= c.reserved {
+            let reserved = c.next.checked_add(NONCE_BLOCK).ok_or(CryptoError::Encrypt)?;
+            let mut stored = c.prefix.to_vec();
+            stored.extend_from_slice(&reserved.to_be_bytes());
+            if self.uses_keychain() {
+                set_generic_password(&self.service_name(), NONCE_ACCOUNT, &stored)?;
+            }
+            c.reserved = reserved;
+        }
+        nonce[..NONCE_PREFIX_LEN].copy_from_slice(&c.prefix);
+        nonce[NONCE_PREFIX_LEN..].copy_from_slice(&c.next.to_be_bytes());
+        c.next += 1;
+        Ok(nonce)
     }
 
     pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
         let key = self.less_safe_key()?;
-        let mut nonce = [0u8; NONCE_LEN];
-        self.rng
-            .fill(&mut nonce)
-            .map_err(|_| anyhow!("rng failed"))?;
-        // buffer: nonce || ciphertext+tag
-        let mut buf = Vec::with_capacity(NONCE_LEN + plaintext.len() + AES_256_GCM.tag_len());
-        buf.extend_from_slice(&nonce);
-        buf.extend_from_slice(plaintext);
-        // offset not needed; we split after NONCE_LEN
-        let mut slice = buf.split_off(NONCE_LEN);
-        let nonce = Nonce::assume_unique_for_key(nonce);
-        key.seal_in_place_append_tag(nonce, Aad::empty(), &mut slice)
-            .map_err(|_| anyhow!("encrypt failed"))?;
-        let mut out = Vec::with_capacity(NONCE_LEN + slice.len());
-        out.extend_from_slice(&buf[..NONCE_LEN]);
-        out.extend_from_slice(&slice);
-        Ok(out)
+        let nonce = self.next_nonce()?;
+        seal_with(&key, nonce, plaintext)
+    }
+
+    /// Seals and reopens a short canary with the loaded key. Cheap enough to
+    /// run periodically; fails with `Locked` while locked.
+    pub fn self_test(&self) -> Result<()> {
+        const CANARY: &[u8] = b"cliper self-test";
+        let sealed = self.encrypt(CANARY)?;
+        if self.decrypt(&sealed)? != CANARY {
+            return Err(CryptoError::Decrypt);
+        }
+        Ok(())
     }
 
     pub fn decrypt(&self, blob: &[u8]) -> Result<Vec<u8>> {
         if blob.len() < NONCE_LEN + AES_256_GCM.tag_len() {
-            return Err(anyhow!("blob too short"));
+            return Err(CryptoError::Decrypt);
         }
-        let key = self.less_safe_key()?;
-        let nonce_bytes: [u8; NONCE_LEN] = blob[..NONCE_LEN]
-            .try_into()
-            .expect("slice with correct length");
-        let nonce = Nonce::assume_unique_for_key(nonce_bytes);
-        let mut ciphertext = blob[NONCE_LEN..].to_vec();
-        let out = key
-            .open_in_place(nonce, Aad::empty(), &mut ciphertext)
-            .map_err(|_| anyhow!("decrypt failed"))?;
-        Ok(out.to_vec())
+        open_with(&self.less_safe_key()?, blob)
+    }
+}
+
+fn aead_key(key: &[u8]) -> Result<LessSafeKey> {
+    let unbound = UnboundKey::new(&AES_256_GCM, key).map_err(|_| CryptoError::BadKey)?;
+    Ok(LessSafeKey::new(unbound))
+}
+
+// Argon2id with the crate's default cost (19 MiB, 2 passes, 1 lane).
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<Zeroizing<Vec<u8>>> {
+    let mut key = Zeroizing::new(vec![0u8; KEY_LEN]);
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| CryptoError::BadKey)?;
+    Ok(key)
+}
+
+fn seal_with(key: &LessSafeKey, nonce: [u8; NONCE_LEN], plaintext: &[u8]) -> Result<Vec<u8>> {
+    // buffer: nonce || ciphertext+tag
+    let mut buf = Vec::with_capacity(NONCE_LEN + plaintext.len() + AES_256_GCM.tag_len());
+    buf.extend_from_slice(&nonce);
+    buf.extend_from_slice(plaintext);
+    // offset not needed; we split after NONCE_LEN
+    let mut slice = buf.split_off(NONCE_LEN);
+    let nonce = Nonce::assume_unique_for_key(nonce);
+    key.seal_in_place_append_tag(nonce, Aad::empty(), &mut slice)
+        .map_err(|_| CryptoError::Encrypt)?;
+    let mut out = Vec::with_capacity(NONCE_LEN + slice.len());
+    out.extend_from_slice(&buf[..NONCE_LEN]);
+    out.extend_from_slice(&slice);
+    Ok(out)
+}
+
+fn open_with(key: &LessSafeKey, blob: &[u8]) -> Result<Vec<u8>> {
+    if blob.len() < NONCE_LEN + AES_256_GCM.tag_len() {
+        return Err(CryptoError::Decrypt);
     }
+    let nonce_bytes: [u8; NONCE_LEN] = blob[..NONCE_LEN]
+        .try_into()
+        .expect("slice with correct length");
+    let nonce = Nonce::assume_unique_for_key(nonce_bytes);
+    let mut ciphertext = blob[NONCE_LEN..].to_vec();
+    let out = key
+        .open_in_place(nonce, Aad::empty(), &mut ciphertext)
+        .map_err(|_| CryptoError::Decrypt)?;
+    Ok(out.to_vec())
 }
 
 #[cfg(test)]
@@ -141,6 +594,6 @@ mod tests {
         // flip a bit
         let last = ct.len() - 1;
         ct[last] ^= 0x01;
-        assert!(km.decrypt(&ct).is_err());
+        assert!(matches!(km.decrypt(&ct), Err(CryptoError::Decrypt)));
     }
 }
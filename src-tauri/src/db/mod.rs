@@ -1,36 +1,194 @@
 use anyhow::Result;
 use rusqlite::{params, Connection, OptionalExtension};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Kinds whose content is UTF-8 text; detectors refine plain "text" into
+/// the more specific ones.
+pub const TEXT_KINDS: &[&str] = &["text", "url", "color", "code", "snippet"];
+
+pub fn is_text_kind(kind: &str) -> bool {
+    TEXT_KINDS.contains(&kind)
+}
+
+/// Longest tag accepted, in characters.
+pub const TAG_MAX_CHARS: usize = 64;
+// joins an item's tags in `ITEM_COLUMNS`; control characters never appear in tags
+const TAG_SEPARATOR: char = '\u{1f}';
+
+/// `tag` trimmed, or an error when it is empty, longer than
+/// `TAG_MAX_CHARS` or contains control characters.
+pub fn clean_tag(tag: &str) -> Result<&str> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err(anyhow::anyhow!("tags cannot be empty"));
+    }
+    if tag.chars().count() > TAG_MAX_CHARS {
+        return Err(anyhow::anyhow!("tags are limited to {TAG_MAX_CHARS} characters"));
+    }
+    if tag.contains(char::is_control) {
+        return Err(anyhow::anyhow!("tags cannot contain control characters"));
+    }
+    Ok(tag)
+}
+
 pub struct Database {
-    _path: PathBuf,
+    path: PathBuf,
     conn: parking_lot::Mutex<Connection>,
+    // Re-captures older than this create a new row; 0 = always dedup.
+    dedup_window_ms: AtomicI64,
+    dedup_by_app: AtomicBool,
+    // Writes since the last WAL checkpoint, and how many trigger one (0 = off).
+    writes: AtomicU64,
+    checkpoint_every: AtomicU64,
+    checkpoints: AtomicU64,
+    // Most items to keep per kind, enforced after each insert of that kind.
+    kind_limits: parking_lot::Mutex<HashMap<String, u32>>,
+    // Most unpinned items to keep overall, enforced after each insert (0 = no cap).
+    max_items: AtomicU64,
+}
+
+/// Writes between passive WAL checkpoints unless configured otherwise.
+pub const DEFAULT_CHECKPOINT_WRITES: u64 = 200;
+
+/// `purge_older_than` vacuums after removing more rows than this.
+const PURGE_VACUUM_ROWS: usize = 100;
+
+/// What makes two captures "the same" for dedup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DedupPolicy {
+    /// Content hash, kind and file path (default).
+    #[default]
+    Content,
+    /// Also the source app, so the same text from two apps is kept twice.
+    ContentAndApp,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct ItemDto {
     pub id: i64,
     pub created_at: i64,
-    pub kind: String, // "text" | "image" | "file"
+    pub kind: String, // "text" | "url" | "color" | "code" | "snippet" | "image" | "file" | "blob"
     pub size: i64,
     pub sha256_hex: String,
     pub file_path: Option<String>,
     pub is_pinned: bool,
+    /// Length of the decrypted content in bytes.
+    pub plaintext_len: i64,
+    /// Bytes the content occupies on disk (encrypted blob incl. nonce/tag).
+    pub stored_len: i64,
+    /// Masked in lists; content only comes back through an explicit reveal.
+    pub is_sensitive: bool,
+    /// Pasteboard type a "blob" item was captured as and is copied back under.
+    pub uti: Option<String>,
+    /// Image whose full content was dropped by `downgrade_image`; only the
+    /// thumbnail is left.
+    pub thumbnail_only: bool,
+    /// Encrypted path of a file item whose `file_path` is sealed; the API
+    /// decrypts it into `file_path` for the UI.
+    #[serde(skip)]
+    pub file_path_blob: Option<Vec<u8>>,
+    /// Encrypted user note, see `set_note`; the API decrypts it for the UI.
+    #[serde(skip)]
+    pub note_blob: Option<Vec<u8>>,
+    /// Image whose stored bytes failed to decode, see `set_image_corrupt`.
+    pub image_corrupt: bool,
+    /// Bundle id of the app the item was copied from, when known.
+    pub source_app: Option<String>,
+    /// User labels, sorted.
+    pub tags: Vec<String>,
     // note: encrypted blobs are not exposed to UI directly
 }
 
+/// Columns needed to build list previews, see `get_preview_sources`.
+#[derive(Debug, Clone)]
+pub struct PreviewSource {
+    pub id: i64,
+    pub kind: String,
+    pub content_blob: Option<Vec<u8>>,
+    pub file_path: Option<String>,
+    pub file_path_blob: Option<Vec<u8>>,
+    pub is_sensitive: bool,
+}
+
+/// Free and total bytes of a volume, see `disk_space`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct DiskSpace {
+    /// Bytes available to this user, not counting root-reserved blocks.
+    pub free_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// Space on the volume holding `path`.
+#[cfg(unix)]
+pub fn disk_space(path: &Path) -> Result<DiskSpace> {
+    use std::os::unix::ffi::OsStrExt;
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let mut st: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut st) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let block = st.f_frsize as u64;
+    Ok(DiskSpace { free_bytes: st.f_bavail as u64 * block, total_bytes: st.f_blocks as u64 * block })
+}
+
+#[cfg(not(unix))]
+pub fn disk_space(_path: &Path) -> Result<DiskSpace> {
+    Err(anyhow::anyhow!("not supported"))
+}
+
+/// Item counts per `size` bucket for one kind, see `size_histogram`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct SizeHistogram {
+    pub kind: String,
+    pub under_1kb: i64,
+    pub under_10kb: i64,
+    pub under_100kb: i64,
+    pub over_100kb: i64,
+    /// Sum of `size` over the kind.
+    pub total_bytes: i64,
+}
+
+/// Plaintext vs. stored content size for one kind, see `encryption_overhead`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct EncryptionOverhead {
+    pub kind: String,
+    /// Items with stored content; file references and thumbnail-only
+    /// images hold none and are not counted.
+    pub items: i64,
+    pub plaintext_bytes: i64,
+    pub stored_bytes: i64,
+    /// `stored_bytes - plaintext_bytes`: nonce and tag per item, less any
+    /// compression savings. Negative when compression wins.
+    pub overhead_bytes: i64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct EncryptionOverheadReport {
+    pub kinds: Vec<EncryptionOverhead>,
+    /// All kinds together, with `kind` "total".
+    pub total: EncryptionOverhead,
+}
+
 #[derive(Debug, Clone)]
 pub struct NewItem {
     pub kind: String,
     pub size: i64,
     pub sha256: Vec<u8>,
     pub file_path: Option<String>,
+    /// Encrypted path, set instead of `file_path` when paths are sealed.
+    pub file_path_blob: Option<Vec<u8>>, // nonce||ciphertext
     pub content_blob: Option<Vec<u8>>, // nonce||ciphertext
     pub preview_blob: Option<Vec<u8>>, // nonce||ciphertext
     pub rtf_blob: Option<Vec<u8>>,     // nonce||ciphertext
+    pub html_blob: Option<Vec<u8>>,    // nonce||ciphertext
+    pub source_app: Option<String>,    // bundle id of the app copied from
+    pub uti: Option<String>,           // pasteboard type of "blob" items
 }
 
 impl Database {
@@ -39,47 +197,245 @@ impl Database {
         let db_path = app_dir.join("cliper.sqlite");
         let conn = Connection::open(&db_path)?;
         conn.pragma_update(None, "journal_mode", &"WAL")?;
+        // deleted rows are zeroed rather than left readable in free pages
+        conn.pragma_update(None, "secure_delete", "ON")?;
         Ok(Self {
-            _path: db_path,
+            path: db_path,
             conn: parking_lot::Mutex::new(conn),
+            dedup_window_ms: AtomicI64::new(0),
+            dedup_by_app: AtomicBool::new(false),
+            writes: AtomicU64::new(0),
+            checkpoint_every: AtomicU64::new(DEFAULT_CHECKPOINT_WRITES),
+            checkpoints: AtomicU64::new(0),
+            kind_limits: parking_lot::Mutex::new(HashMap::new()),
+            max_items: AtomicU64::new(0),
         })
     }
 
-    pub fn migrate(&self) -> Result<()> {
-        let conn = self.conn.lock();
-        conn.execute_batch(
-            r#"
-            CREATE TABLE IF NOT EXISTS items (
-              id INTEGER PRIMARY KEY,
-              created_at INTEGER NOT NULL,
-              kind TEXT NOT NULL,
-              size INTEGER NOT NULL,
-              sha256 BLOB NOT NULL,
-              file_path TEXT,
-              is_pinned INTEGER NOT NULL DEFAULT 0,
-              content_blob BLOB,
-              preview_blob BLOB,
-              rtf_blob BLOB
+    /// In-memory database, the last resort when nothing on disk is writable.
+    pub fn in_memory() -> Result<Self> {
+        let conn = Connection::open_in_memory()?;
+        Ok(Self {
+            path: PathBuf::from(":memory:"),
+            conn: parking_lot::Mutex::new(conn),
+            dedup_window_ms: AtomicI64::new(0),
+            dedup_by_app: AtomicBool::new(false),
+            writes: AtomicU64::new(0),
+            checkpoint_every: AtomicU64::new(DEFAULT_CHECKPOINT_WRITES),
+            checkpoints: AtomicU64::new(0),
+            kind_limits: parking_lot::Mutex::new(HashMap::new()),
+            max_items: AtomicU64::new(0),
+        })
+    }
+
+    /// Opens and migrates the database in `app_dir`; if that directory is
+    /// read-only, full, or otherwise unusable, falls back to `fallback_dir`
+    /// and then to memory. The second value explains any fallback so the UI
+    /// can warn that history is not being kept in the usual place.
+    pub fn open_with_fallback(app_dir: PathBuf, fallback_dir: PathBuf) -> Result<(Self, Option<String>)> {
+        fn open(dir: PathBuf) -> Result<Database> {
+            let db = Database::new(dir)?;
+            db.migrate()?;
+            Ok(db)
+        }
+        let primary_err = match open(app_dir.clone()) {
+            Ok(db) => return Ok((db, None)),
+            Err(e) => e,
+        };
+        eprintln!("db init failed in {}: {primary_err:?}", app_dir.display());
+        if let Ok(db) = open(fallback_dir.clone()) {
+            let msg = format!(
+                "Data folder {} is not writable ({primary_err}); using temporary storage at {}",
+                app_dir.display(),
+                fallback_dir.display()
             );
-            CREATE INDEX IF NOT EXISTS idx_items_created ON items(created_at DESC);
-            CREATE INDEX IF NOT EXISTS idx_items_kind ON items(kind);
-            "#,
-        )?;
+            return Ok((db, Some(msg)));
+        }
+        let db = Self::in_memory()?;
+        db.migrate()?;
+        let msg = format!(
+            "Data folder {} is not writable ({primary_err}); history is kept in memory and lost on quit",
+            app_dir.display()
+        );
+        Ok((db, Some(msg)))
+    }
+
+    /// Database file; `:memory:` when running without storage.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Space on the volume holding the database; `None` when running
+    /// without storage.
+    pub fn disk_space(&self) -> Result<Option<DiskSpace>> {
+        if self.path == Path::new(":memory:") {
+            return Ok(None);
+        }
+        let dir = self.path.parent().unwrap_or(Path::new("/"));
+        Ok(Some(disk_space(dir)?))
+    }
+
+    /// Write-ahead log next to the database file, if there is one.
+    pub fn wal_path(&self) -> Option<PathBuf> {
+        if self.path == Path::new(":memory:") {
+            return None;
+        }
+        let mut wal = self.path.clone().into_os_string();
+        wal.push("-wal");
+        Some(PathBuf::from(wal))
+    }
+
+    /// Applies every migration newer than the stored `user_version`.
+    pub fn migrate(&self) -> Result<()> {
+        let mut conn = self.conn.lock();
+        apply_pending(&mut conn, &mut Vec::new())?;
         Ok(())
     }
 
+    /// Support action for databases left half-upgraded (e.g. after a crash).
+    ///
+    /// Runs pending migrations, then re-runs every migration step; each step
+    /// is idempotent and only creates what is missing, so this never drops
+    /// data and is safe to repeat. Returns a description of each fix made.
+    pub fn repair_schema(&self) -> Result<Vec<String>> {
+        let mut conn = self.conn.lock();
+        let mut fixes = Vec::new();
+        apply_pending(&mut conn, &mut fixes)?;
+        let tx = conn.transaction()?;
+        for step in MIGRATIONS {
+            step(&tx, &mut fixes)?;
+        }
+        tx.commit()?;
+        Ok(fixes)
+    }
+
+    /// Limits dedup to re-captures within `minutes` of the existing row, so
+    /// content copied again later shows up as a new timeline entry.
+    /// `0` keeps the default of always deduplicating.
+    pub fn set_dedup_window(&self, minutes: u64) {
+        let ms = minutes.saturating_mul(60_000).min(i64::MAX as u64) as i64;
+        self.dedup_window_ms.store(ms, Ordering::Relaxed);
+    }
+
+    pub fn set_dedup_policy(&self, policy: DedupPolicy) {
+        self.dedup_by_app.store(policy == DedupPolicy::ContentAndApp, Ordering::Relaxed);
+    }
+
+    pub fn dedup_policy(&self) -> DedupPolicy {
+        if self.dedup_by_app.load(Ordering::Relaxed) {
+            DedupPolicy::ContentAndApp
+        } else {
+            DedupPolicy::Content
+        }
+    }
+
+    /// Runs a passive WAL checkpoint after every `writes` inserts, pins and
+    /// deletes, so the WAL grows with activity rather than time. `0` turns
+    /// write-driven checkpoints off (SQLite's own auto-checkpoint remains).
+    pub fn set_checkpoint_every(&self, writes: u64) {
+        self.checkpoint_every.store(writes, Ordering::Relaxed);
+    }
+
+    /// Caps how many unpinned items of each kind are kept, e.g. `{"image": 50}`;
+    /// kinds not in the map (or mapped to 0) are unlimited. Applies the new
+    /// caps right away and returns how many rows that removed.
+    pub fn set_kind_limits(&self, limits: HashMap<String, u32>) -> Result<usize> {
+        let limits: HashMap<String, u32> = limits.into_iter().filter(|(_, max)| *max > 0).collect();
+        let mut removed = 0;
+        for (kind, max) in &limits {
+            removed += self.prune_kind_to_limit(kind, *max, true)?;
+        }
+        *self.kind_limits.lock() = limits;
+        Ok(removed)
+    }
+
+    /// Caps how many unpinned items are kept in total; `None` keeps all.
+    /// Applies the cap right away and returns how many rows that removed.
+    pub fn set_max_items(&self, max: Option<u64>) -> Result<usize> {
+        let max = max.unwrap_or(0);
+        self.max_items.store(max, Ordering::Relaxed);
+        if max == 0 {
+            return Ok(0);
+        }
+        self.trim_to_limit(max)
+    }
+
+    /// Deletes the oldest unpinned items beyond the newest `max` and returns
+    /// how many were removed. Pinned items are neither counted nor deleted.
+    pub fn trim_to_limit(&self, max: u64) -> Result<usize> {
+        let conn = self.conn.lock();
+        let removed = trim_items(&conn, max)?;
+        if removed > 0 {
+            self.note_write(&conn);
+        }
+        Ok(removed)
+    }
+
+    /// Deletes the oldest items of `kind` beyond the newest `max`. With
+    /// `keep_pinned`, pinned items are neither counted nor deleted.
+    pub fn prune_kind_to_limit(&self, kind: &str, max: u32, keep_pinned: bool) -> Result<usize> {
+        let conn = self.conn.lock();
+        let removed = prune_kind(&conn, kind, max, keep_pinned)?;
+        if removed > 0 {
+            self.note_write(&conn);
+        }
+        Ok(removed)
+    }
+
+    /// Deletes unpinned items created before `cutoff` (unix millis) and
+    /// returns how many were removed. Large purges are followed by a VACUUM
+    /// so the file actually shrinks.
+    pub fn purge_older_than(&self, cutoff: i64) -> Result<usize> {
+        let conn = self.conn.lock();
+        let removed = conn.execute("DELETE FROM items WHERE is_pinned = 0 AND created_at < ?1", params![cutoff])?;
+        if removed > 0 {
+            self.note_write(&conn);
+        }
+        if removed > PURGE_VACUUM_ROWS {
+            conn.execute_batch("VACUUM")?;
+        }
+        Ok(removed)
+    }
+
+    /// Write-driven checkpoints run since opening.
+    #[allow(dead_code)]
+    pub fn checkpoints_run(&self) -> u64 {
+        self.checkpoints.load(Ordering::Relaxed)
+    }
+
+    // Called with the connection still locked after each counted write.
+    fn note_write(&self, conn: &Connection) {
+        let every = self.checkpoint_every.load(Ordering::Relaxed);
+        if every == 0 || self.writes.fetch_add(1, Ordering::Relaxed) + 1 < every {
+            return;
+        }
+        self.writes.store(0, Ordering::Relaxed);
+        // PASSIVE never blocks readers or writers; a busy result is fine
+        if let Err(e) = conn.query_row("PRAGMA wal_checkpoint(PASSIVE)", [], |_| Ok(())) {
+            eprintln!("wal checkpoint failed: {e}");
+            return;
+        }
+        self.checkpoints.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn insert_item(&self, item: NewItem) -> Result<i64> {
-        // Deduplicate by sha256 + kind + file_path
-        let maybe = self.find_by_hash_kind_path(&item.sha256, &item.kind, item.file_path.as_deref())?;
-        if let Some(id) = maybe {
-            return Ok(id);
+        // Deduplicate per the dedup policy, within the dedup window
+        let maybe = self.find_existing(&item, self.dedup_policy())?;
+        if let Some((id, created_at)) = maybe {
+            let window = self.dedup_window_ms.load(Ordering::Relaxed);
+            if window == 0 || now_millis() - created_at <= window {
+                // copied again: back to the top, like a fresh capture
+                self.bump_created_at(id)?;
+                return Ok(id);
+            }
         }
 
         let ts = now_millis();
+        let stored_len = item.content_blob.as_ref().map_or(0, |b| b.len() as i64);
         let conn = self.conn.lock();
         conn.execute(
-            "INSERT INTO items (created_at, kind, size, sha256, file_path, is_pinned, content_blob, preview_blob, rtf_blob)
-             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?7, ?8)",
+            "INSERT INTO items (created_at, kind, size, sha256, file_path, is_pinned, content_blob, preview_blob, rtf_blob, plaintext_len, stored_len, source_app, uti, file_path_blob, html_blob, seq)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, (SELECT IFNULL(MAX(seq), 0) + 1 FROM items))",
             params![
                 ts,
                 item.kind,
@@ -88,22 +444,70 @@ impl Database {
                 item.file_path,
                 item.content_blob,
                 item.preview_blob,
-                item.rtf_blob
+                item.rtf_blob,
+                item.size,
+                stored_len,
+                item.source_app,
+                item.uti,
+                item.file_path_blob,
+                item.html_blob
             ],
         )?;
-        Ok(conn.last_insert_rowid())
+        let id = conn.last_insert_rowid();
+        let limit = self.kind_limits.lock().get(&item.kind).copied();
+        if let Some(max) = limit {
+            prune_kind(&conn, &item.kind, max, true)?;
+        }
+        let max_items = self.max_items.load(Ordering::Relaxed);
+        if max_items > 0 {
+            trim_items(&conn, max_items)?;
+        }
+        self.note_write(&conn);
+        Ok(id)
     }
 
-    pub fn find_by_hash_kind_path(
-        &self,
-        sha256: &[u8],
-        kind: &str,
-        file_path: Option<&str>,
-    ) -> Result<Option<i64>> {
+    /// Restamps item `id` as captured now and moves it to the top of
+    /// `list_recent`. Pinned items keep their place among pins, which are
+    /// ordered by `pin_order`. Not counted as a write for checkpointing.
+    pub fn bump_created_at(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "UPDATE items SET created_at = ?2, seq = (SELECT IFNULL(MAX(seq), 0) + 1 FROM items) WHERE id = ?1",
+            params![id, now_millis()],
+        )?;
+        Ok(())
+    }
+
+    /// Latest row that `item` duplicates under `policy`, as `(id, created_at)`.
+    /// File items match on `sha256`, the hash of their path, since the path
+    /// itself may be sealed.
+    pub fn find_existing(&self, item: &NewItem, policy: DedupPolicy) -> Result<Option<(i64, i64)>> {
+        let by_app = policy == DedupPolicy::ContentAndApp;
+        let conn = self.conn.lock();
+        let row = conn
+            .query_row(
+                "SELECT id, created_at FROM items
+                 WHERE sha256 = ?1 AND kind = ?2 AND (kind = 'file' OR IFNULL(file_path,'') = IFNULL(?3,''))
+                   AND (?4 = 0 OR IFNULL(source_app,'') = IFNULL(?5,''))
+                   AND IFNULL(uti,'') = IFNULL(?6,'')
+                   AND thumbnail_only = 0
+                 ORDER BY id DESC LIMIT 1",
+                params![item.sha256, item.kind, item.file_path, by_app, item.source_app, item.uti],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(row)
+    }
+
+    /// Newest item with this content hash, kind and file path, regardless of
+    /// dedup window or source app. Files match on the path hash alone.
+    pub fn find_by_hash_kind_path(&self, sha256: &[u8], kind: &str, file_path: Option<&str>) -> Result<Option<i64>> {
         let conn = self.conn.lock();
-        let id: Option<i64> = conn
+        let id = conn
             .query_row(
-                "SELECT id FROM items WHERE sha256 = ?1 AND kind = ?2 AND IFNULL(file_path,'') = IFNULL(?3,'') ORDER BY id DESC LIMIT 1",
+                "SELECT id FROM items
+                 WHERE sha256 = ?1 AND kind = ?2 AND (kind = 'file' OR IFNULL(file_path,'') = IFNULL(?3,''))
+                 ORDER BY id DESC LIMIT 1",
                 params![sha256, kind, file_path],
                 |row| row.get(0),
             )
@@ -113,24 +517,216 @@ impl Database {
 
     pub fn list_recent(&self, limit: u32) -> Result<Vec<ItemDto>> {
         let conn = self.conn.lock();
-        let mut stmt = conn.prepare(
-            "SELECT id, created_at, kind, size, sha256, file_path, is_pinned FROM items ORDER BY is_pinned DESC, created_at DESC LIMIT ?1",
-        )?;
-        let rows = stmt.query_map(params![limit as i64], |row| {
-            let sha: Vec<u8> = row.get(4)?;
-            Ok(ItemDto {
-                id: row.get(0)?,
-                created_at: row.get(1)?,
-                kind: row.get::<_, String>(2)?,
-                size: row.get(3)?,
-                sha256_hex: hex::encode(sha),
-                file_path: row.get(5)?,
-                is_pinned: row.get::<_, i64>(6)? != 0,
-            })
-        })?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items ORDER BY is_pinned DESC, pin_order DESC, seq DESC LIMIT ?1"
+        ))?;
+        let rows = stmt.query_map(params![limit as i64], row_to_item)?;
         Ok(rows.filter_map(Result::ok).collect())
     }
 
+    /// Labels item `id` with `tag` (see `clean_tag`); tags differing only in
+    /// ASCII case are the same tag. Returns false if it already had it.
+    pub fn add_tag(&self, id: i64, tag: &str) -> Result<bool> {
+        let tag = clean_tag(tag)?;
+        let conn = self.conn.lock();
+        let exists: bool = conn.query_row("SELECT EXISTS(SELECT 1 FROM items WHERE id = ?1)", params![id], |row| row.get(0))?;
+        if !exists {
+            return Err(anyhow::anyhow!("item {id} not found"));
+        }
+        let added = conn.execute("INSERT OR IGNORE INTO item_tags (item_id, tag) VALUES (?1, ?2)", params![id, tag])? > 0;
+        self.note_write(&conn);
+        Ok(added)
+    }
+
+    /// Removes `tag` from item `id`; false if it did not have it.
+    pub fn remove_tag(&self, id: i64, tag: &str) -> Result<bool> {
+        let conn = self.conn.lock();
+        let removed = conn.execute("DELETE FROM item_tags WHERE item_id = ?1 AND tag = ?2", params![id, tag.trim()])? > 0;
+        self.note_write(&conn);
+        Ok(removed)
+    }
+
+    /// Tags of item `id`, sorted.
+    pub fn list_tags(&self, id: i64) -> Result<Vec<String>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare_cached("SELECT tag FROM item_tags WHERE item_id = ?1 ORDER BY tag")?;
+        let tags = stmt.query_map(params![id], |row| row.get(0))?.collect::<rusqlite::Result<Vec<String>>>()?;
+        Ok(tags)
+    }
+
+    /// Items labeled `tag`, in `list_recent` order.
+    pub fn list_tagged(&self, tag: &str, limit: u32) -> Result<Vec<ItemDto>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+             WHERE id IN (SELECT item_id FROM item_tags WHERE tag = ?1)
+             ORDER BY is_pinned DESC, pin_order DESC, seq DESC LIMIT ?2"
+        ))?;
+        let rows = stmt.query_map(params![tag.trim(), limit as i64], row_to_item)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    /// Pinned items in pin order, the top of every list.
+    pub fn list_pinned(&self) -> Result<Vec<ItemDto>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!("SELECT {ITEM_COLUMNS} FROM items WHERE is_pinned = 1 ORDER BY pin_order DESC, seq DESC"))?;
+        let rows = stmt.query_map([], row_to_item)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    /// Unpinned items listed below `seq`, newest first in `list_recent`
+    /// order, each with its own `seq` to continue from.
+    pub fn list_before(&self, seq: i64, limit: u32) -> Result<Vec<(i64, ItemDto)>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS}, seq FROM items
+             WHERE is_pinned = 0 AND seq < ?1
+             ORDER BY seq DESC LIMIT ?2"
+        ))?;
+        let rows = stmt.query_map(params![seq, limit as i64], |row| Ok((row.get(17)?, row_to_item(row)?)))?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    /// Oldest items first, for reviewing the bottom of history. Pinned items
+    /// are left out, or with `include_pinned` listed after all unpinned ones.
+    pub fn list_oldest(&self, limit: u32, include_pinned: bool) -> Result<Vec<ItemDto>> {
+        let conn = self.conn.lock();
+        let filter = if include_pinned { "" } else { "WHERE is_pinned = 0" };
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items {filter} ORDER BY is_pinned ASC, created_at ASC, id ASC LIMIT ?1"
+        ))?;
+        let rows = stmt.query_map(params![limit as i64], row_to_item)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    /// All file items, pinned first then newest first.
+    pub fn list_files(&self) -> Result<Vec<ItemDto>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items WHERE kind = 'file' ORDER BY is_pinned DESC, pin_order DESC, seq DESC"
+        ))?;
+        let rows = stmt.query_map([], row_to_item)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    /// Items of at least `min_bytes` (by the `size` column), largest first,
+    /// optionally of one kind; `"text"` covers every text kind.
+    pub fn list_large(&self, min_bytes: i64, kind: Option<&str>, limit: u32) -> Result<Vec<ItemDto>> {
+        let text_kinds = TEXT_KINDS.iter().map(|k| format!("'{k}'")).collect::<Vec<_>>().join(", ");
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+             WHERE size >= ?1 AND (?2 IS NULL OR kind = ?2 OR (?2 = 'text' AND kind IN ({text_kinds})))
+             ORDER BY size DESC, seq DESC LIMIT ?3"
+        ))?;
+        let rows = stmt.query_map(params![min_bytes, kind, limit as i64], row_to_item)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Items with `id > after_id`, oldest first: a client holding everything
+    /// up to `after_id` fetches only what is new. Ids only grow, so unlike
+    /// timestamps this is immune to clock changes.
+    pub fn list_since(&self, after_id: i64, limit: u32) -> Result<Vec<ItemDto>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare_cached(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items WHERE id > ?1 ORDER BY id LIMIT ?2"
+        ))?;
+        let rows = stmt.query_map(params![after_id, limit as i64], row_to_item)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Walks every item in id order, `batch` rows per query, calling `f` for
+    /// each; `f` returns `Ok(false)` to stop early. Only one batch is held
+    /// in memory and the connection lock is released while `f` runs, so `f`
+    /// may call back into the database. Returns the number of items visited.
+    pub fn for_each_item<F>(&self, batch: u32, mut f: F) -> Result<usize>
+    where
+        F: FnMut(ItemDto) -> Result<bool>,
+    {
+        let mut after_id = 0i64;
+        let mut visited = 0;
+        loop {
+            let page = self.list_since(after_id, batch.max(1))?;
+            let Some(last) = page.last() else { return Ok(visited) };
+            after_id = last.id;
+            for it in page {
+                visited += 1;
+                if !f(it)? {
+                    return Ok(visited);
+                }
+            }
+        }
+    }
+
+    /// Items that have been copied back at least once, most recent first.
+    pub fn list_recently_pasted(&self, limit: u32) -> Result<Vec<ItemDto>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items WHERE copy_count > 0 ORDER BY last_copied_at DESC LIMIT ?1"
+        ))?;
+        let rows = stmt.query_map(params![limit as i64], row_to_item)?;
+        Ok(rows.filter_map(Result::ok).collect())
+    }
+
+    /// Bumps the paste counters after an item was copied back.
+    pub fn record_copy(&self, id: i64) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "UPDATE items SET copy_count = copy_count + 1, last_copied_at = ?2 WHERE id = ?1",
+            params![id, now_millis()],
+        )?;
+        Ok(())
+    }
+
+    /// Preview inputs for many rows under one lock; unknown ids are skipped.
+    pub fn get_preview_sources(&self, ids: &[i64]) -> Result<Vec<PreviewSource>> {
+        let conn = self.conn.lock();
+        let mut out = Vec::with_capacity(ids.len());
+        // stay well under SQLite's bound-parameter limit
+        for chunk in ids.chunks(500) {
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let mut stmt = conn.prepare(&format!(
+                "SELECT id, kind, content_blob, file_path, is_sensitive, file_path_blob FROM items WHERE id IN ({placeholders})"
+            ))?;
+            let rows = stmt.query_map(rusqlite::params_from_iter(chunk), |row| {
+                Ok(PreviewSource {
+                    id: row.get(0)?,
+                    kind: row.get(1)?,
+                    content_blob: row.get(2)?,
+                    file_path: row.get(3)?,
+                    file_path_blob: row.get(5)?,
+                    is_sensitive: row.get::<_, i64>(4)? != 0,
+                })
+            })?;
+            for r in rows {
+                out.push(r?);
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn get_item(&self, id: i64) -> Result<ItemDto> {
+        let conn = self.conn.lock();
+        let item = conn.query_row(&format!("SELECT {ITEM_COLUMNS} FROM items WHERE id = ?1"), params![id], row_to_item)?;
+        Ok(item)
+    }
+
+    /// Items for `ids` in the order given, fetched in one query per 500 ids;
+    /// unknown ids are skipped and repeated ones returned once.
+    pub fn get_items(&self, ids: &[i64]) -> Result<Vec<ItemDto>> {
+        let conn = self.conn.lock();
+        let mut found = HashMap::with_capacity(ids.len());
+        for chunk in ids.chunks(500) {
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let mut stmt = conn.prepare(&format!("SELECT {ITEM_COLUMNS} FROM items WHERE id IN ({placeholders})"))?;
+            for item in stmt.query_map(rusqlite::params_from_iter(chunk), row_to_item)? {
+                let item = item?;
+                found.insert(item.id, item);
+            }
+        }
+        Ok(ids.iter().filter_map(|id| found.remove(id)).collect())
+    }
+
     pub fn get_item_raw(&self, id: i64) -> Result<(String, Option<Vec<u8>>, Option<Vec<u8>>, Option<Vec<u8>>, Option<String>)> {
         let conn = self.conn.lock();
         let row: (String, Option<Vec<u8>>, Option<Vec<u8>>, Option<Vec<u8>>, Option<String>) = conn
@@ -142,25 +738,399 @@ impl Database {
         Ok(row)
     }
 
+    /// Encrypted HTML captured alongside a text item, if any.
+    pub fn get_html_blob(&self, id: i64) -> Result<Option<Vec<u8>>> {
+        let conn = self.conn.lock();
+        Ok(conn.query_row("SELECT html_blob FROM items WHERE id = ?1", params![id], |row| row.get(0))?)
+    }
+
+    /// Sealed path of a file item, if its path is stored encrypted.
+    pub fn get_file_path_blob(&self, id: i64) -> Result<Option<Vec<u8>>> {
+        let conn = self.conn.lock();
+        Ok(conn.query_row("SELECT file_path_blob FROM items WHERE id = ?1", params![id], |row| row.get(0))?)
+    }
+
+    /// File items whose path is still stored in plaintext.
+    pub fn plaintext_file_paths(&self) -> Result<Vec<(i64, String)>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare("SELECT id, file_path FROM items WHERE kind = 'file' AND file_path IS NOT NULL ORDER BY id")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Replaces a file item's plaintext path with `blob`, its encryption.
+    pub fn seal_file_path(&self, id: i64, blob: &[u8]) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "UPDATE items SET file_path_blob = ?2, file_path = NULL WHERE id = ?1 AND kind = 'file'",
+            params![id, blob],
+        )?;
+        self.note_write(&conn);
+        Ok(())
+    }
+
+    /// Stores encrypted content for a file item so it outlives the original.
+    pub fn set_file_content(&self, id: i64, content_blob: &[u8], plaintext_len: i64) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "UPDATE items SET content_blob = ?2, size = ?3, plaintext_len = ?3, stored_len = ?4 WHERE id = ?1 AND kind = 'file'",
+            params![id, content_blob, plaintext_len, content_blob.len() as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Encrypted content of the newest item that has any, for health checks.
+    pub fn latest_content_blob(&self) -> Result<Option<Vec<u8>>> {
+        let conn = self.conn.lock();
+        let blob = conn
+            .query_row(
+                "SELECT content_blob FROM items WHERE content_blob IS NOT NULL ORDER BY seq DESC LIMIT 1",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(blob)
+    }
+
+    pub fn set_preview(&self, id: i64, preview_blob: Option<&[u8]>) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute("UPDATE items SET preview_blob = ?2 WHERE id = ?1", params![id, preview_blob])?;
+        Ok(())
+    }
+
+    /// Drops every cached thumbnail, e.g. after the thumbnail settings change.
+    pub fn clear_previews(&self) -> Result<()> {
+        let conn = self.conn.lock();
+        // thumbnail-only images have nothing to regenerate from
+        conn.execute("UPDATE items SET preview_blob = NULL WHERE preview_blob IS NOT NULL AND thumbnail_only = 0", [])?;
+        Ok(())
+    }
+
+    /// Unpinned images captured before `cutoff` (ms since epoch) that still
+    /// hold their full content, oldest first.
+    pub fn images_to_downgrade(&self, cutoff: i64) -> Result<Vec<i64>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT id FROM items
+             WHERE kind = 'image' AND is_pinned = 0 AND thumbnail_only = 0 AND content_blob IS NOT NULL AND created_at < ?1
+             ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![cutoff], |row| row.get(0))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Replaces an image's full content with `preview_blob` (its encrypted
+    /// thumbnail) and marks it thumbnail-only.
+    pub fn downgrade_image(&self, id: i64, preview_blob: &[u8]) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "UPDATE items SET content_blob = NULL, preview_blob = ?2, stored_len = 0, thumbnail_only = 1
+             WHERE id = ?1 AND kind = 'image'",
+            params![id, preview_blob],
+        )?;
+        self.note_write(&conn);
+        Ok(())
+    }
+
+    /// Newly pinned items go to the top of the pinned group; pinning an
+    /// already pinned item keeps its place.
     pub fn pin_item(&self, id: i64, pin: bool) -> Result<()> {
+        let conn = self.conn.lock();
+        if pin {
+            conn.execute(
+                "UPDATE items SET is_pinned = 1,
+                        pin_order = (SELECT IFNULL(MAX(pin_order), 0) + 1 FROM items WHERE is_pinned = 1)
+                 WHERE id = ?1 AND is_pinned = 0",
+                params![id],
+            )?;
+        } else {
+            conn.execute("UPDATE items SET is_pinned = 0, pin_order = 0 WHERE id = ?1", params![id])?;
+        }
+        self.note_write(&conn);
+        Ok(())
+    }
+
+    /// Exchanges the positions of two pinned items in one transaction, for
+    /// drag and drop. Fails, changing nothing, unless both are pinned.
+    pub fn swap_pin_order(&self, id_a: i64, id_b: i64) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let order = |id: i64| -> Result<i64> {
+            tx.query_row("SELECT pin_order FROM items WHERE id = ?1 AND is_pinned = 1", params![id], |row| row.get(0))
+                .optional()?
+                .ok_or_else(|| anyhow::anyhow!("item {id} is not pinned"))
+        };
+        let (a, b) = (order(id_a)?, order(id_b)?);
+        tx.execute("UPDATE items SET pin_order = ?2 WHERE id = ?1", params![id_a, b])?;
+        tx.execute("UPDATE items SET pin_order = ?2 WHERE id = ?1", params![id_b, a])?;
+        tx.commit()?;
+        self.note_write(&conn);
+        Ok(())
+    }
+
+    pub fn set_sensitive(&self, id: i64, sensitive: bool) -> Result<()> {
         let conn = self.conn.lock();
         conn.execute(
-            "UPDATE items SET is_pinned = ?2 WHERE id = ?1",
-            params![id, if pin { 1 } else { 0 }],
+            "UPDATE items SET is_sensitive = ?2 WHERE id = ?1",
+            params![id, if sensitive { 1 } else { 0 }],
+        )?;
+        if sensitive {
+            conn.execute("DELETE FROM items_fts WHERE rowid = ?1", params![id])?;
+            scrub_search_index(&conn)?;
+        }
+        Ok(())
+    }
+
+    /// Puts the decrypted `text` of item `id` in the search index,
+    /// replacing what was indexed for it before.
+    pub fn index_text(&self, id: i64, text: &str) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute("DELETE FROM items_fts WHERE rowid = ?1", params![id])?;
+        conn.execute("INSERT INTO items_fts (rowid, text) VALUES (?1, ?2)", params![id, text])?;
+        Ok(())
+    }
+
+    /// Text items not in the search index yet, newest first. Sensitive
+    /// items are never indexed.
+    pub fn unindexed_text_ids(&self, limit: u32) -> Result<Vec<i64>> {
+        let text_kinds = TEXT_KINDS.iter().map(|k| format!("'{k}'")).collect::<Vec<_>>().join(", ");
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id FROM items
+             WHERE kind IN ({text_kinds}) AND is_sensitive = 0 AND content_blob IS NOT NULL
+               AND id NOT IN (SELECT rowid FROM items_fts)
+             ORDER BY seq DESC LIMIT ?1"
+        ))?;
+        let ids = stmt.query_map(params![limit], |row| row.get(0))?.collect::<rusqlite::Result<Vec<i64>>>()?;
+        Ok(ids)
+    }
+
+    /// Ids of indexed items whose text contains `query`, ignoring case, in
+    /// `list_recent` order. Queries under three characters are too short for
+    /// the trigram index and scan it instead.
+    pub fn search_fts(&self, query: &str, limit: u32) -> Result<Vec<i64>> {
+        let (filter, pattern) = if query.chars().count() >= 3 {
+            ("items_fts MATCH ?1", format!("\"{}\"", query.replace('"', "\"\"")))
+        } else {
+            let escaped = query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+            ("items_fts.text LIKE ?1 ESCAPE '\\'", format!("%{escaped}%"))
+        };
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT items.id FROM items_fts JOIN items ON items.id = items_fts.rowid
+             WHERE {filter}
+             ORDER BY items.is_pinned DESC, items.pin_order DESC, items.seq DESC LIMIT ?2"
+        ))?;
+        let ids = stmt.query_map(params![pattern, limit], |row| row.get(0))?.collect::<rusqlite::Result<Vec<i64>>>()?;
+        Ok(ids)
+    }
+
+    /// Empties the search index, dropping the plaintext it holds from the
+    /// database file and the WAL.
+    pub fn clear_search_index(&self) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute("DELETE FROM items_fts", [])?;
+        scrub_search_index(&conn)?;
+        Ok(())
+    }
+
+    /// Flags an image whose stored bytes could not be decoded, so lists can
+    /// show it as corrupt instead of an empty preview.
+    pub fn set_image_corrupt(&self, id: i64, corrupt: bool) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute(
+            "UPDATE items SET image_corrupt = ?2 WHERE id = ?1 AND kind = 'image'",
+            params![id, if corrupt { 1 } else { 0 }],
+        )?;
+        Ok(())
+    }
+
+    /// Sets or clears (`None`) the encrypted note of an item. The note lives
+    /// on the item row, so it goes away when the item is deleted.
+    pub fn set_note(&self, id: i64, note_blob: Option<&[u8]>) -> Result<()> {
+        let conn = self.conn.lock();
+        let n = conn.execute("UPDATE items SET note_blob = ?2 WHERE id = ?1", params![id, note_blob])?;
+        if n == 0 {
+            return Err(anyhow::anyhow!("item {id} not found"));
+        }
+        Ok(())
+    }
+
+    /// Bumps the audit counter for a reveal and returns the new total.
+    pub fn record_reveal(&self, id: i64) -> Result<i64> {
+        let conn = self.conn.lock();
+        conn.execute("UPDATE items SET reveal_count = reveal_count + 1 WHERE id = ?1", params![id])?;
+        let count = conn.query_row("SELECT reveal_count FROM items WHERE id = ?1", params![id], |row| row.get(0))?;
+        Ok(count)
+    }
+
+    pub fn set_kind(&self, id: i64, kind: &str) -> Result<()> {
+        let conn = self.conn.lock();
+        conn.execute("UPDATE items SET kind = ?2 WHERE id = ?1", params![id, kind])?;
+        Ok(())
+    }
+
+    /// Manual override of a detected kind. Only moves between text kinds;
+    /// images, files and blobs are stored differently and keep theirs.
+    pub fn relabel_item(&self, id: i64, kind: &str) -> Result<()> {
+        if !is_text_kind(kind) {
+            return Err(anyhow::anyhow!("{kind} is not a kind items can be relabeled to"));
+        }
+        let current = self.get_item(id)?.kind;
+        if !is_text_kind(&current) {
+            return Err(anyhow::anyhow!("{current} items cannot be relabeled"));
+        }
+        self.set_kind(id, kind)
+    }
+
+    pub fn count_items(&self) -> Result<i64> {
+        let conn = self.conn.lock();
+        Ok(conn.query_row("SELECT COUNT(*) FROM items", [], |row| row.get(0))?)
+    }
+
+    /// Number of items with an id above `after_id`.
+    pub fn count_after(&self, after_id: i64) -> Result<i64> {
+        let conn = self.conn.lock();
+        Ok(conn.query_row("SELECT COUNT(*) FROM items WHERE id > ?1", params![after_id], |row| row.get(0))?)
+    }
+
+    /// Highest item id, or 0 when the history is empty.
+    pub fn max_id(&self) -> Result<i64> {
+        let conn = self.conn.lock();
+        Ok(conn.query_row("SELECT COALESCE(MAX(id), 0) FROM items", [], |row| row.get(0))?)
+    }
+
+    pub fn count_kind(&self, kind: &str) -> Result<i64> {
+        let conn = self.conn.lock();
+        Ok(conn.query_row("SELECT COUNT(*) FROM items WHERE kind = ?1", params![kind], |row| row.get(0))?)
+    }
+
+    /// Size distribution per kind, from the `size` column alone (no
+    /// decryption). Buckets are <1 KB, 1–10 KB, 10–100 KB and >=100 KB.
+    pub fn size_histogram(&self) -> Result<Vec<SizeHistogram>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT kind,
+                    SUM(size < 1024),
+                    SUM(size >= 1024 AND size < 10240),
+                    SUM(size >= 10240 AND size < 102400),
+                    SUM(size >= 102400),
+                    SUM(size)
+             FROM items GROUP BY kind ORDER BY kind",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(SizeHistogram {
+                kind: row.get(0)?,
+                under_1kb: row.get(1)?,
+                under_10kb: row.get(2)?,
+                under_100kb: row.get(3)?,
+                over_100kb: row.get(4)?,
+                total_bytes: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Content size before and after encryption per kind, from the
+    /// `plaintext_len`/`stored_len` columns (no decryption). Previews,
+    /// RTF/HTML and notes are not included.
+    pub fn encryption_overhead(&self) -> Result<EncryptionOverheadReport> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare(
+            "SELECT kind, COUNT(*), SUM(plaintext_len), SUM(stored_len)
+             FROM items WHERE content_blob IS NOT NULL GROUP BY kind ORDER BY kind",
         )?;
+        let rows = stmt.query_map([], |row| {
+            let (plaintext_bytes, stored_bytes): (i64, i64) = (row.get(2)?, row.get(3)?);
+            Ok(EncryptionOverhead {
+                kind: row.get(0)?,
+                items: row.get(1)?,
+                plaintext_bytes,
+                stored_bytes,
+                overhead_bytes: stored_bytes - plaintext_bytes,
+            })
+        })?;
+        let kinds: Vec<EncryptionOverhead> = rows.collect::<rusqlite::Result<_>>()?;
+        let mut total = EncryptionOverhead { kind: "total".into(), ..Default::default() };
+        for k in &kinds {
+            total.items += k.items;
+            total.plaintext_bytes += k.plaintext_bytes;
+            total.stored_bytes += k.stored_bytes;
+            total.overhead_bytes += k.overhead_bytes;
+        }
+        Ok(EncryptionOverheadReport { kinds, total })
+    }
+
+    /// Passes every encrypted blob (content, previews, rich text, HTML,
+    /// sealed paths, notes and extra types) through `recode` and writes the
+    /// results back in one transaction, running `before_commit` last. Any
+    /// error rolls all of it back. Returns how many blobs were rewritten.
+    pub fn reencrypt_all(
+        &self,
+        mut recode: impl FnMut(&[u8]) -> Result<Vec<u8>>,
+        before_commit: impl FnOnce() -> Result<()>,
+    ) -> Result<usize> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        let mut rewritten = 0;
+        for (table, key, column) in ENCRYPTED_COLUMNS {
+            // one blob in memory at a time; images can be large
+            let keys: Vec<i64> = tx
+                .prepare(&format!("SELECT {key} FROM {table} WHERE {column} IS NOT NULL"))?
+                .query_map([], |row| row.get(0))?
+                .collect::<rusqlite::Result<_>>()?;
+            for k in keys {
+                let blob: Vec<u8> = tx.query_row(&format!("SELECT {column} FROM {table} WHERE {key} = ?1"), params![k], |row| row.get(0))?;
+                tx.execute(&format!("UPDATE {table} SET {column} = ?1 WHERE {key} = ?2"), params![recode(&blob)?, k])?;
+                rewritten += 1;
+            }
+        }
+        before_commit()?;
+        tx.commit()?;
+        Ok(rewritten)
+    }
+
+    /// Replaces the extra pasteboard types stored for an item with `types`,
+    /// as (UTI, encrypted data) pairs.
+    pub fn set_extra_types(&self, item_id: i64, types: &[(String, Vec<u8>)]) -> Result<()> {
+        let mut conn = self.conn.lock();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM extra_types WHERE item_id = ?1", params![item_id])?;
+        for (uti, blob) in types {
+            tx.execute(
+                "INSERT OR REPLACE INTO extra_types (item_id, uti, data_blob) VALUES (?1, ?2, ?3)",
+                params![item_id, uti, blob],
+            )?;
+        }
+        tx.commit()?;
         Ok(())
     }
 
+    /// Extra pasteboard types of an item as (UTI, encrypted data), in the
+    /// order they were captured.
+    pub fn get_extra_types(&self, item_id: i64) -> Result<Vec<(String, Vec<u8>)>> {
+        let conn = self.conn.lock();
+        let mut stmt = conn.prepare("SELECT uti, data_blob FROM extra_types WHERE item_id = ?1 ORDER BY rowid")?;
+        let rows = stmt.query_map(params![item_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
     pub fn delete_item(&self, id: i64) -> Result<()> {
         let conn = self.conn.lock();
         conn.execute("DELETE FROM items WHERE id = ?1", params![id])?;
+        self.note_write(&conn);
         Ok(())
     }
 
-    #[allow(dead_code)]
+    /// Deletes all history for a wipe, leaving nothing readable behind:
+    /// the search index is scrubbed like `clear_search_index` does, and a
+    /// VACUUM rebuilds the file without its free pages.
     pub fn clear_all(&self) -> Result<()> {
         let conn = self.conn.lock();
         conn.execute("DELETE FROM items", [])?;
+        conn.execute("INSERT INTO items_fts (items_fts) VALUES ('optimize')", [])?;
+        conn.execute_batch("VACUUM")?;
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
         Ok(())
     }
 
@@ -171,6 +1141,320 @@ impl Database {
     }
 }
 
+/// Makes text deleted from the search index unreadable on disk. FTS5 keeps
+/// deleted entries in its segments until they are merged, so they are
+/// merged now; `secure_delete` zeroes the pages that frees, and the
+/// checkpoint moves that into the database file and empties the WAL, which
+/// still holds the old page images.
+fn scrub_search_index(conn: &Connection) -> Result<()> {
+    conn.execute("INSERT INTO items_fts (items_fts) VALUES ('optimize')", [])?;
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    Ok(())
+}
+
+fn prune_kind(conn: &Connection, kind: &str, max: u32, keep_pinned: bool) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM items WHERE kind = ?1 AND (?3 = 0 OR is_pinned = 0) AND id NOT IN (
+           SELECT id FROM items WHERE kind = ?1 AND (?3 = 0 OR is_pinned = 0) ORDER BY seq DESC LIMIT ?2
+         )",
+        params![kind, max as i64, keep_pinned],
+    )
+}
+
+fn trim_items(conn: &Connection, max: u64) -> rusqlite::Result<usize> {
+    conn.execute(
+        "DELETE FROM items WHERE is_pinned = 0 AND id NOT IN (
+           SELECT id FROM items WHERE is_pinned = 0 ORDER BY seq DESC LIMIT ?1
+         )",
+        params![max.min(i64::MAX as u64) as i64],
+    )
+}
+
+/// Every column holding blobs sealed with the master key, as (table, row
+/// key, column).
+const ENCRYPTED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("items", "id", "content_blob"),
+    ("items", "id", "preview_blob"),
+    ("items", "id", "rtf_blob"),
+    ("items", "id", "html_blob"),
+    ("items", "id", "file_path_blob"),
+    ("items", "id", "note_blob"),
+    ("extra_types", "rowid", "data_blob"),
+];
+
+/// Column list read by `row_to_item`.
+const ITEM_COLUMNS: &str = "id, created_at, kind, size, sha256, file_path, is_pinned, plaintext_len, stored_len, is_sensitive, uti, thumbnail_only, file_path_blob, note_blob, image_corrupt, source_app,
+    (SELECT GROUP_CONCAT(tag, char(31)) FROM item_tags WHERE item_id = items.id)";
+
+fn row_to_item(row: &rusqlite::Row) -> rusqlite::Result<ItemDto> {
+    let sha: Vec<u8> = row.get(4)?;
+    Ok(ItemDto {
+        id: row.get(0)?,
+        created_at: row.get(1)?,
+        kind: row.get::<_, String>(2)?,
+        size: row.get(3)?,
+        sha256_hex: hex::encode(sha),
+        file_path: row.get(5)?,
+        is_pinned: row.get::<_, i64>(6)? != 0,
+        plaintext_len: row.get(7)?,
+        stored_len: row.get(8)?,
+        is_sensitive: row.get::<_, i64>(9)? != 0,
+        uti: row.get(10)?,
+        thumbnail_only: row.get::<_, i64>(11)? != 0,
+        file_path_blob: row.get(12)?,
+        note_blob: row.get(13)?,
+        image_corrupt: row.get::<_, i64>(14)? != 0,
+        source_app: row.get(15)?,
+        tags: {
+            let joined: Option<String> = row.get(16)?;
+            let mut tags: Vec<String> = joined.iter().flat_map(|j| j.split(TAG_SEPARATOR)).map(str::to_string).collect();
+            tags.sort_by_key(|t| t.to_lowercase());
+            tags
+        },
+    })
+}
+
+type Migration = fn(&Connection, &mut Vec<String>) -> rusqlite::Result<()>;
+
+/// Schema migrations in order; after applying step `n` (0-based) the
+/// database `user_version` is `n + 1`. Every step must be idempotent.
+const MIGRATIONS: &[Migration] = &[m001_initial, m002_copy_tracking, m003_content_lengths, m004_sensitive_items, m005_item_seq, m006_source_app, m007_item_uti, m008_pin_order, m009_thumbnail_only, m010_file_path_blob, m011_html_blob, m012_item_note, m013_extra_types, m014_size_index, m015_image_corrupt, m016_search_index, m017_item_tags];
+
+fn m001_initial(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    create_table_if_missing(
+        conn,
+        "items",
+        r#"
+        CREATE TABLE items (
+          id INTEGER PRIMARY KEY,
+          created_at INTEGER NOT NULL,
+          kind TEXT NOT NULL,
+          size INTEGER NOT NULL,
+          sha256 BLOB NOT NULL,
+          file_path TEXT,
+          is_pinned INTEGER NOT NULL DEFAULT 0,
+          content_blob BLOB,
+          preview_blob BLOB,
+          rtf_blob BLOB
+        )
+        "#,
+        fixes,
+    )?;
+    create_index_if_missing(conn, "idx_items_created", "CREATE INDEX idx_items_created ON items(created_at DESC)", fixes)?;
+    create_index_if_missing(conn, "idx_items_kind", "CREATE INDEX idx_items_kind ON items(kind)", fixes)?;
+    Ok(())
+}
+
+fn m002_copy_tracking(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "copy_count", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    add_column_if_missing(conn, "items", "last_copied_at", "INTEGER", fixes)?;
+    create_index_if_missing(conn, "idx_items_last_copied", "CREATE INDEX idx_items_last_copied ON items(last_copied_at DESC)", fixes)?;
+    Ok(())
+}
+
+fn m003_content_lengths(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "plaintext_len", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    add_column_if_missing(conn, "items", "stored_len", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    // Rows from before this migration: `size` was the plaintext length.
+    conn.execute_batch(
+        "UPDATE items SET plaintext_len = size, stored_len = IFNULL(length(content_blob), 0)
+         WHERE plaintext_len = 0 AND stored_len = 0 AND content_blob IS NOT NULL",
+    )?;
+    Ok(())
+}
+
+fn m004_sensitive_items(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "is_sensitive", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    add_column_if_missing(conn, "items", "reveal_count", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    Ok(())
+}
+
+// Insertion order for sorting; unlike `created_at` it cannot go backwards
+// when the system clock is adjusted. `created_at` is for display only.
+fn m005_item_seq(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "seq", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    conn.execute_batch("UPDATE items SET seq = id WHERE seq = 0")?;
+    create_index_if_missing(conn, "idx_items_seq", "CREATE INDEX idx_items_seq ON items(seq DESC)", fixes)?;
+    Ok(())
+}
+
+fn m006_source_app(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "source_app", "TEXT", fixes)?;
+    Ok(())
+}
+
+fn m007_item_uti(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "uti", "TEXT", fixes)?;
+    Ok(())
+}
+
+fn m008_pin_order(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "pin_order", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    // keep the current newest-first order of existing pins
+    conn.execute_batch("UPDATE items SET pin_order = seq WHERE is_pinned = 1 AND pin_order = 0")?;
+    Ok(())
+}
+
+fn m009_thumbnail_only(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "thumbnail_only", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    Ok(())
+}
+
+fn m010_file_path_blob(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    // existing paths are sealed by `seal_file_paths` once the key is available
+    add_column_if_missing(conn, "items", "file_path_blob", "BLOB", fixes)?;
+    Ok(())
+}
+
+fn m011_html_blob(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "html_blob", "BLOB", fixes)?;
+    Ok(())
+}
+
+fn m012_item_note(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "note_blob", "BLOB", fixes)?;
+    Ok(())
+}
+
+fn m013_extra_types(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    create_table_if_missing(
+        conn,
+        "extra_types",
+        r#"
+        CREATE TABLE extra_types (
+          item_id INTEGER NOT NULL,
+          uti TEXT NOT NULL,
+          data_blob BLOB NOT NULL,
+          PRIMARY KEY (item_id, uti)
+        )
+        "#,
+        fixes,
+    )?;
+    // every path that deletes items (delete, clear, per-kind pruning) takes
+    // their extra types along
+    create_trigger_if_missing(
+        conn,
+        "items_delete_extra_types",
+        "CREATE TRIGGER items_delete_extra_types AFTER DELETE ON items BEGIN
+           DELETE FROM extra_types WHERE item_id = OLD.id;
+         END",
+        fixes,
+    )?;
+    Ok(())
+}
+
+fn m014_size_index(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    create_index_if_missing(conn, "idx_items_size", "CREATE INDEX idx_items_size ON items(size DESC)", fixes)?;
+    Ok(())
+}
+
+fn m015_image_corrupt(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    add_column_if_missing(conn, "items", "image_corrupt", "INTEGER NOT NULL DEFAULT 0", fixes)?;
+    Ok(())
+}
+
+fn m016_search_index(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    // plaintext of text items, only filled while `enable_search_index` is on;
+    // trigrams make MATCH a case-insensitive substring search like the scan
+    create_table_if_missing(
+        conn,
+        "items_fts",
+        "CREATE VIRTUAL TABLE items_fts USING fts5(text, tokenize = 'trigram')",
+        fixes,
+    )?;
+    create_trigger_if_missing(
+        conn,
+        "items_delete_fts",
+        "CREATE TRIGGER items_delete_fts AFTER DELETE ON items BEGIN
+           DELETE FROM items_fts WHERE rowid = OLD.id;
+         END",
+        fixes,
+    )?;
+    Ok(())
+}
+
+fn m017_item_tags(conn: &Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    create_table_if_missing(
+        conn,
+        "item_tags",
+        r#"
+        CREATE TABLE item_tags (
+          item_id INTEGER NOT NULL,
+          tag TEXT NOT NULL COLLATE NOCASE,
+          PRIMARY KEY (item_id, tag)
+        )
+        "#,
+        fixes,
+    )?;
+    create_index_if_missing(conn, "idx_item_tags_tag", "CREATE INDEX idx_item_tags_tag ON item_tags(tag)", fixes)?;
+    create_trigger_if_missing(
+        conn,
+        "items_delete_tags",
+        "CREATE TRIGGER items_delete_tags AFTER DELETE ON items BEGIN
+           DELETE FROM item_tags WHERE item_id = OLD.id;
+         END",
+        fixes,
+    )?;
+    Ok(())
+}
+
+fn apply_pending(conn: &mut Connection, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (i, step) in MIGRATIONS.iter().enumerate().skip(version.max(0) as usize) {
+        let tx = conn.transaction()?;
+        step(&tx, &mut Vec::new())?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+        fixes.push(format!("applied migration {}", i + 1));
+    }
+    Ok(())
+}
+
+fn schema_object_exists(conn: &Connection, ty: &str, name: &str) -> rusqlite::Result<bool> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = ?1 AND name = ?2)",
+        params![ty, name],
+        |row| row.get(0),
+    )
+}
+
+fn create_table_if_missing(conn: &Connection, name: &str, sql: &str, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    if !schema_object_exists(conn, "table", name)? {
+        conn.execute_batch(sql)?;
+        fixes.push(format!("created table {name}"));
+    }
+    Ok(())
+}
+
+fn create_index_if_missing(conn: &Connection, name: &str, sql: &str, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    if !schema_object_exists(conn, "index", name)? {
+        conn.execute_batch(sql)?;
+        fixes.push(format!("created index {name}"));
+    }
+    Ok(())
+}
+
+fn create_trigger_if_missing(conn: &Connection, name: &str, sql: &str, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    if !schema_object_exists(conn, "trigger", name)? {
+        conn.execute_batch(sql)?;
+        fixes.push(format!("created trigger {name}"));
+    }
+    Ok(())
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str, fixes: &mut Vec<String>) -> rusqlite::Result<()> {
+    let exists: bool = conn.query_row(
+        &format!("SELECT EXISTS(SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1)"),
+        params![column],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))?;
+        fixes.push(format!("added column {table}.{column}"));
+    }
+    Ok(())
+}
+
 pub fn now_millis() -> i64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -4,3 +4,5 @@ pub mod clipboard;
 pub mod crypto;
 pub mod db;
 pub mod state;
+pub mod timefmt;
+pub mod tray;
@@ -4,15 +4,18 @@
 #[cfg(target_os = "macos")]
 
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
 
 use parking_lot::Mutex;
-use tauri::{GlobalShortcutManager, Manager, ActivationPolicy, SystemTray, SystemTrayEvent};
+use tauri::{Manager, ActivationPolicy, SystemTrayEvent};
 
 mod api;
 mod clipboard;
 mod crypto;
 mod db;
 mod state;
+mod timefmt;
+mod tray;
 
 #[cfg(target_os = "macos")]
 use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial, NSVisualEffectState};
@@ -21,7 +24,7 @@ use cocoa::base::id as cocoa_id;
 #[cfg(target_os = "macos")]
 use objc::{msg_send, sel, sel_impl};
 
-use state::{AppState, Settings};
+use state::AppState;
 
 #[cfg(target_os = "macos")]
 fn setup_vibrancy(win: &tauri::Window) {
@@ -50,30 +53,71 @@ fn round_corners(win: &tauri::Window, radius: f64) {
 
 
 fn main() {
+    let context = tauri::generate_context!();
+    // the menu bar item is the only way to reach an accessory app
+    let system_tray = tray::system_tray(context.system_tray_icon());
     tauri::Builder::default()
         .setup(|app| {
             let app_handle = app.handle();
 
-            // Init DB
-            let app_dir = app_handle.path_resolver().app_data_dir().expect("app data dir");
-            let db = db::Database::new(app_dir).expect("db init");
-            db.migrate().expect("db migrate");
+            // Init DB, degrading to temp/in-memory storage if the data dir is unusable
+            let app_dir = state::app_data_dir(&app_handle);
+            let (db, storage_warning) = db::Database::open_with_fallback(app_dir.clone(), state::fallback_data_dir())?;
+            if let Some(w) = &storage_warning {
+                eprintln!("{w}");
+            }
 
-            // Init crypto manager (lazy-unlock from Keychain on demand)
-            let bundle_id = app.config().tauri.bundle.identifier.clone();
-            let crypto = crypto::KeyManager::new(bundle_id);
+            let last_capture = clipboard::LastCapture::load(state::last_capture_path(&app_dir));
+            // decrypted file copies from a previous run; the app starts locked
+            clipboard::clear_restored_files();
 
             // Load settings from app data dir
-            let settings_path = state::settings_path(app_handle.path_resolver().app_data_dir().expect("app data dir"));
-            let settings = state::load_settings(&settings_path).unwrap_or(Settings { auto_lock_minutes: 5, hotkey: "CmdOrCtrl+Shift+Space".into() });
+            let settings_path = state::settings_path(app_dir);
+            let settings = state::load_settings(&settings_path).unwrap_or_default();
+            db.set_dedup_window(settings.dedup_window_minutes);
+            db.set_dedup_policy(settings.dedup_policy);
+            db.set_checkpoint_every(settings.wal_checkpoint_writes);
+            if let Err(e) = db.set_kind_limits(settings.per_kind_limits.clone()) {
+                eprintln!("failed to apply per-kind limits: {e}");
+            }
+            if let Err(e) = db.set_max_items(settings.max_items) {
+                eprintln!("failed to apply the item limit: {e}");
+            }
+
+            // Init crypto manager (lazy-unlock from Keychain on demand)
+            let bundle_id = app.config().tauri.bundle.identifier.clone();
+            let crypto = crypto::KeyManager::new(bundle_id)
+                .with_nonce_mode(settings.nonce_mode)
+                .with_key_mode(settings.key_mode)
+                .with_passphrase(settings.use_passphrase);
+            // Rows from an earlier ephemeral launch can never be decrypted again
+            if settings.key_mode == crypto::KeyMode::Ephemeral {
+                if let Err(e) = db.clear_all() {
+                    eprintln!("failed to clear ephemeral history: {e}");
+                }
+            }
 
             let state = AppState {
                 db: Arc::new(db),
                 crypto: Arc::new(crypto),
                 settings: Arc::new(Mutex::new(settings)),
+                reclassify_cancel: Arc::new(AtomicBool::new(false)),
+                storage_warning: storage_warning.clone(),
+                locked_captures: Arc::new(clipboard::LockedCaptures::default()),
+                self_writes: Arc::new(clipboard::SelfWrites::default()),
+                storm_guard: Arc::new(clipboard::StormGuard::default()),
+                last_capture: Arc::new(last_capture),
+                low_disk: Arc::new(AtomicBool::new(false)),
+                wipe_confirm: Arc::new(state::WipeConfirm::default()),
+                activity: Arc::new(state::Activity::default()),
+                previous_app: Arc::new(Mutex::new(None)),
+                accessibility: Arc::new(AtomicBool::new(clipboard::paste::accessibility_trusted())),
             };
 
             app.manage(state.clone());
+            if let Some(w) = storage_warning {
+                let _ = app_handle.emit_all("storage_warning", w);
+            }
 
             // Apply vibrancy and basic window tweaks
             #[cfg(target_os = "macos")]
@@ -83,21 +127,10 @@ fn main() {
                 round_corners(&win, 14.0);
             }
 
-            // Global hotkey to toggle/show window
-            {
-                let app_for_cb = app.handle();
-                let mut gsm = app.handle().global_shortcut_manager();
-                let _ = gsm.unregister_all();
-                let accel = state.settings.lock().hotkey.clone();
-                if let Err(e) = gsm.register(accel.as_str(), move || {
-                    if let Some(win) = app_for_cb.get_window("main") {
-                        let _ = win.show();
-                        let _ = win.unminimize();
-                        let _ = win.set_focus();
-                    }
-                }) {
-                    eprintln!("failed to register global shortcut: {e}");
-                }
+            // Global hotkeys to show (or peek at) the window
+            let settings = state.settings.lock().clone();
+            if let Err(e) = api::register_shortcuts(&app.handle(), &settings) {
+                eprintln!("failed to register global shortcut: {e}");
             }
 
             // Start clipboard poller (macOS)
@@ -110,6 +143,65 @@ fn main() {
                 });
             }
 
+            // "Save to Cliper" in the Services menu
+            #[cfg(target_os = "macos")]
+            clipboard::service::register_service_provider(app.handle());
+
+            // Periodic encryption self-test and image downgrade; the interval is re-read each round
+            {
+                let state_clone = state.clone();
+                let app_for_health = app.handle();
+                std::thread::spawn(move || loop {
+                    let minutes = state_clone.settings.lock().health_check_minutes;
+                    std::thread::sleep(std::time::Duration::from_secs(minutes.max(1) * 60));
+                    if minutes > 0 {
+                        api::run_health_check(&app_for_health, &state_clone);
+                    }
+                    api::run_image_downgrade(&app_for_health, &state_clone);
+                });
+            }
+
+            // Delete items past `retention_days` at startup, then daily
+            {
+                let state_clone = state.clone();
+                let app_for_purge = app.handle();
+                std::thread::spawn(move || loop {
+                    api::run_retention_purge(&app_for_purge, &state_clone);
+                    std::thread::sleep(std::time::Duration::from_secs(24 * 60 * 60));
+                });
+            }
+
+            // Watch free space on the database volume
+            {
+                let state_clone = state.clone();
+                let app_for_disk = app.handle();
+                std::thread::spawn(move || loop {
+                    api::run_disk_check(&app_for_disk, &state_clone);
+                    std::thread::sleep(std::time::Duration::from_secs(30));
+                });
+            }
+
+            // Notice the Accessibility permission being granted (or revoked) while running
+            #[cfg(target_os = "macos")]
+            {
+                let state_clone = state.clone();
+                let app_for_permissions = app.handle();
+                std::thread::spawn(move || loop {
+                    std::thread::sleep(std::time::Duration::from_secs(2));
+                    api::run_permission_check(&app_for_permissions, &state_clone);
+                });
+            }
+
+            // Lock after `auto_lock_minutes` without activity
+            {
+                let state_clone = state.clone();
+                let app_for_lock = app.handle();
+                std::thread::spawn(move || loop {
+                    std::thread::sleep(std::time::Duration::from_secs(15));
+                    api::run_auto_lock(&app_for_lock, &state_clone);
+                });
+            }
+
             // Hide Dock icon, keep menu bar (Accessory app)
             #[cfg(target_os = "macos")]
             app.set_activation_policy(ActivationPolicy::Accessory);
@@ -117,7 +209,7 @@ fn main() {
             Ok(())
         })
         // Add system tray (top bar) to toggle window
-        .system_tray(SystemTray::new())
+        .system_tray(system_tray)
         .on_system_tray_event(|app, event| {
             if let SystemTrayEvent::LeftClick { .. } = event {
                 if let Some(win) = app.get_window("main") {
@@ -128,17 +220,111 @@ fn main() {
         })
         .invoke_handler(tauri::generate_handler![
             api::search,
+            api::set_enable_search_index,
+            api::tag_item,
+            api::untag_item,
+            api::search_capabilities,
             api::list_recent,
+            api::set_sticky_item,
+            api::unread_count,
+            api::mark_all_seen,
+            api::format_timestamp,
+            api::list_recently_pasted,
+            api::list_files,
+            api::list_oldest,
+            api::list_page,
+            api::get_items,
+            api::list_since,
+            api::has_new_since,
+            api::list_large_items,
+            api::get_previews,
             api::copy_item,
+            api::find_matching_clipboard,
+            api::dump_clipboard_to,
+            api::copy_item_wrapped,
+            api::copy_items_joined,
+            api::paste_and_restore_focus,
+            api::permissions,
+            api::capture_type,
+            api::save_item_to,
+            api::detach_item,
+            api::import_text_files,
+            api::materialize_file,
+            api::dedup_files_by_inode,
             api::pin_item,
+            api::set_item_kind,
+            api::swap_pin_order,
             api::delete_item,
+            api::set_item_sensitive,
+            api::set_item_note,
+            api::reveal_item,
             api::reveal_in_finder,
+            api::diagnose_copy,
+            api::get_data_paths,
+            api::size_histogram,
+            api::encryption_overhead,
+            api::disk_space_info,
+            api::export_item_blob,
+            api::export_items_ndjson,
+            api::reveal_data_dir,
             api::get_settings,
+            api::get_settings_schema,
+            api::get_last_filter_state,
+            api::set_last_filter_state,
+            api::set_restore_search_on_open,
             api::set_hotkey,
+            api::set_peek,
             api::get_image_preview,
+            api::set_preview_max_data_url_bytes,
+            api::get_file_preview,
+            api::decode_item,
+            api::get_item_html_preview,
+            api::get_item_text_decoded,
+            api::set_thumbnail_options,
+            api::set_dedup_window,
+            api::set_dedup_policy,
+            api::set_health_check_interval,
+            api::set_image_full_days,
+            api::set_retention_days,
+            api::purge_now,
+            api::set_encrypt_file_paths,
+            api::set_extra_utis,
+            api::set_storm_guard,
+            api::set_require_stable_ms,
+            api::set_respect_transient,
+            api::set_poll_interval,
+            api::set_max_capture_bytes,
+            api::set_ignore_whitespace_only,
+            api::set_keep_image_with_text,
+            api::add_excluded_app,
+            api::remove_excluded_app,
+            api::get_refresh_debounce_ms,
+            api::set_refresh_debounce_ms,
+            api::set_auto_pin_rules,
+            api::set_capture_transforms,
+            api::set_per_kind_limits,
+            api::set_max_items,
+            api::set_locked_capture_behavior,
+            api::get_missed_captures,
             api::reset_master_key,
+            api::request_panic_wipe,
+            api::panic_wipe,
+            api::set_wipe_hotkey,
+            api::choose_key_mode,
+            api::choose_passphrase_mode,
+            api::rotate_master_key,
+            api::set_require_biometrics,
+            api::unlock_with_passphrase,
+            api::has_passphrase,
+            api::repair_schema,
+            api::get_storage_warning,
+            api::reclassify_items,
+            api::cancel_reclassify,
             api::unlock,
-            api::lock
+            api::get_keychain_error,
+            api::lock,
+            api::auto_lock_remaining,
+            api::set_auto_lock
         ])
         .on_window_event(|event| {
             match event.event() {
@@ -147,6 +333,10 @@ fn main() {
                     api.prevent_close();
                     let _ = event.window().hide();
                 }
+                // Focusing the window counts as activity for auto-lock
+                tauri::WindowEvent::Focused(true) => {
+                    event.window().state::<AppState>().activity.touch();
+                }
                 // Hide when focus lost
                 tauri::WindowEvent::Focused(false) => {
                     let _ = event.window().hide();
@@ -154,6 +344,6 @@ fn main() {
                 _ => {}
             }
         })
-        .run(tauri::generate_context!())
+        .run(context)
         .expect("error while running tauri application");
 }
@@ -1,23 +1,309 @@
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
 
 use parking_lot::Mutex;
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use crate::{crypto::KeyManager, db::Database};
+use crate::{clipboard::{autopin::AutoPinRule, transform::CaptureTransform, LastCapture, LockedCaptureBehavior, LockedCaptures, SelfWrites, StormGuard, ThumbnailFormat}, crypto::{KeyManager, KeyMode, NonceMode}, db::{Database, DedupPolicy}};
 
+/// Persisted preferences. Keys missing from `settings.json` take their
+/// `Default` value, so new fields never reset existing ones.
 #[derive(Clone, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub auto_lock_minutes: u64,
     pub hotkey: String,
+    /// Accelerator that shows the window without taking focus.
+    pub peek_hotkey: Option<String>,
+    /// Accelerator that asks to wipe all history and reset the key; never
+    /// wipes without confirmation.
+    pub wipe_hotkey: Option<String>,
+    /// Make the main hotkey peek as well instead of focusing the window.
+    pub peek_no_focus: bool,
+    /// Nonce scheme for new encryptions; applied at startup.
+    pub nonce_mode: NonceMode,
+    /// Keychain-backed key, or a throwaway in-memory key per launch; chosen
+    /// on first run and applied at startup.
+    pub key_mode: KeyMode,
+    /// Derive the Keychain-mode key from a master passphrase instead of
+    /// storing it; chosen while history is empty and applied at startup.
+    pub use_passphrase: bool,
+    /// Ask for Touch ID before `unlock` loads the key from the Keychain.
+    pub require_biometrics: bool,
+    /// Longest side of cached image thumbnails, in pixels.
+    pub thumbnail_max: u32,
+    pub thumbnail_format: ThumbnailFormat,
+    /// Largest data URL `get_image_preview` returns, in bytes; bigger
+    /// previews are downsized to fit. 0 = no limit.
+    pub preview_max_data_url_bytes: usize,
+    /// Only dedup re-captures within this many minutes; 0 = always dedup.
+    pub dedup_window_minutes: u64,
+    /// Whether the source app is part of the dedup key.
+    pub dedup_policy: DedupPolicy,
+    /// Inserts, pins and deletes between WAL checkpoints; 0 = off.
+    pub wal_checkpoint_writes: u64,
+    /// Reopen the window with the last kind filter and search query.
+    pub restore_search_on_open: bool,
+    /// Whether text/image copied while locked is dropped, buffered or counted.
+    pub locked_capture_behavior: LockedCaptureBehavior,
+    /// Minutes between encryption self-tests while unlocked; 0 = off.
+    pub health_check_minutes: u64,
+    /// Most unpinned items kept per kind, e.g. `{"image": 50}`; unlisted
+    /// kinds are unlimited.
+    pub per_kind_limits: HashMap<String, u32>,
+    /// Most unpinned items kept in total; the oldest go first. `None` keeps
+    /// all of them.
+    pub max_items: Option<u64>,
+    /// Clean-ups applied to captured text before it is stored, in order.
+    pub capture_transforms: Vec<CaptureTransform>,
+    /// Days after which unpinned images keep only their thumbnail; 0 = keep
+    /// full images.
+    pub image_full_days: u64,
+    /// Days unpinned items are kept before they are deleted; `None` keeps
+    /// history forever.
+    pub retention_days: Option<u64>,
+    /// Store file item paths encrypted, leaving only their hash in the clear.
+    pub encrypt_file_paths: bool,
+    /// Custom pasteboard types stored as opaque blobs with captured text or
+    /// images and written back on copy.
+    pub extra_utis: Vec<String>,
+    /// Identical captures allowed within `storm_window_ms` before further
+    /// repeats are ignored; 0 = never ignore.
+    pub storm_max_repeats: u32,
+    pub storm_window_ms: u64,
+    /// How long the UI waits after `items_updated` for more before
+    /// refreshing the list, in milliseconds; see `REFRESH_DEBOUNCE_MS`.
+    pub refresh_debounce_ms: u64,
+    /// Capture only once the clipboard has stayed unchanged this long, in
+    /// milliseconds, skipping selections some apps copy automatically.
+    /// 0 = capture every change.
+    pub require_stable_ms: u64,
+    /// How often the clipboard is checked for changes, in milliseconds;
+    /// see `POLL_INTERVAL_MS`. Lower captures sooner, higher wakes less.
+    pub poll_interval_ms: u64,
+    /// Text, images and files larger than this many bytes are not captured;
+    /// 0 leaves only the built-in `MAX_CAPTURE_BYTES` cap for text and images.
+    pub max_capture_bytes: u64,
+    /// Don't capture text that is nothing but spaces, tabs and newlines,
+    /// as some apps copy while navigating.
+    pub ignore_whitespace_only: bool,
+    /// When a copy carries both text and an image (e.g. a rich email),
+    /// store the image as its own item under the text. Each such copy then
+    /// adds two rows to history; off keeps only the text.
+    pub keep_image_with_text: bool,
+    /// Bundle ids of apps whose copies are never captured, e.g.
+    /// `com.agilebits.onepassword7`.
+    pub excluded_apps: Vec<String>,
+    /// Keep a full-text index of decrypted text so search covers all
+    /// history quickly. The index stores plaintext in the database file.
+    pub enable_search_index: bool,
+    /// Free bytes on the database volume below which `low_disk` is emitted;
+    /// 0 = don't watch.
+    pub low_disk_bytes: u64,
+    /// Stop capturing while disk space is low, resuming once it recovers.
+    pub pause_capture_on_low_disk: bool,
+    /// Skip copies password managers mark as concealed or transient.
+    pub respect_transient: bool,
+    /// Captures matching any of these (by source app and/or text) are
+    /// pinned as they are stored.
+    pub auto_pin_rules: Vec<AutoPinRule>,
+    /// Item `list_recent` always shows first, ahead of pins.
+    pub sticky_item_id: Option<i64>,
+    /// Highest item id the user has seen; newer items count as unread.
+    pub last_seen_id: i64,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            auto_lock_minutes: 5,
+            hotkey: "CmdOrCtrl+Shift+Space".into(),
+            peek_hotkey: None,
+            wipe_hotkey: None,
+            peek_no_focus: false,
+            nonce_mode: NonceMode::Random,
+            key_mode: KeyMode::Keychain,
+            use_passphrase: false,
+            require_biometrics: false,
+            thumbnail_max: 128,
+            thumbnail_format: ThumbnailFormat::Png,
+            preview_max_data_url_bytes: 512 * 1024,
+            dedup_window_minutes: 0,
+            dedup_policy: DedupPolicy::Content,
+            wal_checkpoint_writes: crate::db::DEFAULT_CHECKPOINT_WRITES,
+            restore_search_on_open: false,
+            locked_capture_behavior: LockedCaptureBehavior::Drop,
+            health_check_minutes: 60,
+            per_kind_limits: HashMap::new(),
+            max_items: None,
+            capture_transforms: Vec::new(),
+            image_full_days: 0,
+            retention_days: None,
+            encrypt_file_paths: false,
+            extra_utis: crate::clipboard::DEFAULT_EXTRA_UTIS.iter().map(|u| u.to_string()).collect(),
+            storm_max_repeats: 5,
+            storm_window_ms: 2_000,
+            refresh_debounce_ms: 100,
+            require_stable_ms: 0,
+            poll_interval_ms: 250,
+            max_capture_bytes: 10 * 1024 * 1024,
+            ignore_whitespace_only: true,
+            keep_image_with_text: true,
+            excluded_apps: Vec::new(),
+            enable_search_index: false,
+            low_disk_bytes: 200 * 1024 * 1024,
+            pause_capture_on_low_disk: true,
+            respect_transient: true,
+            auto_pin_rules: Vec::new(),
+            sticky_item_id: None,
+            last_seen_id: 0,
+        }
+    }
+}
+
+/// Describes one `Settings` key for a generated settings form.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct SettingField {
+    pub key: &'static str,
+    /// "bool" | "integer" | "string" | "enum" | "map" (kind -> integer) |
+    /// "list" (ordered subset of `options`) | "strings" (free-form list) |
+    /// "rules" (list of objects with their own setter)
+    #[serde(rename = "type")]
+    pub ty: &'static str,
+    pub nullable: bool,
+    /// Value from `Settings::default()`.
+    pub default: serde_json::Value,
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    /// Allowed values for "enum" and "list" fields.
+    pub options: &'static [&'static str],
+    pub description: &'static str,
+}
+
+// (key, type, nullable, min, max, options, description); keep in step with
+// `Settings`, the settings test checks every serialized key is listed.
+type FieldSpec = (&'static str, &'static str, bool, Option<u64>, Option<u64>, &'static [&'static str], &'static str);
+
+const SETTING_FIELDS: &[FieldSpec] = &[
+    ("auto_lock_minutes", "integer", false, Some(0), None, &[], "Lock after this many idle minutes"),
+    ("hotkey", "string", false, None, None, &[], "Accelerator that shows the window"),
+    ("peek_hotkey", "string", true, None, None, &[], "Accelerator that shows the window without focus"),
+    ("wipe_hotkey", "string", true, None, None, &[], "Accelerator that asks to erase all history and reset the key"),
+    ("peek_no_focus", "bool", false, None, None, &[], "Main hotkey peeks instead of focusing"),
+    ("nonce_mode", "enum", false, None, None, &["random", "counter"], "Nonce scheme for new encryptions; applied at startup"),
+    ("key_mode", "enum", false, None, None, &["keychain", "ephemeral"], "Keychain key, or an in-memory key lost on quit; applied at startup"),
+    ("use_passphrase", "bool", false, None, None, &[], "Unlock with a master passphrase instead of the Keychain key alone; applied at startup"),
+    ("require_biometrics", "bool", false, None, None, &[], "Ask for Touch ID before unlocking"),
+    ("thumbnail_max", "integer", false, Some(16), Some(1024), &[], "Longest side of cached thumbnails, in pixels"),
+    ("thumbnail_format", "enum", false, None, None, &["png", "jpeg"], "Encoding of cached thumbnails"),
+    ("preview_max_data_url_bytes", "integer", false, Some(0), None, &[], "Largest image preview data URL; bigger ones are downsized, 0 = no limit"),
+    ("dedup_window_minutes", "integer", false, Some(0), None, &[], "Only dedup re-captures within this many minutes; 0 = always"),
+    ("dedup_policy", "enum", false, None, None, &["content", "content_and_app"], "Whether the source app is part of the dedup key"),
+    ("wal_checkpoint_writes", "integer", false, Some(0), None, &[], "Database writes between WAL checkpoints; 0 = off"),
+    ("restore_search_on_open", "bool", false, None, None, &[], "Reopen with the last filter and search query"),
+    ("locked_capture_behavior", "enum", false, None, None, &["drop", "buffer", "notify"], "What happens to copies made while locked"),
+    ("health_check_minutes", "integer", false, Some(0), None, &[], "Minutes between encryption self-tests; 0 = off"),
+    ("capture_transforms", "list", false, None, None, &["trim", "normalize_eol", "strip_zero_width", "collapse_whitespace"], "Clean-ups applied to captured text, in order"),
+    ("image_full_days", "integer", false, Some(0), Some(MAX_IMAGE_FULL_DAYS), &[], "Days before old images are reduced to thumbnails; 0 = never"),
+    ("retention_days", "integer", true, Some(1), None, &[], "Delete unpinned items older than this many days; null = keep forever"),
+    ("encrypt_file_paths", "bool", false, None, None, &[], "Encrypt the paths of copied files"),
+    ("extra_utis", "strings", false, None, None, &[], "Custom pasteboard types kept with captures and restored on copy"),
+    ("storm_max_repeats", "integer", false, Some(0), None, &[], "Identical copies allowed within the storm window; 0 = no limit"),
+    ("storm_window_ms", "integer", false, Some(100), Some(60_000), &[], "Window for counting identical copies, in milliseconds"),
+    ("refresh_debounce_ms", "integer", false, Some(0), Some(2_000), &[], "Delay before the list refreshes after a change, coalescing bursts; in milliseconds"),
+    ("require_stable_ms", "integer", false, Some(0), Some(10_000), &[], "Capture only after the clipboard stays unchanged this long, in milliseconds; 0 = off"),
+    ("poll_interval_ms", "integer", false, Some(50), Some(5_000), &[], "How often the clipboard is checked for changes, in milliseconds"),
+    ("max_capture_bytes", "integer", false, Some(0), Some(crate::clipboard::MAX_CAPTURE_BYTES as u64), &[], "Largest text, image or file captured, in bytes; 0 = built-in limit only"),
+    ("ignore_whitespace_only", "bool", false, None, None, &[], "Skip copies that are only whitespace"),
+    ("keep_image_with_text", "bool", false, None, None, &[], "Also store the image when a copy has text and an image; adds a second history row"),
+    ("excluded_apps", "strings", false, None, None, &[], "Bundle ids of apps whose copies are never captured"),
+    ("enable_search_index", "bool", false, None, None, &[], "Index decrypted text for fast search over all history; stores plaintext on disk"),
+    ("low_disk_bytes", "integer", false, Some(0), None, &[], "Warn when free space on the database volume drops below this many bytes; 0 = off"),
+    ("pause_capture_on_low_disk", "bool", false, None, None, &[], "Stop capturing while disk space is low"),
+    ("respect_transient", "bool", false, None, None, &[], "Skip copies password managers mark as concealed or transient"),
+    ("auto_pin_rules", "rules", false, None, None, &[], "Pin captures from an app and/or whose text matches a pattern"),
+    ("last_seen_id", "integer", false, Some(0), None, &[], "Items above this id count as unread; set with mark_all_seen"),
+    ("sticky_item_id", "integer", true, None, None, &[], "Item always listed first; set with set_sticky_item"),
+    ("max_items", "integer", true, Some(1), None, &[], "Most unpinned items kept; the oldest are deleted first, null = no limit"),
+    ("per_kind_limits", "map", false, None, None, &[], "Most unpinned items kept per kind, e.g. {\"image\": 50}"),
+];
+
+/// Every settings key with its type, default and allowed range, so the UI
+/// can render the form without hard-coding it and skip keys it doesn't know.
+pub fn settings_schema() -> Vec<SettingField> {
+    let defaults = serde_json::to_value(Settings::default()).unwrap_or_default();
+    SETTING_FIELDS
+        .iter()
+        .map(|&(key, ty, nullable, min, max, options, description)| SettingField {
+            key,
+            ty,
+            nullable,
+            default: defaults.get(key).cloned().unwrap_or_default(),
+            min,
+            max,
+            options,
+            description,
+        })
+        .collect()
+}
+
+/// Used for storage when the platform has no app data dir.
+pub fn fallback_data_dir() -> PathBuf { std::env::temp_dir().join("cliper") }
+
+/// App data dir holding the database and `settings.json`.
+pub fn app_data_dir(app: &tauri::AppHandle) -> PathBuf {
+    app.path_resolver().app_data_dir().unwrap_or_else(fallback_data_dir)
 }
 
 pub fn settings_path(app_dir: PathBuf) -> PathBuf { app_dir.join("settings.json") }
 
+/// Where `LastCapture` keeps the fingerprint of the last stored capture.
+pub fn last_capture_path(app_dir: &Path) -> PathBuf { app_dir.join("last_capture") }
+
+/// Accepted `refresh_debounce_ms` values; 0 refreshes on every event.
+pub const REFRESH_DEBOUNCE_MS: std::ops::RangeInclusive<u64> = 0..=2_000;
+
+/// `ms` pulled into `REFRESH_DEBOUNCE_MS`.
+pub fn clamp_refresh_debounce_ms(ms: u64) -> u64 {
+    ms.clamp(*REFRESH_DEBOUNCE_MS.start(), *REFRESH_DEBOUNCE_MS.end())
+}
+
+/// Largest accepted `image_full_days`, about a century.
+pub const MAX_IMAGE_FULL_DAYS: u64 = 36_500;
+
+/// Accepted `poll_interval_ms` values.
+pub const POLL_INTERVAL_MS: std::ops::RangeInclusive<u64> = 50..=5_000;
+
+/// `ms` pulled into `POLL_INTERVAL_MS`, so a hand-edited settings file
+/// cannot make the poll loop spin.
+pub fn clamp_poll_interval_ms(ms: u64) -> u64 {
+    ms.clamp(*POLL_INTERVAL_MS.start(), *POLL_INTERVAL_MS.end())
+}
+
+/// Loads settings, keeping every key that parses: missing keys take their
+/// defaults, unknown keys (from a newer version) are ignored, and a key
+/// with a value this version can't read falls back alone instead of
+/// discarding the whole file. `None` if the file is missing or not JSON.
 pub fn load_settings(path: &PathBuf) -> Option<Settings> {
-    if let Ok(data) = fs::read(path) {
-        serde_json::from_slice(&data).ok()
-    } else { None }
+    let data = fs::read(path).ok()?;
+    let serde_json::Value::Object(obj) = serde_json::from_slice(&data).ok()? else { return None };
+    if let Ok(s) = serde_json::from_value(obj.clone().into()) {
+        return Some(s);
+    }
+    let mut merged = serde_json::to_value(Settings::default()).ok()?;
+    for (key, value) in obj {
+        let mut candidate = merged.clone();
+        candidate[key.as_str()] = value;
+        if serde_json::from_value::<Settings>(candidate.clone()).is_ok() {
+            merged = candidate;
+        } else {
+            eprintln!("ignoring unreadable setting `{key}`");
+        }
+    }
+    serde_json::from_value(merged).ok()
 }
 
 pub fn save_settings(path: &PathBuf, s: &Settings) {
@@ -25,9 +311,121 @@ pub fn save_settings(path: &PathBuf, s: &Settings) {
     if let Ok(bytes) = serde_json::to_vec_pretty(s) { let _ = fs::write(path, bytes); }
 }
 
+/// Last kind filter and search query, kept in `session.json` next to the
+/// settings while `restore_search_on_open` is set.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FilterState {
+    pub kind: Option<String>,
+    pub query: String,
+}
+
+pub fn session_path(app_dir: PathBuf) -> PathBuf { app_dir.join("session.json") }
+
+pub fn load_filter_state(path: &PathBuf) -> FilterState {
+    fs::read(path).ok().and_then(|d| serde_json::from_slice(&d).ok()).unwrap_or_default()
+}
+
+/// Saves `f`, or removes the session file when `f` is empty.
+pub fn save_filter_state(path: &PathBuf, f: &FilterState) {
+    if *f == FilterState::default() {
+        let _ = fs::remove_file(path);
+        return;
+    }
+    if let Some(dir) = path.parent() { let _ = fs::create_dir_all(dir); }
+    if let Ok(bytes) = serde_json::to_vec_pretty(f) { let _ = fs::write(path, bytes); }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<Database>,
     pub crypto: Arc<KeyManager>,
     pub settings: Arc<Mutex<Settings>>,
+    /// Set to stop a running `reclassify_items` pass between batches.
+    pub reclassify_cancel: Arc<AtomicBool>,
+    /// Set when the database could not be opened in the app data dir.
+    pub storage_warning: Option<String>,
+    pub locked_captures: Arc<LockedCaptures>,
+    pub self_writes: Arc<SelfWrites>,
+    pub storm_guard: Arc<StormGuard>,
+    pub last_capture: Arc<LastCapture>,
+    /// Set while free space is under `low_disk_bytes`, see `run_disk_check`.
+    pub low_disk: Arc<AtomicBool>,
+    pub wipe_confirm: Arc<WipeConfirm>,
+    pub activity: Arc<Activity>,
+    /// Pid of the app that was frontmost when the window was last shown
+    /// with focus; `paste_and_restore_focus` returns to it.
+    pub previous_app: Arc<Mutex<Option<i32>>>,
+    /// Set while Cliper has the Accessibility permission, see
+    /// `run_permission_check`.
+    pub accessibility: Arc<AtomicBool>,
+}
+
+/// When the user last interacted with Cliper, in unix millis; the
+/// auto-lock timer counts from here.
+pub struct Activity {
+    last: AtomicI64,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self { last: AtomicI64::new(crate::db::now_millis()) }
+    }
+}
+
+impl Activity {
+    /// Restarts the auto-lock countdown.
+    pub fn touch(&self) {
+        self.touch_at(crate::db::now_millis());
+    }
+
+    pub fn touch_at(&self, now_ms: i64) {
+        self.last.fetch_max(now_ms, Ordering::Relaxed);
+    }
+
+    pub fn last(&self) -> i64 {
+        self.last.load(Ordering::Relaxed)
+    }
+
+    /// Seconds until auto-lock at `now_ms`, rounded up so the countdown
+    /// only reads 0 once the deadline has passed. `None` when
+    /// `auto_lock_minutes` is 0 (never lock).
+    pub fn remaining_at(&self, now_ms: i64, auto_lock_minutes: u64) -> Option<u64> {
+        if auto_lock_minutes == 0 {
+            return None;
+        }
+        let deadline = self.last().saturating_add(auto_lock_minutes.saturating_mul(60_000).min(i64::MAX as u64) as i64);
+        Some(((deadline - now_ms).max(0) as u64).div_ceil(1000))
+    }
+}
+
+/// How long a panic-wipe confirmation token stays valid.
+pub const WIPE_TOKEN_TTL: Duration = Duration::from_secs(60);
+
+/// One-time tokens that `panic_wipe` requires, so a stray call or a single
+/// keypress can never erase history on its own.
+#[derive(Default)]
+pub struct WipeConfirm {
+    pending: Mutex<Option<(String, Instant)>>,
+}
+
+impl WipeConfirm {
+    /// Issues a fresh token, replacing any earlier one.
+    pub fn issue(&self) -> anyhow::Result<String> {
+        let mut bytes = [0u8; 16];
+        ring::rand::SecureRandom::fill(&ring::rand::SystemRandom::new(), &mut bytes)
+            .map_err(|_| anyhow::anyhow!("rng failed"))?;
+        let token = hex::encode(bytes);
+        *self.pending.lock() = Some((token.clone(), Instant::now()));
+        Ok(token)
+    }
+
+    /// Consumes the pending token; true only if it matches and has not
+    /// expired. Any attempt, right or wrong, uses the token up.
+    pub fn redeem(&self, token: &str) -> bool {
+        match self.pending.lock().take() {
+            Some((t, issued)) => t == token && issued.elapsed() <= WIPE_TOKEN_TTL,
+            None => false,
+        }
+    }
 }
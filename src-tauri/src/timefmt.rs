@@ -0,0 +1,97 @@
+use anyhow::{anyhow, Result};
+use time::{OffsetDateTime, UtcOffset};
+
+/// How `format_timestamp` renders a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampStyle {
+    /// "just now", "5 min ago", "yesterday, 09:30"; the short form past a week.
+    Relative,
+    /// Date and time, e.g. "2026-10-16 14:05".
+    Short,
+    /// Weekday, full date and time with seconds and offset.
+    Long,
+}
+
+/// Timestamps this far ahead of the clock still read as "just now";
+/// further ahead (clock skew, synced history) they get the short form.
+const FUTURE_TOLERANCE_MS: i64 = 60_000;
+
+/// Offset of the system timezone at `unix_ms`, DST included; UTC when it
+/// cannot be determined.
+pub fn local_offset_at(unix_ms: i64) -> UtcOffset {
+    #[cfg(unix)]
+    unsafe {
+        let secs = unix_ms.div_euclid(1000) as libc::time_t;
+        let mut tm: libc::tm = std::mem::zeroed();
+        if !libc::localtime_r(&secs, &mut tm).is_null() {
+            if let Ok(offset) = UtcOffset::from_whole_seconds(tm.tm_gmtoff as i32) {
+                return offset;
+            }
+        }
+    }
+    UtcOffset::UTC
+}
+
+fn to_local(unix_ms: i64, offset: UtcOffset) -> Result<OffsetDateTime> {
+    let dt = OffsetDateTime::from_unix_timestamp_nanos(unix_ms as i128 * 1_000_000)
+        .map_err(|_| anyhow!("timestamp {unix_ms} is out of range"))?;
+    Ok(dt.to_offset(offset))
+}
+
+fn short(dt: OffsetDateTime) -> String {
+    format!("{:04}-{:02}-{:02} {:02}:{:02}", dt.year(), dt.month() as u8, dt.day(), dt.hour(), dt.minute())
+}
+
+fn long(dt: OffsetDateTime) -> String {
+    let offset = dt.offset();
+    let (h, m, _) = offset.as_hms();
+    format!(
+        "{}, {} {} {} {:02}:{:02}:{:02} UTC{}{:02}:{:02}",
+        dt.weekday(),
+        dt.day(),
+        dt.month(),
+        dt.year(),
+        dt.hour(),
+        dt.minute(),
+        dt.second(),
+        if offset.is_negative() { '-' } else { '+' },
+        h.unsigned_abs(),
+        m.unsigned_abs(),
+    )
+}
+
+/// Formats `unix_ms` (an item's `created_at`) as seen at `now_ms` in the
+/// timezone `offset`. Relative times count calendar days in that timezone,
+/// so "yesterday" means the previous local date, not 24 hours ago.
+pub fn format_timestamp_at(unix_ms: i64, now_ms: i64, offset: UtcOffset, style: TimestampStyle) -> Result<String> {
+    let dt = to_local(unix_ms, offset)?;
+    match style {
+        TimestampStyle::Short => return Ok(short(dt)),
+        TimestampStyle::Long => return Ok(long(dt)),
+        TimestampStyle::Relative => {}
+    }
+    let age_ms = now_ms - unix_ms;
+    if age_ms < -FUTURE_TOLERANCE_MS {
+        return Ok(short(dt));
+    }
+    if age_ms < 60_000 {
+        return Ok("just now".into());
+    }
+    if age_ms < 3_600_000 {
+        return Ok(format!("{} min ago", age_ms / 60_000));
+    }
+    let today = to_local(now_ms, offset)?.date();
+    let days = (today - dt.date()).whole_days();
+    Ok(match days {
+        0 => format!("{} h ago", age_ms / 3_600_000),
+        1 => format!("yesterday, {:02}:{:02}", dt.hour(), dt.minute()),
+        d if d < 7 => format!("{d} days ago"),
+        _ => short(dt),
+    })
+}
+
+/// `format_timestamp_at` for now, in the system timezone.
+pub fn format_timestamp(unix_ms: i64, style: TimestampStyle) -> Result<String> {
+    format_timestamp_at(unix_ms, crate::db::now_millis(), local_offset_at(unix_ms), style)
+}
@@ -0,0 +1,39 @@
+use tauri::Icon;
+
+/// Tray icon compiled into the binary, used when the configured one is
+/// missing or empty so the menu bar item never ends up invisible.
+const FALLBACK_TRAY_PNG: &[u8] = include_bytes!("../icons/tray_fallback.png");
+
+/// The embedded fallback as RGBA; `None` only if the embedded PNG is broken.
+pub fn fallback_icon() -> Option<Icon> {
+    let img = image::load_from_memory_with_format(FALLBACK_TRAY_PNG, image::ImageFormat::Png).ok()?.to_rgba8();
+    let (width, height) = img.dimensions();
+    Some(Icon::Rgba { rgba: img.into_raw(), width, height })
+}
+
+/// Whether `icon` has something to draw: a non-empty file, bytes, or pixels.
+pub fn is_usable(icon: Option<&Icon>) -> bool {
+    match icon {
+        None => false,
+        Some(Icon::File(path)) => std::fs::metadata(path).is_ok_and(|m| m.len() > 0),
+        Some(Icon::Raw(bytes)) => !bytes.is_empty(),
+        Some(Icon::Rgba { rgba, width, height }) => {
+            *width > 0 && *height > 0 && rgba.len() == (*width as usize) * (*height as usize) * 4
+        }
+        #[allow(unreachable_patterns)]
+        Some(_) => true,
+    }
+}
+
+/// The tray to build: the configured icon when usable, else the fallback.
+pub fn system_tray(configured: Option<&Icon>) -> tauri::SystemTray {
+    let tray = tauri::SystemTray::new();
+    if is_usable(configured) {
+        return tray;
+    }
+    eprintln!("tray icon missing or empty, using the built-in one");
+    match fallback_icon() {
+        Some(icon) => tray.with_icon(icon),
+        None => tray,
+    }
+}
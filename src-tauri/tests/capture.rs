@@ -0,0 +1,1150 @@
+use arboard::ImageData;
+use cliper_lib::clipboard::charset::{decode_text_bytes, DecodedText};
+use cliper_lib::clipboard::html::{html_preview, sanitize_html, HtmlPreview};
+use cliper_lib::clipboard::autopin::{apply_auto_pin, AutoPinRule, AutoPinRules, MAX_AUTO_PIN_RULES};
+use cliper_lib::clipboard::decode::{decode_base64_text, decode_preview, DecodedPreview};
+use cliper_lib::clipboard::detect::{detect_text_kind, reclassify_text_items};
+use cliper_lib::clipboard::import::import_text_files;
+use cliper_lib::clipboard::preview::{file_preview, FilePreview};
+use cliper_lib::clipboard::service::save_service_text;
+use cliper_lib::clipboard::transform::{apply_transforms, CaptureTransform};
+use cliper_lib::clipboard::typed::store_typed;
+use cliper_lib::clipboard::{copy_back, copy_needs_key, dedup_files_by_inode, decode_image, detach_item, downgrade_old_images, item_thumbnail, dump_snapshot, make_thumbnail, preview_data_url, extra_types, restored_types, file_to_paste, find_matching, resolve_file_path, seal_file_paths, materialize_file, remove_restored_file, restore_file, restored_files_dir, CopyError, save_item_to, store_snapshot, wrapped_text, joined_text, Snapshot};
+use cliper_lib::api::days_before;
+use cliper_lib::crypto::KeyManager;
+use cliper_lib::db::{now_millis, Database, NewItem};
+use cliper_lib::clipboard::{is_excluded_app, is_transient, over_capture_limit, size_route, SizeRoute, MAX_CAPTURE_BYTES, NATIVE_READ_BYTES, LastCapture, LockedCaptureBehavior, LockedCaptures, SelfWrites, Settle, StormGuard, ThumbnailFormat};
+use cliper_lib::state::Settings;
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+#[test]
+fn text_and_image_are_both_kept() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let snap = |text: &str| Snapshot {
+        text: Some(text.into()),
+        image: Some(ImageData {
+            width: 2,
+            height: 2,
+            bytes: vec![255u8; 2 * 2 * 4].into(),
+        }),
+        ..Default::default()
+    };
+    let ids = store_snapshot(&db, &km, &Settings::default(), snap("hello from mail")).unwrap();
+
+    // the text is the primary representation and lands on top
+    let list = db.list_recent(10).unwrap();
+    assert_eq!(list.len(), 2);
+    assert_eq!(list[0].kind, "text");
+    assert_eq!(list[1].kind, "image");
+    assert_eq!(ids, vec![list[1].id, list[0].id]);
+
+    // with keep_image_with_text off only the text is stored
+    let settings = Settings { keep_image_with_text: false, ..Default::default() };
+    let ids = store_snapshot(&db, &km, &settings, snap("reply from mail")).unwrap();
+    let list = db.list_recent(10).unwrap();
+    assert_eq!(list.len(), 3);
+    assert_eq!(list[0].kind, "text");
+    assert_eq!(ids, vec![list[0].id]);
+}
+
+#[test]
+fn file_urls_take_priority() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let snap = Snapshot {
+        file_paths: vec!["/tmp/report.pdf".into()],
+        text: Some("report.pdf".into()),
+        ..Default::default()
+    };
+    store_snapshot(&db, &km, &Settings::default(), snap).unwrap();
+
+    let list = db.list_recent(10).unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].kind, "file");
+}
+
+#[test]
+fn html_preview_is_sanitized_with_text_fallback() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let html = r#"<p onclick="steal()">Hi <b>there</b></p><script>alert(1)</script><img src="https://tracker.example/p.gif"><a href="https://example.com">link</a>"#;
+    let snap = Snapshot { text: Some("Hi there".into()), html: Some(html.as_bytes().to_vec()), ..Default::default() };
+    let ids = store_snapshot(&db, &km, &Settings::default(), snap).unwrap();
+    let HtmlPreview::Html { html: clean } = html_preview(&db, &km, ids[0]).unwrap() else { panic!("expected html") };
+    assert!(clean.contains("<b>there</b>"));
+    assert!(clean.contains(r#"href="https://example.com""#));
+    for banned in ["script", "alert", "onclick", "tracker.example"] {
+        assert!(!clean.contains(banned), "{banned} survived: {clean}");
+    }
+
+    let snap = Snapshot { text: Some("plain only".into()), ..Default::default() };
+    let ids = store_snapshot(&db, &km, &Settings::default(), snap).unwrap();
+    assert_eq!(html_preview(&db, &km, ids[0]).unwrap(), HtmlPreview::Text { text: "plain only".into() });
+
+    db.set_sensitive(ids[0], true).unwrap();
+    assert!(html_preview(&db, &km, ids[0]).is_err());
+    assert!(!sanitize_html(r#"<a href="/relative">x</a>"#).contains("href"));
+}
+
+#[test]
+fn extra_types_follow_the_primary_item() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let custom = ("org.chromium.web-custom-data".to_string(), b"vscode-editor-data".to_vec());
+    let snap = Snapshot {
+        text: Some("let x = 1;".into()),
+        image: Some(ImageData { width: 1, height: 1, bytes: vec![0u8; 4].into() }),
+        extra_types: vec![custom.clone()],
+        ..Default::default()
+    };
+    let ids = store_snapshot(&db, &km, &Settings::default(), snap).unwrap();
+    let (image, text) = (ids[0], ids[1]);
+    assert_eq!(extra_types(&db, &km, text).unwrap(), vec![custom.clone()]);
+    assert!(extra_types(&db, &km, image).unwrap().is_empty());
+    let (_, sealed) = &db.get_extra_types(text).unwrap()[0];
+    assert!(!sealed.windows(6).any(|w| w == b"vscode"));
+
+    // a re-capture of the same text replaces the extras
+    let source = ("org.chromium.source-url".to_string(), b"https://example.com".to_vec());
+    let snap = Snapshot { text: Some("let x = 1;".into()), extra_types: vec![source.clone()], ..Default::default() };
+    assert_eq!(store_snapshot(&db, &km, &Settings::default(), snap).unwrap(), vec![text]);
+    assert_eq!(extra_types(&db, &km, text).unwrap(), vec![source]);
+
+    db.delete_item(text).unwrap();
+    assert!(db.get_extra_types(text).unwrap().is_empty());
+}
+
+#[test]
+fn captured_html_is_restored_with_the_text() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let html = br#"<p>See <a href="https://example.com">the docs</a></p>"#.to_vec();
+    let snap = Snapshot {
+        text: Some("See the docs".into()),
+        html: Some(html.clone()),
+        extra_types: vec![("org.chromium.source-url".into(), b"https://example.com".to_vec())],
+        ..Default::default()
+    };
+    let id = store_snapshot(&db, &km, &Settings::default(), snap).unwrap()[0];
+    let html_blob = db.get_html_blob(id).unwrap().unwrap();
+    assert_ne!(html_blob, html, "stored encrypted");
+
+    // pasted back as captured, not sanitized, ahead of the extra types
+    assert_eq!(
+        restored_types(&db, &km, id).unwrap(),
+        vec![("public.html".to_string(), html), ("org.chromium.source-url".to_string(), b"https://example.com".to_vec())]
+    );
+
+    let plain = store_snapshot(&db, &km, &Settings::default(), Snapshot { text: Some("plain".into()), ..Default::default() }).unwrap()[0];
+    assert!(restored_types(&db, &km, plain).unwrap().is_empty());
+}
+
+#[test]
+fn storm_guard_suppresses_rapid_identical_changes() {
+    let guard = StormGuard::default();
+    let spam = Snapshot { text: Some("same again".into()), ..Default::default() }.fingerprint().unwrap();
+    let other = Snapshot { text: Some("something else".into()), ..Default::default() }.fingerprint().unwrap();
+    assert_ne!(spam, other);
+    assert!(Snapshot::default().fingerprint().is_none());
+
+    let window = Duration::from_millis(1000);
+    let start = Instant::now();
+    // 30 writes 20 ms apart: the first 5 go through, the rest are ignored
+    let suppressed: Vec<bool> = (0..30)
+        .map(|i| guard.observe(&spam, start + Duration::from_millis(i * 20), 5, window))
+        .collect();
+    assert!(suppressed[..5].iter().all(|s| !s));
+    assert!(suppressed[5..].iter().all(|s| *s));
+    // other content is unaffected during the storm
+    assert!(!guard.observe(&other, start + Duration::from_millis(600), 5, window));
+    // once the writer quiets down the content is captured again
+    assert!(!guard.observe(&spam, start + Duration::from_millis(600 + 1001), 5, window));
+    // 0 disables the guard
+    assert!((0..20).all(|_| !guard.observe(&other, start, 0, window)));
+}
+
+#[test]
+fn settle_waits_for_the_clipboard_to_stop_changing() {
+    let mut settle = Settle::default();
+    let stable = Duration::from_millis(500);
+    let start = Instant::now();
+    let at = |ms| start + Duration::from_millis(ms);
+
+    // a selection copied on every drag step, 100 ms apart
+    for i in 0..8 {
+        let ms = i as u64 * 100;
+        settle.changed(i, at(ms));
+        assert_eq!(settle.take_settled(at(ms + 50), stable), None);
+    }
+    assert_eq!(settle.take_settled(at(1_100), stable), None);
+    // only the final selection is captured, once
+    assert_eq!(settle.take_settled(at(1_200), stable), Some(7));
+    assert_eq!(settle.take_settled(at(5_000), stable), None);
+
+    // 0 captures every change right away
+    settle.changed(8, at(6_000));
+    assert_eq!(settle.take_settled(at(6_000), Duration::ZERO), Some(8));
+}
+
+#[test]
+fn unchanged_clipboard_is_not_recaptured_after_restart() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("last_capture");
+    let copied = Snapshot { text: Some("copied before quitting".into()), ..Default::default() }.fingerprint().unwrap();
+    let newer = Snapshot { text: Some("copied after launch".into()), ..Default::default() }.fingerprint().unwrap();
+
+    // first launch: nothing to compare against
+    let run = LastCapture::load(path.clone());
+    assert!(!run.is_leftover(&copied));
+    run.record(&copied);
+    // the same content copied again in the same run is a real capture
+    assert!(!run.is_leftover(&copied));
+
+    // relaunch with the clipboard unchanged: the first pass is skipped once
+    let run = LastCapture::load(path.clone());
+    assert!(run.is_leftover(&copied));
+    assert!(!run.is_leftover(&copied));
+
+    // relaunch after the clipboard changed while the app was closed
+    let run = LastCapture::load(path.clone());
+    assert!(!run.is_leftover(&newer));
+    run.record(&newer);
+    assert!(LastCapture::load(path).is_leftover(&newer));
+
+    // without a saved hash nothing is skipped
+    assert!(!LastCapture::load(tmp.path().join("missing")).is_leftover(&copied));
+}
+
+#[test]
+fn auto_pin_rules_pin_matching_captures() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let rules = vec![
+        AutoPinRule { source_app: Some("com.example.vault".into()), text_pattern: None },
+        AutoPinRule { source_app: None, text_pattern: Some("^TODO:".into()) },
+        AutoPinRule { source_app: Some("com.apple.Terminal".into()), text_pattern: Some(r"^ssh \S+".into()) },
+    ];
+    let compiled = AutoPinRules::compile(&rules).unwrap();
+    assert!(compiled.matches(Some("com.example.vault"), None));
+    assert!(compiled.matches(None, Some("TODO: ship it")));
+    assert!(!compiled.matches(None, Some("not a TODO: here")));
+    assert!(compiled.matches(Some("com.apple.Terminal"), Some("ssh prod-1")));
+    assert!(!compiled.matches(Some("com.apple.Safari"), Some("ssh prod-1")));
+
+    let capture = |text: &str, app: Option<&str>| {
+        let snap = Snapshot { text: Some(text.into()), source_app: app.map(Into::into), ..Default::default() };
+        let ids = store_snapshot(&db, &km, &Settings::default(), snap).unwrap();
+        apply_auto_pin(&db, &rules, app, Some(text), &ids).unwrap();
+        db.get_item(ids[0]).unwrap().is_pinned
+    };
+    assert!(capture("TODO: call the bank", None));
+    assert!(capture("hunter2", Some("com.example.vault")));
+    assert!(!capture("hello", Some("com.apple.Safari")));
+    assert!(!apply_auto_pin(&db, &[], None, Some("TODO: x"), &[1]).unwrap());
+
+    let bad = |rule: AutoPinRule| AutoPinRules::compile(&[rule]).is_err();
+    assert!(bad(AutoPinRule::default()));
+    assert!(bad(AutoPinRule { source_app: Some("  ".into()), text_pattern: None }));
+    assert!(bad(AutoPinRule { source_app: None, text_pattern: Some("(unclosed".into()) }));
+    assert!(bad(AutoPinRule { source_app: None, text_pattern: Some("a{1000}{1000}".into()) }));
+    let many = vec![AutoPinRule { source_app: Some("x".into()), text_pattern: None }; MAX_AUTO_PIN_RULES + 1];
+    assert!(AutoPinRules::compile(&many).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn dedup_files_by_inode_merges_aliases_of_one_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().join("db")).unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let real = tmp.path().join("report.txt");
+    std::fs::write(&real, b"quarterly numbers").unwrap();
+    let symlink = tmp.path().join("alias.txt");
+    std::os::unix::fs::symlink(&real, &symlink).unwrap();
+    let hardlink = tmp.path().join("hard.txt");
+    std::fs::hard_link(&real, &hardlink).unwrap();
+    let other = tmp.path().join("other.txt");
+    std::fs::write(&other, b"something else").unwrap();
+
+    let mut ids = Vec::new();
+    for p in [&real, &symlink, &hardlink, &other, &tmp.path().join("gone.txt")] {
+        let snap = Snapshot { file_paths: vec![p.to_string_lossy().into_owned()], ..Default::default() };
+        ids.extend(store_snapshot(&db, &km, &Settings::default(), snap).unwrap());
+    }
+    db.pin_item(ids[1], true).unwrap();
+
+    assert_eq!(dedup_files_by_inode(&db, &km).unwrap(), 2);
+    let left: Vec<i64> = db.list_files().unwrap().iter().map(|i| i.id).collect();
+    assert_eq!(left.len(), 3);
+    assert!(left.contains(&ids[0]) && left.contains(&ids[3]) && left.contains(&ids[4]));
+    // the pin moved to the item that was kept
+    assert!(db.get_item(ids[0]).unwrap().is_pinned);
+    assert_eq!(dedup_files_by_inode(&db, &km).unwrap(), 0);
+}
+
+#[test]
+fn detects_specific_text_kinds() {
+    assert_eq!(detect_text_kind("https://example.com/a?b=1"), "url");
+    assert_eq!(detect_text_kind("#1e90ff"), "color");
+    assert_eq!(detect_text_kind("rgba(0, 0, 0, 0.5)"), "color");
+    assert_eq!(detect_text_kind("fn main() {\n    println!(\"hi\");\n}"), "code");
+    assert_eq!(detect_text_kind("just a sentence, see example.com"), "text");
+    assert_eq!(detect_text_kind("#hashtag"), "text");
+}
+
+#[test]
+fn reclassify_updates_plain_text_items() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    // rows captured before detection existed are all plain "text"
+    for t in ["https://example.com", "hello there", "#ffffff"] {
+        db.insert_item(NewItem {
+            kind: "text".into(),
+            size: t.len() as i64,
+            sha256: Database::compute_sha256(t.as_bytes()),
+            file_path: None,
+            file_path_blob: None,
+            content_blob: Some(km.encrypt(t.as_bytes()).unwrap()),
+            preview_blob: None,
+            rtf_blob: None,
+            html_blob: None,
+            source_app: None,
+            uti: None,
+        })
+        .unwrap();
+    }
+
+    let mut calls = 0;
+    let report = reclassify_text_items(&db, &km, &AtomicBool::new(false), |_, _| calls += 1).unwrap();
+    assert_eq!(report.scanned, 3);
+    assert_eq!(report.changed, 2);
+    assert!(calls > 0);
+    assert_eq!(db.count_kind("text").unwrap(), 1);
+
+    let cancelled = reclassify_text_items(&db, &km, &AtomicBool::new(true), |_, _| {}).unwrap();
+    assert!(cancelled.cancelled);
+    assert_eq!(cancelled.scanned, 0);
+}
+
+#[test]
+fn detach_deletes_only_after_a_verified_save() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let text = b"sensitive clip";
+    let id = db
+        .insert_item(NewItem {
+            kind: "text".into(),
+            size: text.len() as i64,
+            sha256: Database::compute_sha256(text),
+            file_path: None,
+            file_path_blob: None,
+            content_blob: Some(km.encrypt(text).unwrap()),
+            preview_blob: None,
+            rtf_blob: None,
+            html_blob: None,
+            source_app: None,
+            uti: None,
+        })
+        .unwrap();
+
+    // unwritable destination: nothing is deleted
+    let bad = tmp.path().join("missing-dir").join("out.txt");
+    assert!(detach_item(&db, &km, id, &bad).is_err());
+    assert_eq!(db.list_recent(10).unwrap().len(), 1);
+
+    let dest = tmp.path().join("out.txt");
+    let saved = detach_item(&db, &km, id, &dest).unwrap();
+    assert_eq!(saved.bytes, text.len() as u64);
+    assert_eq!(std::fs::read(&dest).unwrap(), text);
+    assert!(db.list_recent(10).unwrap().is_empty());
+}
+
+#[test]
+fn file_previews_cover_text_images_and_binaries() {
+    let tmp = tempfile::tempdir().unwrap();
+
+    let notes = tmp.path().join("notes.txt");
+    let body: String = (1..=40).map(|i| format!("line {i}\n")).collect();
+    std::fs::write(&notes, body).unwrap();
+    match file_preview(&notes, 64, ThumbnailFormat::Png).unwrap() {
+        Some(FilePreview::Text { snippet, truncated }) => {
+            assert!(snippet.starts_with("line 1\nline 2"));
+            assert!(!snippet.contains("line 40"));
+            assert!(truncated);
+        }
+        other => panic!("expected text preview, got {other:?}"),
+    }
+
+    let blob = tmp.path().join("data.bin");
+    std::fs::write(&blob, [0u8, 159, 146, 150, 0, 1]).unwrap();
+    assert!(file_preview(&blob, 64, ThumbnailFormat::Png).unwrap().is_none());
+
+    let pic = tmp.path().join("pic.PNG");
+    image::RgbaImage::from_pixel(300, 200, image::Rgba([0, 128, 255, 255]))
+        .save_with_format(&pic, image::ImageFormat::Png)
+        .unwrap();
+    match file_preview(&pic, 64, ThumbnailFormat::Png).unwrap() {
+        Some(FilePreview::Image { data_url }) => assert!(data_url.starts_with("data:image/png;base64,")),
+        other => panic!("expected image preview, got {other:?}"),
+    }
+}
+
+#[test]
+fn locked_captures_follow_the_configured_behavior() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    let held = LockedCaptures::default();
+    let snap = |t: &str| Snapshot { text: Some(t.into()), ..Default::default() };
+
+    assert_eq!(held.hold(snap("dropped"), LockedCaptureBehavior::Drop), None);
+    assert_eq!(held.hold(snap("missed 1"), LockedCaptureBehavior::Notify), Some(1));
+    assert_eq!(held.hold(snap("missed 2"), LockedCaptureBehavior::Notify), Some(2));
+    assert_eq!(held.hold(snap("kept"), LockedCaptureBehavior::Buffer), None);
+    assert_eq!(held.missed(), 2);
+
+    km.unlock().unwrap();
+    let flushed = held.flush(&db, &km, &Settings::default());
+    assert_eq!(flushed.ids.len(), 1);
+    assert!(flushed.errors.is_empty());
+    assert_eq!(held.missed(), 0);
+    assert_eq!(db.list_recent(10).unwrap().len(), 1);
+
+    // buffer is emptied by the flush
+    let flushed = held.flush(&db, &km, &Settings::default());
+    assert!(flushed.ids.is_empty() && flushed.errors.is_empty());
+
+    // a snapshot that cannot be stored does not cost the ones after it
+    km.lock();
+    let broken = Snapshot { image: Some(ImageData { width: 4, height: 4, bytes: vec![0u8; 3].into() }), ..Default::default() };
+    held.hold(snap("before"), LockedCaptureBehavior::Buffer);
+    held.hold(broken, LockedCaptureBehavior::Buffer);
+    held.hold(snap("after"), LockedCaptureBehavior::Buffer);
+    km.unlock().unwrap();
+    let flushed = held.flush(&db, &km, &Settings::default());
+    assert_eq!(flushed.ids.len(), 2);
+    assert_eq!(flushed.errors.len(), 1);
+    assert_eq!(db.list_recent(10).unwrap().len(), 3);
+}
+
+#[test]
+fn imports_text_files_and_reports_skips() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    let snippets = tmp.path().join("snippets");
+    std::fs::create_dir(&snippets).unwrap();
+    std::fs::write(snippets.join("greeting.txt"), "Hello, {name}!").unwrap();
+    std::fs::write(snippets.join("latin1.txt"), [0x63u8, 0x61, 0x66, 0xe9]).unwrap();
+    let paths: Vec<String> = ["greeting.txt", "latin1.txt", "missing.txt"]
+        .iter()
+        .map(|f| snippets.join(f).to_string_lossy().into_owned())
+        .collect();
+
+    // locked: nothing is read
+    assert!(import_text_files(&db, &km, &Settings::default(), &paths, true).is_err());
+
+    km.unlock().unwrap();
+    let report = import_text_files(&db, &km, &Settings::default(), &paths, true).unwrap();
+    assert_eq!((report.imported, report.skipped), (1, 2));
+    assert_eq!(report.files[0].title, "greeting");
+    assert!(report.files[1].skipped.is_some());
+
+    let list = db.list_recent(10).unwrap();
+    assert_eq!(list.len(), 1);
+    assert!(list[0].is_pinned);
+    assert_eq!(Some(list[0].id), report.files[0].id);
+}
+
+#[test]
+fn materialized_files_survive_deleting_the_original() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let original = tmp.path().join("contract.txt");
+    std::fs::write(&original, b"signed copy").unwrap();
+    let snap = Snapshot { file_paths: vec![original.to_string_lossy().into_owned()], ..Default::default() };
+    let id = store_snapshot(&db, &km, &Settings::default(), snap).unwrap()[0];
+
+    assert_eq!(materialize_file(&db, &km, id).unwrap(), 11);
+    std::fs::remove_file(&original).unwrap();
+
+    let dest = tmp.path().join("restored.txt");
+    save_item_to(&db, &km, id, &dest).unwrap();
+    assert_eq!(std::fs::read(&dest).unwrap(), b"signed copy");
+
+    let (_, ct, _, _, fp) = db.get_item_raw(id).unwrap();
+    let pasted = restore_file(&km, id, &fp.unwrap(), &ct.unwrap()).unwrap();
+    assert_eq!(pasted.file_name().unwrap(), "contract.txt");
+    assert_eq!(std::fs::read(pasted).unwrap(), b"signed copy");
+
+    // the original is gone now
+    assert!(materialize_file(&db, &km, id).is_err());
+}
+
+#[test]
+fn bad_image_buffer_does_not_drop_the_text() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let snap = Snapshot {
+        text: Some("caption under a broken picture".into()),
+        // 2x2 RGBA needs 16 bytes
+        image: Some(ImageData { width: 2, height: 2, bytes: vec![0u8; 3].into() }),
+        ..Default::default()
+    };
+    let ids = store_snapshot(&db, &km, &Settings::default(), snap).unwrap();
+    assert_eq!(ids.len(), 1);
+    let list = db.list_recent(10).unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!(list[0].kind, "text");
+
+    // with nothing else to keep, the image error is reported
+    let only_bad = Snapshot {
+        image: Some(ImageData { width: 2, height: 2, bytes: vec![0u8; 3].into() }),
+        ..Default::default()
+    };
+    assert!(store_snapshot(&db, &km, &Settings::default(), only_bad).is_err());
+}
+
+#[test]
+fn service_text_is_saved_pinned() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    let settings = Settings::default();
+    assert!(save_service_text(&db, &km, &settings, "selected text").is_err());
+
+    km.unlock().unwrap();
+    assert!(save_service_text(&db, &km, &settings, "  \n").is_err());
+
+    let id = save_service_text(&db, &km, &settings, "https://example.com").unwrap();
+    let list = db.list_recent(10).unwrap();
+    assert_eq!(list.len(), 1);
+    assert_eq!((list[0].id, list[0].kind.as_str()), (id, "url"));
+    assert!(list[0].is_pinned);
+
+    // already in history: pinned in place rather than duplicated
+    store_snapshot(&db, &km, &settings, Snapshot { text: Some("copied earlier".into()), ..Default::default() }).unwrap();
+    save_service_text(&db, &km, &settings, "copied earlier").unwrap();
+    let list = db.list_recent(10).unwrap();
+    assert_eq!(list.len(), 2);
+    assert!(list.iter().all(|i| i.is_pinned));
+}
+
+#[test]
+fn typed_blobs_keep_their_uti() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    let pdf = b"%PDF-1.4 fake";
+
+    assert!(store_typed(&db, &km, "com.adobe.pdf", pdf).is_err());
+    km.unlock().unwrap();
+    assert!(store_typed(&db, &km, "com.apple.webarchive", pdf).is_err());
+    assert!(store_typed(&db, &km, "com.adobe.pdf", b"").is_err());
+
+    let id = store_typed(&db, &km, "com.adobe.pdf", pdf).unwrap();
+    let item = db.get_item(id).unwrap();
+    assert_eq!(item.kind, "blob");
+    assert_eq!(item.uti.as_deref(), Some("com.adobe.pdf"));
+    let (_, ct, ..) = db.get_item_raw(id).unwrap();
+    assert_eq!(km.decrypt(&ct.unwrap()).unwrap(), pdf);
+
+    // same bytes under another type are a separate item
+    assert_eq!(store_typed(&db, &km, "com.adobe.pdf", pdf).unwrap(), id);
+    assert_ne!(store_typed(&db, &km, "public.html", pdf).unwrap(), id);
+}
+
+#[test]
+fn decodes_only_clearly_encoded_text() {
+    use base64::Engine;
+    let b64 = |b: &[u8]| base64::engine::general_purpose::STANDARD.encode(b);
+
+    let message = "The quick brown fox jumps over the lazy dog, twice over for length.";
+    match decode_preview(&b64(message.as_bytes()), 64, ThumbnailFormat::Png) {
+        Some(DecodedPreview::Text { text, truncated }) => assert_eq!((text.as_str(), truncated), (message, false)),
+        other => panic!("expected text, got {other:?}"),
+    }
+
+    let mut png = Vec::new();
+    image::RgbaImage::from_pixel(40, 20, image::Rgba([255, 0, 0, 255]))
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png)
+        .unwrap();
+    let uri = format!("data:image/png;base64,{}", b64(&png));
+    match decode_preview(&uri, 16, ThumbnailFormat::Png) {
+        Some(DecodedPreview::Image { data_url }) => assert!(data_url.starts_with("data:image/png;base64,")),
+        other => panic!("expected image, got {other:?}"),
+    }
+    assert_eq!(decode_preview(&b64(&[0u8; 64]), 16, ThumbnailFormat::Png), Some(DecodedPreview::Binary { bytes: 64 }));
+
+    // wrapped lines are fine
+    let wrapped: String = b64(message.as_bytes()).as_bytes().chunks(20).map(|c| format!("{}\n", std::str::from_utf8(c).unwrap())).collect();
+    assert_eq!(decode_base64_text(&wrapped).unwrap(), message.as_bytes());
+
+    // conservative: short, prose, hex digests and non-base64 data URIs
+    assert!(decode_base64_text("aGVsbG8=").is_none());
+    assert!(decode_base64_text(message).is_none());
+    assert!(decode_base64_text(&"ab12".repeat(16)).is_none());
+    assert!(decode_base64_text("data:text/plain,hello%20world").is_none());
+}
+
+#[test]
+fn capture_transforms_change_what_is_stored_and_deduped() {
+    use CaptureTransform::*;
+
+    assert_eq!(apply_transforms("  a\r\nb\rc  ", &[NormalizeEol, Trim]), "a\nb\nc");
+    assert_eq!(apply_transforms("pass\u{200B}word\u{FEFF}", &[StripZeroWidth]), "password");
+    assert_eq!(apply_transforms("a \t  b\n  c", &[CollapseWhitespace]), "a b\n c");
+    assert_eq!(apply_transforms(" x ", &[]), " x ");
+
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    let settings = Settings { capture_transforms: vec![StripZeroWidth, Trim], ..Settings::default() };
+    let text = |t: &str| Snapshot { text: Some(t.into()), ..Default::default() };
+
+    let a = store_snapshot(&db, &km, &settings, text("token\u{200B}\n")).unwrap();
+    let b = store_snapshot(&db, &km, &settings, text("  token")).unwrap();
+    assert_eq!(a, b);
+    let (_, ct, ..) = db.get_item_raw(a[0]).unwrap();
+    assert_eq!(km.decrypt(&ct.unwrap()).unwrap(), b"token");
+
+    // nothing left after the transforms: nothing stored
+    assert!(store_snapshot(&db, &km, &settings, text(" \u{200B} ")).unwrap().is_empty());
+    assert_eq!(db.list_recent(10).unwrap().len(), 1);
+}
+
+#[test]
+fn wrapped_text_needs_unlock_and_text() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let snap = Snapshot { text: Some("SELECT 1;".into()), ..Default::default() };
+    let text_id = store_snapshot(&db, &km, &Settings::default(), snap).unwrap()[0];
+    let files = Snapshot { file_paths: vec!["/tmp/report.pdf".into()], ..Default::default() };
+    let file_id = store_snapshot(&db, &km, &Settings::default(), files).unwrap()[0];
+
+    assert_eq!(wrapped_text(&db, &km, text_id, "-- ", "\n").unwrap(), "-- SELECT 1;\n");
+    assert_eq!(wrapped_text(&db, &km, text_id, "", "").unwrap(), "SELECT 1;");
+    assert!(wrapped_text(&db, &km, file_id, "<", ">").is_err());
+
+    km.lock();
+    assert!(wrapped_text(&db, &km, text_id, "-- ", "").is_err());
+}
+
+#[test]
+fn joined_text_keeps_selection_order_and_skips_non_text() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    let store = |snap| store_snapshot(&db, &km, &Settings::default(), snap).unwrap()[0];
+    let first = store(Snapshot { text: Some("first".into()), ..Default::default() });
+    let second = store(Snapshot { text: Some("second".into()), ..Default::default() });
+    let file = store(Snapshot { file_paths: vec!["/tmp/report.pdf".into()], ..Default::default() });
+    let before = db.count_items().unwrap();
+
+    assert_eq!(joined_text(&db, &km, &[second, file, first, 999], "\n").unwrap(), ("second\nfirst".to_string(), 2));
+    assert_eq!(joined_text(&db, &km, &[first, first], ", ").unwrap(), ("first, first".to_string(), 2));
+    assert_eq!(joined_text(&db, &km, &[file], "\n").unwrap(), (String::new(), 0));
+    // nothing is stored
+    assert_eq!(db.count_items().unwrap(), before);
+
+    km.lock();
+    assert!(joined_text(&db, &km, &[first], "\n").is_err());
+}
+
+#[test]
+fn self_writes_are_skipped_whatever_the_write_path() {
+    use std::sync::atomic::{AtomicI64, Ordering};
+
+    // stands in for NSPasteboard's changeCount
+    let pasteboard = AtomicI64::new(10);
+    let count = || pasteboard.load(Ordering::SeqCst);
+    let change = || pasteboard.fetch_add(1, Ordering::SeqCst) + 1;
+    let writes = SelfWrites::default();
+
+    // copy_back of plain text: one change
+    writes.around(count, || Ok(change())).unwrap();
+    assert!(writes.is_own(11));
+
+    // the user copies something: captured
+    assert!(!writes.is_own(change()));
+
+    // copy_back with RTF / diagnose_copy: several changes, and a poll can
+    // land in the middle of the write
+    writes
+        .around(count, || {
+            change();
+            assert!(writes.is_own(count()));
+            change();
+            Ok(())
+        })
+        .unwrap();
+    assert!(writes.is_own(13) && writes.is_own(14));
+
+    // a failed write that still touched the pasteboard is ours too
+    assert!(writes.around(count, || -> anyhow::Result<()> { change(); anyhow::bail!("boom") }).is_err());
+    assert!(writes.is_own(15));
+
+    // a write that changed nothing leaves nothing behind
+    writes.around(count, || Ok(())).unwrap();
+    assert!(!writes.is_own(change()));
+}
+
+#[test]
+fn missing_files_fail_typed_unless_content_was_stored() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let kept = tmp.path().join("kept.txt");
+    let lost = tmp.path().join("lost.txt");
+    std::fs::write(&kept, b"kept").unwrap();
+    std::fs::write(&lost, b"lost").unwrap();
+    let store = |p: &std::path::Path| {
+        let snap = Snapshot { file_paths: vec![p.to_string_lossy().into_owned()], ..Default::default() };
+        store_snapshot(&db, &km, &Settings::default(), snap).unwrap()[0]
+    };
+    let kept_id = store(&kept);
+    let lost_id = store(&lost);
+    materialize_file(&db, &km, kept_id).unwrap();
+
+    let (_, ct, _, _, fp) = db.get_item_raw(lost_id).unwrap();
+    assert_eq!(file_to_paste(&km, lost_id, fp.as_deref(), ct.as_deref()).unwrap(), lost);
+
+    std::fs::remove_file(&kept).unwrap();
+    std::fs::remove_file(&lost).unwrap();
+
+    let err = file_to_paste(&km, lost_id, fp.as_deref(), ct.as_deref()).unwrap_err();
+    assert!(matches!(err.downcast_ref::<CopyError>(), Some(CopyError::FileMissing)));
+    assert_eq!(err.to_string(), "file missing");
+
+    let (_, ct, _, _, fp) = db.get_item_raw(kept_id).unwrap();
+    let restored = file_to_paste(&km, kept_id, fp.as_deref(), ct.as_deref()).unwrap();
+    assert_eq!(std::fs::read(restored).unwrap(), b"kept");
+}
+
+#[test]
+fn plain_file_paths_copy_back_while_locked() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    let file = tmp.path().join("notes.txt");
+    std::fs::write(&file, b"notes").unwrap();
+    let snap = || Snapshot { file_paths: vec![file.to_string_lossy().into_owned()], ..Default::default() };
+    let text = Snapshot { text: Some("hello".into()), ..Default::default() };
+
+    // stored while locked, so the path stays plaintext
+    let plain = store_snapshot(&db, &km, &Settings::default(), snap()).unwrap()[0];
+    assert!(!copy_needs_key(&db, plain).unwrap());
+    copy_back(&db, &km, plain).unwrap();
+
+    km.unlock().unwrap();
+    let text_id = store_snapshot(&db, &km, &Settings::default(), text).unwrap()[0];
+    assert!(copy_needs_key(&db, text_id).unwrap());
+    materialize_file(&db, &km, plain).unwrap();
+    assert!(copy_needs_key(&db, plain).unwrap());
+
+    std::fs::write(tmp.path().join("other.txt"), b"other").unwrap();
+    let other = Snapshot { file_paths: vec![tmp.path().join("other.txt").to_string_lossy().into_owned()], ..Default::default() };
+    let sealed = store_snapshot(&db, &km, &Settings { encrypt_file_paths: true, ..Default::default() }, other).unwrap()[0];
+    assert!(db.get_item(sealed).unwrap().file_path.is_none());
+    assert!(copy_needs_key(&db, sealed).unwrap());
+}
+
+#[test]
+fn restored_files_are_private_and_removed_with_the_item() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    // an id no other test restores under, as the directory is shared
+    let id = 987_654;
+    let ct = km.encrypt(b"private").unwrap();
+    let restored = restore_file(&km, id, "/Users/me/private.txt", &ct).unwrap();
+    assert!(restored.starts_with(restored_files_dir()));
+    assert_eq!(std::fs::read(&restored).unwrap(), b"private");
+    assert_eq!(std::fs::metadata(&restored).unwrap().permissions().mode() & 0o777, 0o600);
+    assert_eq!(std::fs::metadata(restored.parent().unwrap()).unwrap().permissions().mode() & 0o777, 0o700);
+
+    remove_restored_file(id);
+    assert!(!restored.exists());
+    assert!(!restored_files_dir().join(id.to_string()).exists());
+}
+
+#[test]
+fn old_images_keep_only_their_thumbnail() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    let settings = Settings::default();
+
+    let image = |shade: u8| Snapshot {
+        image: Some(ImageData { width: 4, height: 4, bytes: vec![shade; 4 * 4 * 4].into() }),
+        ..Default::default()
+    };
+    let old = store_snapshot(&db, &km, &settings, image(10)).unwrap()[0];
+    let pinned = store_snapshot(&db, &km, &settings, image(20)).unwrap()[0];
+    db.pin_item(pinned, true).unwrap();
+
+    // nothing is older than a cutoff in the past
+    assert_eq!(downgrade_old_images(&db, &km, &settings, 0).unwrap(), 0);
+    assert_eq!(downgrade_old_images(&db, &km, &settings, now_millis() + 1).unwrap(), 1);
+
+    let it = db.get_item(old).unwrap();
+    assert!(it.thumbnail_only);
+    assert_eq!(it.stored_len, 0);
+    let (_, content, preview, _, _) = db.get_item_raw(old).unwrap();
+    assert!(content.is_none());
+    assert!(image::load_from_memory(&km.decrypt(&preview.unwrap()).unwrap()).is_ok());
+    assert!(!db.get_item(pinned).unwrap().thumbnail_only);
+
+    let err = copy_back(&db, &km, old).unwrap_err();
+    assert!(matches!(err.downcast_ref::<CopyError>(), Some(CopyError::OriginalNotStored)));
+    assert!(save_item_to(&db, &km, old, &tmp.path().join("out.png")).is_err());
+
+    // copying the same image again stores it in full rather than reviving the thumbnail
+    let again = store_snapshot(&db, &km, &settings, image(10)).unwrap()[0];
+    assert_ne!(again, old);
+    assert!(!db.get_item(again).unwrap().thumbnail_only);
+    assert_eq!(downgrade_old_images(&db, &km, &settings, 0).unwrap(), 0);
+}
+
+#[test]
+fn downgrade_cutoff_saturates_for_huge_day_counts() {
+    assert_eq!(days_before(1_000, 0), 1_000);
+    assert_eq!(days_before(86_400_000 * 3, 2), 86_400_000);
+    assert_eq!(days_before(1_000, u64::MAX), 1_000 - i64::MAX);
+    assert_eq!(days_before(-1_000, u64::MAX), i64::MIN);
+    assert!(days_before(now_millis(), u64::MAX / 1_000) < 0);
+}
+
+#[test]
+fn find_matching_hashes_the_snapshot_like_capture_does() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    let settings = Settings { capture_transforms: vec![CaptureTransform::Trim], ..Default::default() };
+
+    let text = |t: &str| Snapshot { text: Some(t.into()), ..Default::default() };
+    let url = store_snapshot(&db, &km, &settings, text("https://example.com")).unwrap()[0];
+    let img = Snapshot { image: Some(ImageData { width: 2, height: 2, bytes: vec![7u8; 16].into() }), ..Default::default() };
+    let image = store_snapshot(&db, &km, &settings, img).unwrap()[0];
+    let file = tmp.path().join("a.txt").to_string_lossy().into_owned();
+    let file_id = store_snapshot(&db, &km, &settings, Snapshot { file_paths: vec![file.clone()], ..Default::default() }).unwrap()[0];
+
+    // found without the key
+    km.lock();
+    assert_eq!(find_matching(&db, &settings, &text("  https://example.com\n")).unwrap(), Some(url));
+    assert_eq!(find_matching(&db, &settings, &text("something else")).unwrap(), None);
+    let img = Snapshot { image: Some(ImageData { width: 2, height: 2, bytes: vec![7u8; 16].into() }), ..Default::default() };
+    assert_eq!(find_matching(&db, &settings, &img).unwrap(), Some(image));
+    assert_eq!(find_matching(&db, &settings, &Snapshot { file_paths: vec![file], ..Default::default() }).unwrap(), Some(file_id));
+    assert_eq!(find_matching(&db, &settings, &Snapshot::default()).unwrap(), None);
+}
+
+#[test]
+fn sealed_file_paths_dedup_by_hash_and_open_with_the_key() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    let file = tmp.path().join("secret-plans.txt");
+    std::fs::write(&file, b"plans").unwrap();
+    let path = file.to_string_lossy().into_owned();
+    let snap = || Snapshot { file_paths: vec![path.clone()], ..Default::default() };
+    let settings = Settings { encrypt_file_paths: true, ..Default::default() };
+
+    // copied while locked: plaintext until the next unlocked run
+    let id = store_snapshot(&db, &km, &settings, snap()).unwrap()[0];
+    assert_eq!(db.get_item(id).unwrap().file_path.as_deref(), Some(path.as_str()));
+    km.unlock().unwrap();
+    assert_eq!(seal_file_paths(&db, &km).unwrap(), 1);
+    assert_eq!(seal_file_paths(&db, &km).unwrap(), 0);
+
+    let it = db.get_item(id).unwrap();
+    assert!(it.file_path.is_none());
+    let blob = it.file_path_blob.unwrap();
+    assert!(!blob.windows(b"secret-plans".len()).any(|w| w == b"secret-plans"));
+    assert_eq!(resolve_file_path(&db, &km, id, None).unwrap().as_deref(), Some(path.as_str()));
+
+    // a re-copy dedups against the sealed row, sealed again or not
+    assert_eq!(store_snapshot(&db, &km, &settings, snap()).unwrap()[0], id);
+    assert_eq!(store_snapshot(&db, &km, &Settings::default(), snap()).unwrap()[0], id);
+    assert_eq!(find_matching(&db, &settings, &snap()).unwrap(), Some(id));
+
+    assert_eq!(materialize_file(&db, &km, id).unwrap(), 5);
+    let dest = tmp.path().join("out.txt");
+    save_item_to(&db, &km, id, &dest).unwrap();
+    assert_eq!(std::fs::read(&dest).unwrap(), b"plans");
+
+    km.lock();
+    assert!(resolve_file_path(&db, &km, id, None).is_err());
+}
+
+#[test]
+fn size_route_goes_native_then_skips() {
+    assert_eq!(size_route(0), SizeRoute::Arboard);
+    assert_eq!(size_route(NATIVE_READ_BYTES), SizeRoute::Arboard);
+    assert_eq!(size_route(NATIVE_READ_BYTES + 1), SizeRoute::Native);
+    assert_eq!(size_route(MAX_CAPTURE_BYTES), SizeRoute::Native);
+    assert_eq!(size_route(MAX_CAPTURE_BYTES + 1), SizeRoute::Skip);
+}
+
+#[test]
+fn captures_over_max_capture_bytes_are_skipped() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    let settings = Settings { max_capture_bytes: 1024 * 1024, ..Default::default() };
+    let text = |len: usize| Snapshot { text: Some("x".repeat(len)), ..Default::default() };
+
+    assert!(!over_capture_limit(1024 * 1024, settings.max_capture_bytes));
+    assert!(over_capture_limit(1024 * 1024 + 1, settings.max_capture_bytes));
+    assert!(!over_capture_limit(usize::MAX, 0));
+
+    assert!(store_snapshot(&db, &km, &settings, text(1024 * 1024 + 1)).unwrap().is_empty());
+    assert_eq!(store_snapshot(&db, &km, &settings, text(1024 * 1024)).unwrap().len(), 1);
+
+    let big = tmp.path().join("big.bin");
+    std::fs::write(&big, vec![0u8; 1024 * 1024 + 1]).unwrap();
+    let files = Snapshot { file_paths: vec![big.to_string_lossy().into_owned()], ..Default::default() };
+    assert!(store_snapshot(&db, &km, &settings, files).unwrap().is_empty());
+    assert_eq!(db.list_recent(10).unwrap().len(), 1);
+}
+
+#[test]
+fn whitespace_only_text_is_not_captured() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    let text = |t: &str| Snapshot { text: Some(t.into()), ..Default::default() };
+
+    let settings = Settings::default();
+    assert!(store_snapshot(&db, &km, &settings, text("\t")).unwrap().is_empty());
+    assert!(store_snapshot(&db, &km, &settings, text("\n\r\n")).unwrap().is_empty());
+    assert_eq!(find_matching(&db, &settings, &text("\t")).unwrap(), None);
+    assert_eq!(store_snapshot(&db, &km, &settings, text("\tindented")).unwrap().len(), 1);
+
+    let keep = Settings { ignore_whitespace_only: false, ..Default::default() };
+    assert_eq!(store_snapshot(&db, &km, &keep, text("\t")).unwrap().len(), 1);
+    assert_eq!(db.list_recent(10).unwrap().len(), 2);
+}
+
+#[test]
+fn excluded_apps_match_ignoring_case_and_whitespace() {
+    let excluded = vec!["com.Bank.App".to_string(), " com.secrets.vault ".to_string()];
+    assert!(is_excluded_app(&excluded, Some("com.bank.app")));
+    assert!(is_excluded_app(&excluded, Some("COM.SECRETS.VAULT\n")));
+    assert!(!is_excluded_app(&excluded, Some("com.bank.app.helper")));
+    assert!(!is_excluded_app(&excluded, Some("  ")));
+    assert!(!is_excluded_app(&excluded, None));
+    assert!(!is_excluded_app(&[], Some("com.bank.app")));
+}
+
+#[test]
+fn password_manager_markers_are_transient() {
+    assert!(is_transient(&["public.utf8-plain-text", "org.nspasteboard.ConcealedType"]));
+    assert!(is_transient(&["org.nspasteboard.TransientType"]));
+    assert!(!is_transient(&["public.utf8-plain-text", "public.html"]));
+    assert!(!is_transient::<&str>(&[]));
+    assert!(Settings::default().respect_transient);
+}
+
+#[test]
+fn oversized_image_previews_are_downsized_to_the_budget() {
+    // noise compresses badly, so the PNG stays big
+    let mut seed = 0x2545_f491u32;
+    let noisy = image::RgbaImage::from_fn(1024, 768, |_, _| {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        let [r, g, b, _] = seed.to_le_bytes();
+        image::Rgba([r, g, b, 255])
+    });
+    let mut png = Vec::new();
+    noisy.write_to(&mut std::io::Cursor::new(&mut png), image::ImageOutputFormat::Png).unwrap();
+    let thumb = make_thumbnail(&png, 1024, ThumbnailFormat::Png).unwrap();
+
+    let budget = 64 * 1024;
+    let preview = preview_data_url(&thumb, ThumbnailFormat::Png, budget).unwrap();
+    assert!(preview.downsized);
+    assert!(preview.data_url.len() <= budget, "{} bytes", preview.data_url.len());
+    assert!(preview.data_url.starts_with("data:image/jpeg;base64,"));
+
+    // no limit, or a small image, passes through untouched
+    let whole = preview_data_url(&thumb, ThumbnailFormat::Png, 0).unwrap();
+    assert!(!whole.downsized && whole.data_url.starts_with("data:image/png;base64,"));
+    let small = make_thumbnail(&png, 16, ThumbnailFormat::Png).unwrap();
+    assert!(!preview_data_url(&small, ThumbnailFormat::Png, budget).unwrap().downsized);
+}
+
+#[test]
+fn clipboard_dump_writes_the_preferred_representation() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dest = tmp.path().join("dump");
+
+    let text = Snapshot { text: Some("just looking".into()), ..Default::default() };
+    let dumped = dump_snapshot(&text, &dest).unwrap();
+    assert_eq!((dumped.kind.as_str(), dumped.bytes), ("text", 12));
+    assert_eq!(std::fs::read_to_string(&dest).unwrap(), "just looking");
+
+    let files = Snapshot { file_paths: vec!["/tmp/a.txt".into(), "/tmp/b.txt".into()], text: Some("a.txt".into()), ..Default::default() };
+    assert_eq!(dump_snapshot(&files, &dest).unwrap().kind, "files");
+    assert_eq!(std::fs::read_to_string(&dest).unwrap(), "/tmp/a.txt\n/tmp/b.txt\n");
+
+    let image = Snapshot {
+        image: Some(ImageData { width: 2, height: 1, bytes: vec![255u8; 8].into() }),
+        ..Default::default()
+    };
+    assert_eq!(dump_snapshot(&image, &dest).unwrap().kind, "image");
+    let png = image::load_from_memory(&std::fs::read(&dest).unwrap()).unwrap();
+    assert_eq!((png.width(), png.height()), (2, 1));
+
+    assert!(dump_snapshot(&Snapshot::default(), &tmp.path().join("empty")).is_err());
+    assert!(!tmp.path().join("empty").exists());
+}
+
+#[test]
+fn corrupt_image_blobs_fail_with_a_specific_error_and_are_flagged() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let garbage = b"\x89PNG\r\n\x1a\n but then nothing sensible";
+    let id = db
+        .insert_item(NewItem {
+            kind: "image".into(),
+            size: garbage.len() as i64,
+            sha256: Database::compute_sha256(garbage),
+            file_path: None,
+            file_path_blob: None,
+            content_blob: Some(km.encrypt(garbage).unwrap()),
+            preview_blob: None,
+            rtf_blob: None,
+            html_blob: None,
+            source_app: None,
+            uti: None,
+        })
+        .unwrap();
+    assert!(!db.get_item(id).unwrap().image_corrupt);
+
+    let err = copy_back(&db, &km, id).unwrap_err();
+    match err.downcast_ref::<CopyError>() {
+        Some(CopyError::ImageDecode { id: bad, .. }) => assert_eq!(*bad, id),
+        other => panic!("expected ImageDecode, got {other:?}"),
+    }
+    assert!(err.to_string().contains("delete the item"));
+    assert!(db.get_item(id).unwrap().image_corrupt);
+
+    let err = item_thumbnail(&db, id, garbage, 64, ThumbnailFormat::Png).unwrap_err();
+    assert!(matches!(err.downcast_ref::<CopyError>(), Some(CopyError::ImageDecode { .. })));
+
+    // a good image decodes and leaves the flag alone
+    let good = store_snapshot(&db, &km, &Settings::default(), Snapshot {
+        image: Some(ImageData { width: 2, height: 2, bytes: vec![9; 16].into() }),
+        ..Default::default()
+    })
+    .unwrap()[0];
+    let (_, ct, _, _, _) = db.get_item_raw(good).unwrap();
+    assert!(decode_image(&db, good, &km.decrypt(&ct.unwrap()).unwrap()).is_ok());
+    assert!(!db.get_item(good).unwrap().image_corrupt);
+}
+
+#[test]
+fn text_bytes_are_decoded_in_their_own_encoding() {
+    let utf8 = decode_text_bytes("naïve café".as_bytes());
+    assert_eq!(utf8, DecodedText { text: "naïve café".into(), encoding: "UTF-8", lossy: false });
+
+    // "naïve café" in Latin-1 is not valid UTF-8
+    let latin1 = decode_text_bytes(b"na\xefve caf\xe9, d\xe9j\xe0 vu");
+    assert_eq!(latin1.text, "naïve café, déjà vu");
+    assert_eq!(latin1.encoding, "windows-1252");
+    assert!(!latin1.lossy);
+
+    let utf16: Vec<u8> = "hello wörld".encode_utf16().flat_map(u16::to_le_bytes).collect();
+    assert_eq!(decode_text_bytes(&utf16).text, "hello wörld");
+    assert_eq!(decode_text_bytes(&utf16).encoding, "UTF-16LE");
+    let with_bom: Vec<u8> = [0xfe, 0xff].into_iter().chain("hi".encode_utf16().flat_map(u16::to_be_bytes)).collect();
+    assert_eq!(decode_text_bytes(&with_bom).text, "hi");
+}
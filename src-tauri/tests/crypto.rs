@@ -1,4 +1,4 @@
-use cliper_lib::crypto::KeyManager;
+use cliper_lib::crypto::{CryptoError, KeyManager};
 
 #[test]
 fn crypto_roundtrip_and_tamper() {
@@ -12,6 +12,147 @@ fn crypto_roundtrip_and_tamper() {
     // tamper
     let last = ct.len() - 2;
     ct[last] ^= 0xFF;
-    assert!(km.decrypt(&ct).is_err());
+    assert!(matches!(km.decrypt(&ct), Err(CryptoError::Decrypt)));
+    assert!(matches!(km.decrypt(&ct[..8]), Err(CryptoError::Decrypt)));
+
+    km.lock();
+    assert!(matches!(km.encrypt(msg), Err(CryptoError::Locked)));
+    assert!(matches!(km.decrypt(&ct), Err(CryptoError::Locked)));
+}
+
+#[test]
+fn self_test_passes_unlocked_and_reports_locked() {
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    km.self_test().unwrap();
+
+    km.lock();
+    assert!(matches!(km.self_test(), Err(CryptoError::Locked)));
+}
+
+
+#[test]
+fn counter_nonces_advance_across_restarts() {
+    use cliper_lib::crypto::NonceMode;
+    use std::collections::HashSet;
+
+    let bundle = "test.bundle.counter";
+    let km = KeyManager::new(bundle.into()).with_nonce_mode(NonceMode::Counter);
+    km.reset_master_key().unwrap();
+
+    let mut seen = HashSet::new();
+    for _ in 0..5 {
+        let ct = km.encrypt(b"x").unwrap();
+        assert!(seen.insert(ct[..12].to_vec()));
+    }
+    let before_restart = km.nonce_counter().unwrap().unwrap();
+    assert_eq!(before_restart, 5);
+    drop(km);
+
+    // a fresh manager for the same key must resume past every used value
+    let km = KeyManager::new(bundle.into()).with_nonce_mode(NonceMode::Counter);
+    km.unlock().unwrap();
+    assert!(km.nonce_counter().unwrap().unwrap() >= before_restart);
+    for _ in 0..5 {
+        let ct = km.encrypt(b"x").unwrap();
+        assert!(seen.insert(ct[..12].to_vec()));
+        assert_eq!(km.decrypt(&ct).unwrap(), b"x");
+    }
+}
+
+#[test]
+fn ephemeral_keys_stay_out_of_the_keychain() {
+    use cliper_lib::crypto::{KeyMode, NonceMode};
+
+    let bundle = "test.bundle.ephemeral";
+    let km = KeyManager::new(bundle.into()).with_key_mode(KeyMode::Ephemeral).with_nonce_mode(NonceMode::Counter);
+    km.unlock().unwrap();
+    let ct = km.encrypt(b"kiosk").unwrap();
+
+    // lock/unlock within a launch keeps the same key
+    km.lock();
+    km.unlock().unwrap();
+    assert_eq!(km.decrypt(&ct).unwrap(), b"kiosk");
+    assert!(security_framework::passwords::get_generic_password(&format!("{bundle}.masterkey"), "default").is_err());
+    assert!(security_framework::passwords::get_generic_password(&format!("{bundle}.masterkey"), "nonce-counter").is_err());
+
+    // a new launch gets a new key
+    let next = KeyManager::new(bundle.into()).with_key_mode(KeyMode::Ephemeral);
+    next.unlock().unwrap();
+    assert!(matches!(next.decrypt(&ct), Err(CryptoError::Decrypt)));
 }
 
+#[test]
+fn passphrase_unlock_derives_the_key_and_rejects_a_wrong_one() {
+    let bundle = "test.bundle.passphrase";
+    let service = format!("{bundle}.masterkey");
+    let km = KeyManager::new(bundle.into()).with_passphrase(true);
+    assert!(matches!(km.unlock(), Err(CryptoError::PassphraseRequired)));
+    assert!(!km.has_passphrase().unwrap());
+
+    // the first unlock sets the passphrase; no key is stored, only salt and verifier
+    km.unlock_with_passphrase("correct horse").unwrap();
+    let ct = km.encrypt(b"secret").unwrap();
+    assert!(km.has_passphrase().unwrap());
+    assert!(security_framework::passwords::get_generic_password(&service, "default").is_err());
+    assert!(security_framework::passwords::get_generic_password(&service, "passphrase-salt").is_ok());
+
+    // a new launch derives the same key from the same passphrase
+    let next = KeyManager::new(bundle.into()).with_passphrase(true);
+    assert!(matches!(next.unlock_with_passphrase("wrong horse"), Err(CryptoError::WrongPassphrase)));
+    assert!(!next.is_unlocked());
+    next.unlock_with_passphrase("correct horse").unwrap();
+    assert_eq!(next.decrypt(&ct).unwrap(), b"secret");
+
+    // resetting forgets the passphrase, so the next one is accepted as new
+    next.reset_master_key().unwrap();
+    assert!(!next.is_unlocked());
+    next.unlock_with_passphrase("battery staple").unwrap();
+    assert!(matches!(next.decrypt(&ct), Err(CryptoError::Decrypt)));
+
+    // without passphrase mode the keychain path is unchanged
+    let plain = KeyManager::new(bundle.into());
+    assert!(matches!(plain.unlock_with_passphrase("correct horse"), Err(CryptoError::PassphraseDisabled)));
+}
+
+#[test]
+fn keychain_status_codes_map_to_kinds() {
+    use cliper_lib::crypto::KeychainErrorKind;
+
+    assert_eq!(KeychainErrorKind::from_code(-128), KeychainErrorKind::Denied);
+    assert_eq!(KeychainErrorKind::from_code(-25293), KeychainErrorKind::Denied);
+    assert_eq!(KeychainErrorKind::from_code(-25300), KeychainErrorKind::NotFound);
+    assert_eq!(KeychainErrorKind::from_code(-25308), KeychainErrorKind::InteractionNotAllowed);
+    assert_eq!(KeychainErrorKind::from_code(-1), KeychainErrorKind::Other(-1));
+
+    let err = CryptoError::Keychain { kind: KeychainErrorKind::Denied };
+    assert_eq!(err.to_string(), "keychain access denied");
+
+    // a missing item on first run is not an error
+    let km = KeyManager::new("test.bundle.first-run".into());
+    km.unlock().unwrap();
+    assert_eq!(km.last_keychain_error(), None);
+}
+
+#[test]
+fn biometric_errors_map_to_kinds() {
+    use cliper_lib::crypto::BiometricErrorKind;
+
+    assert_eq!(BiometricErrorKind::from_code(-1), BiometricErrorKind::Failed);
+    assert_eq!(BiometricErrorKind::from_code(-2), BiometricErrorKind::Cancelled);
+    assert_eq!(BiometricErrorKind::from_code(-4), BiometricErrorKind::Cancelled);
+    assert_eq!(BiometricErrorKind::from_code(-6), BiometricErrorKind::Unavailable);
+    assert_eq!(BiometricErrorKind::from_code(-8), BiometricErrorKind::LockedOut);
+    assert_eq!(BiometricErrorKind::from_code(-1000), BiometricErrorKind::Other(-1000));
+
+    let err = CryptoError::Biometrics { kind: BiometricErrorKind::Cancelled };
+    assert_eq!(err.to_string(), "biometrics cancelled");
+
+    // without Touch ID the gate fails before the Keychain is touched
+    #[cfg(not(target_os = "macos"))]
+    {
+        let km = KeyManager::new("test.bundle.biometrics".into());
+        assert!(matches!(km.unlock_with_biometrics(), Err(CryptoError::Biometrics { kind: BiometricErrorKind::Unavailable })));
+        assert!(!km.is_unlocked());
+    }
+}
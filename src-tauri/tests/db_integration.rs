@@ -1,7 +1,35 @@
-use cliper_lib::crypto::KeyManager;
-use cliper_lib::db::{Database, NewItem};
+use cliper_lib::api::{build_previews, file_items, health_check, history_page, index_pending, search_items_indexed, item_blob, preview_side, recent_items, search_capabilities_of, search_items, set_note, wipe_everything, write_items_ndjson, SearchCapabilities, NOTE_MAX_CHARS, SEARCH_SCAN_LIMIT};
+use cliper_lib::clipboard::LockedCaptures;
+use cliper_lib::crypto::{CryptoError, KeyManager};
+use cliper_lib::db::{now_millis, Database, DedupPolicy, EncryptionOverhead, NewItem, SizeHistogram};
 use std::path::PathBuf;
 
+/// A text row for `bytes` with no content stored; tests override what they
+/// need with `NewItem { .., ..text_item(bytes) }`.
+fn text_item(bytes: &[u8]) -> NewItem {
+    NewItem {
+        kind: "text".into(),
+        size: bytes.len() as i64,
+        sha256: Database::compute_sha256(bytes),
+        file_path: None,
+        file_path_blob: None,
+        content_blob: None,
+        preview_blob: None,
+        rtf_blob: None,
+        html_blob: None,
+        source_app: None,
+        uti: None,
+    }
+}
+
+/// Whether `needle` can be read from the database file or WAL in `dir`.
+fn on_disk(dir: &std::path::Path, needle: &str) -> bool {
+    ["cliper.sqlite", "cliper.sqlite-wal"].iter().any(|f| {
+        let bytes = std::fs::read(dir.join(f)).unwrap_or_default();
+        bytes.windows(needle.len()).any(|w| w == needle.as_bytes())
+    })
+}
+
 #[test]
 fn db_migration_and_insert() {
     let tmp = tempfile::tempdir().unwrap();
@@ -21,9 +49,13 @@ fn db_migration_and_insert() {
             size: text.len() as i64,
             sha256: sha,
             file_path: None,
+            file_path_blob: None,
             content_blob: Some(enc),
             preview_blob: None,
             rtf_blob: None,
+            html_blob: None,
+            source_app: None,
+            uti: None,
         })
         .unwrap();
     assert!(id > 0);
@@ -33,3 +65,1085 @@ fn db_migration_and_insert() {
     assert_eq!(list[0].kind, "text");
 }
 
+
+#[test]
+fn migrate_is_idempotent_and_repair_restores_schema() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    db.migrate().unwrap();
+    assert!(db.repair_schema().unwrap().is_empty());
+
+    // simulate a half-upgraded database missing an index
+    let raw = rusqlite::Connection::open(tmp.path().join("cliper.sqlite")).unwrap();
+    raw.execute_batch("DROP INDEX idx_items_kind").unwrap();
+    drop(raw);
+
+    let fixes = db.repair_schema().unwrap();
+    assert_eq!(fixes, vec!["created index idx_items_kind".to_string()]);
+    assert!(db.repair_schema().unwrap().is_empty());
+}
+
+#[test]
+fn recently_pasted_orders_by_last_copy() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let mut ids = Vec::new();
+    for t in ["one", "two", "three"] {
+        let id = db.insert_item(text_item(t.as_bytes())).unwrap();
+        ids.push(id);
+    }
+    assert!(db.list_recently_pasted(10).unwrap().is_empty());
+
+    db.record_copy(ids[2]).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    db.record_copy(ids[0]).unwrap();
+
+    let pasted: Vec<i64> = db.list_recently_pasted(10).unwrap().iter().map(|i| i.id).collect();
+    assert_eq!(pasted, vec![ids[0], ids[2]]);
+}
+
+#[test]
+fn insert_records_plaintext_and_stored_lengths() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let text = b"twelve bytes";
+    let enc = km.encrypt(text).unwrap();
+    let stored = enc.len() as i64;
+    db.insert_item(NewItem { content_blob: Some(enc), ..text_item(text) }).unwrap();
+
+    let it = &db.list_recent(1).unwrap()[0];
+    assert_eq!(it.plaintext_len, 12);
+    assert_eq!(it.stored_len, stored);
+    assert!(it.stored_len > it.plaintext_len); // nonce + tag
+}
+
+#[test]
+fn unwritable_data_dir_falls_back_instead_of_failing() {
+    let tmp = tempfile::tempdir().unwrap();
+    // a regular file where a directory is expected can't be created into,
+    // even when the tests run as root
+    let blocker = tmp.path().join("blocker");
+    std::fs::write(&blocker, b"").unwrap();
+    let unwritable = blocker.join("app");
+
+    let fallback = tmp.path().join("fallback");
+    let (db, warning) = Database::open_with_fallback(unwritable.clone(), fallback.clone()).unwrap();
+    assert!(warning.unwrap().contains("temporary storage"));
+    assert!(fallback.join("cliper.sqlite").exists());
+    drop(db);
+
+    let (db, warning) = Database::open_with_fallback(unwritable, blocker.join("also-bad")).unwrap();
+    assert!(warning.unwrap().contains("in memory"));
+    db.insert_item(text_item(b"x")).unwrap();
+    assert_eq!(db.list_recent(10).unwrap().len(), 1);
+}
+
+#[test]
+fn for_each_item_pages_through_large_histories() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    for i in 0..3000u32 {
+        let bytes = i.to_be_bytes();
+        db.insert_item(text_item(&bytes)).unwrap();
+    }
+
+    let mut last_id = 0;
+    let visited = db
+        .for_each_item(256, |it| {
+            assert!(it.id > last_id);
+            last_id = it.id;
+            Ok(true)
+        })
+        .unwrap();
+    assert_eq!(visited, 3000);
+
+    // stopping early, and calling back into the db from the callback
+    let visited = db
+        .for_each_item(256, |it| {
+            db.pin_item(it.id, true)?;
+            Ok(it.id < 10)
+        })
+        .unwrap();
+    assert_eq!(visited, 10);
+}
+
+#[test]
+fn dedup_window_separates_old_recaptures() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    db.set_dedup_window(1);
+
+    let item = || text_item(b"again");
+    let first = db.insert_item(item()).unwrap();
+    // inside the window: same row
+    assert_eq!(db.insert_item(item()).unwrap(), first);
+
+    // age the row past the one-minute window
+    let raw = rusqlite::Connection::open(tmp.path().join("cliper.sqlite")).unwrap();
+    raw.execute("UPDATE items SET created_at = created_at - 120000 WHERE id = ?1", [first]).unwrap();
+    let second = db.insert_item(item()).unwrap();
+    assert_ne!(second, first);
+    assert_eq!(db.list_recent(10).unwrap().len(), 2);
+
+    // window 0 restores always-dedup
+    db.set_dedup_window(0);
+    raw.execute("UPDATE items SET created_at = created_at - 120000", []).unwrap();
+    assert_eq!(db.insert_item(item()).unwrap(), second);
+}
+
+#[test]
+fn recopied_content_moves_back_to_the_top() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let insert = |n: u8| db.insert_item(text_item(&[n])).unwrap();
+    let order = || db.list_recent(10).unwrap().into_iter().map(|i| i.id).collect::<Vec<_>>();
+    let a = insert(1);
+    let b = insert(2);
+    let pinned = insert(3);
+    db.pin_item(pinned, true).unwrap();
+    let raw = rusqlite::Connection::open(tmp.path().join("cliper.sqlite")).unwrap();
+    raw.execute("UPDATE items SET created_at = created_at - 86400000", []).unwrap();
+
+    assert_eq!(insert(1), a);
+    assert_eq!(order(), vec![pinned, a, b]);
+    assert!(now_millis() - db.get_item(a).unwrap().created_at < 60_000);
+
+    // a re-copied pin stays where it is among pins
+    assert_eq!(insert(3), pinned);
+    assert_eq!(order(), vec![pinned, a, b]);
+}
+
+#[test]
+fn reports_database_and_wal_paths() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    assert_eq!(db.path(), tmp.path().join("cliper.sqlite"));
+    assert_eq!(db.wal_path(), Some(tmp.path().join("cliper.sqlite-wal")));
+
+    let mem = Database::in_memory().unwrap();
+    assert_eq!(mem.wal_path(), None);
+}
+
+#[test]
+fn sensitive_flag_and_reveal_counter() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let id = db.insert_item(text_item(b"123456")).unwrap();
+    assert!(!db.get_item(id).unwrap().is_sensitive);
+
+    db.set_sensitive(id, true).unwrap();
+    assert!(db.list_recent(1).unwrap()[0].is_sensitive);
+    assert_eq!(db.record_reveal(id).unwrap(), 1);
+    assert_eq!(db.record_reveal(id).unwrap(), 2);
+    // revealing does not clear the flag
+    assert!(db.get_item(id).unwrap().is_sensitive);
+}
+
+#[test]
+fn batched_previews_skip_unknown_ids() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+
+    let mut ids = Vec::new();
+    for t in ["first clip", "second clip"] {
+        ids.push(
+            db.insert_item(NewItem { content_blob: Some(km.encrypt(t.as_bytes()).unwrap()), ..text_item(t.as_bytes()) }).unwrap(),
+        );
+    }
+    let file_id = db
+        .insert_item(NewItem {
+            kind: "file".into(),
+            size: 0,
+            sha256: Database::compute_sha256(b"/tmp/report.pdf"),
+            file_path: Some("/tmp/report.pdf".into()),
+            file_path_blob: None,
+            content_blob: None,
+            preview_blob: None,
+            rtf_blob: None,
+            html_blob: None,
+            source_app: None,
+            uti: None,
+        })
+        .unwrap();
+
+    let previews = build_previews(&db, &km, &[ids[0], 9999, ids[1], file_id, -1]).unwrap();
+    assert_eq!(previews.len(), 3);
+    assert_eq!(previews[&ids[0]], "first clip");
+    assert_eq!(previews[&file_id], "report.pdf");
+
+    // locked: only the file name survives
+    km.lock();
+    let previews = build_previews(&db, &km, &[ids[0], ids[1], file_id]).unwrap();
+    assert_eq!(previews.keys().collect::<Vec<_>>(), vec![&file_id]);
+}
+
+#[test]
+fn list_order_survives_a_backwards_clock() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let first = db.insert_item(text_item(b"before the clock change")).unwrap();
+    // the first capture happened while the clock ran an hour fast
+    let raw = rusqlite::Connection::open(tmp.path().join("cliper.sqlite")).unwrap();
+    raw.execute("UPDATE items SET created_at = created_at + 3600000 WHERE id = ?1", [first]).unwrap();
+    let second = db.insert_item(text_item(b"after the clock change")).unwrap();
+
+    let list = db.list_recent(10).unwrap();
+    assert_eq!(list.iter().map(|i| i.id).collect::<Vec<_>>(), vec![second, first]);
+    assert!(list[0].created_at < list[1].created_at);
+}
+
+#[test]
+fn dedup_policy_can_keep_copies_from_different_apps() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+
+    let from = |app: &str| NewItem { source_app: Some(app.into()), ..text_item(b"same") };
+
+    // default: the app is not part of the key
+    let a = db.insert_item(from("com.apple.Safari")).unwrap();
+    assert_eq!(db.insert_item(from("com.apple.Notes")).unwrap(), a);
+    assert_eq!(db.list_recent(10).unwrap().len(), 1);
+
+    db.set_dedup_policy(DedupPolicy::ContentAndApp);
+    let b = db.insert_item(from("com.apple.Notes")).unwrap();
+    assert_ne!(b, a);
+    assert_eq!(db.insert_item(from("com.apple.Notes")).unwrap(), b);
+    assert_eq!(db.list_recent(10).unwrap().len(), 2);
+}
+
+#[test]
+fn wal_checkpoint_runs_after_write_threshold() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    db.set_checkpoint_every(3);
+
+    let insert = |n: u8| db.insert_item(text_item(&[n])).unwrap();
+    let a = insert(1);
+    insert(2);
+    assert_eq!(db.checkpoints_run(), 0);
+    db.pin_item(a, true).unwrap();
+    assert_eq!(db.checkpoints_run(), 1);
+
+    // dedup hits are not writes
+    insert(1);
+    insert(1);
+    assert_eq!(db.checkpoints_run(), 1);
+    db.delete_item(a).unwrap();
+    insert(3);
+    insert(4);
+    assert_eq!(db.checkpoints_run(), 2);
+
+    db.set_checkpoint_every(0);
+    for n in 5..20 {
+        insert(n);
+    }
+    assert_eq!(db.checkpoints_run(), 2);
+}
+
+#[test]
+fn size_histogram_buckets_by_kind() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    assert!(db.size_histogram().unwrap().is_empty());
+
+    for (n, (kind, size)) in [("text", 10), ("text", 2_000), ("text", 500_000), ("image", 50_000)].into_iter().enumerate() {
+        db.insert_item(NewItem { kind: kind.into(), size, ..text_item(&[n as u8]) }).unwrap();
+    }
+
+    let hist = db.size_histogram().unwrap();
+    assert_eq!(
+        hist,
+        vec![
+            SizeHistogram { kind: "image".into(), under_100kb: 1, total_bytes: 50_000, ..Default::default() },
+            SizeHistogram {
+                kind: "text".into(),
+                under_1kb: 1,
+                under_10kb: 1,
+                over_100kb: 1,
+                total_bytes: 502_010,
+                ..Default::default()
+            },
+        ]
+    );
+}
+
+#[test]
+fn health_check_flags_a_key_that_no_longer_matches() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle.health".into());
+    // locked: nothing to check yet
+    assert!(health_check(&db, &km).is_ok());
+
+    km.reset_master_key().unwrap();
+    km.self_test().unwrap();
+    let ct = km.encrypt(b"before the key changed").unwrap();
+    db.insert_item(NewItem { content_blob: Some(ct), ..text_item(b"before the key changed") }).unwrap();
+    assert!(health_check(&db, &km).is_ok());
+
+    km.reset_master_key().unwrap();
+    assert!(health_check(&db, &km).is_err());
+}
+
+#[test]
+fn per_kind_limits_prune_oldest_unpinned() {
+    use std::collections::HashMap;
+
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let insert = |kind: &str, n: u8| {
+        db.insert_item(NewItem { kind: kind.into(), ..text_item(&[n]) }).unwrap()
+    };
+    let first_image = insert("image", 1);
+    db.pin_item(first_image, true).unwrap();
+    for n in 2..=5 {
+        insert("image", n);
+        insert("text", n);
+    }
+
+    // existing rows are pruned when the cap is set
+    let removed = db.set_kind_limits(HashMap::from([("image".to_string(), 2), ("text".to_string(), 0)])).unwrap();
+    assert_eq!(removed, 2);
+    assert_eq!(db.count_kind("image").unwrap(), 3);
+    assert_eq!(db.count_kind("text").unwrap(), 4);
+
+    // and each insert of a capped kind drops the oldest unpinned one
+    let newest = insert("image", 6);
+    let images: Vec<i64> = db.list_recent(20).unwrap().into_iter().filter(|i| i.kind == "image").map(|i| i.id).collect();
+    assert_eq!(images.len(), 3);
+    assert_eq!(images[0], first_image);
+    assert_eq!(images[1], newest);
+
+    // without keep_pinned the pinned one counts too
+    assert_eq!(db.prune_kind_to_limit("image", 1, false).unwrap(), 2);
+    assert_eq!(db.count_kind("image").unwrap(), 1);
+}
+
+#[test]
+fn max_items_keeps_the_newest_unpinned_and_all_pins() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let insert = |n: u8| db.insert_item(text_item(&[n])).unwrap();
+    let pinned = insert(1);
+    db.pin_item(pinned, true).unwrap();
+    let ids: Vec<i64> = (2..=6).map(insert).collect();
+
+    assert_eq!(db.set_max_items(Some(3)).unwrap(), 2);
+    assert_eq!(db.trim_to_limit(3).unwrap(), 0);
+
+    // each insert past the cap drops the oldest unpinned item
+    let newest = insert(7);
+    let left: Vec<i64> = db.list_recent(20).unwrap().into_iter().map(|i| i.id).collect();
+    assert_eq!(left, vec![pinned, newest, ids[4], ids[3]]);
+
+    // without a cap nothing is trimmed
+    assert_eq!(db.set_max_items(None).unwrap(), 0);
+    insert(8);
+    assert_eq!(db.list_recent(20).unwrap().len(), 5);
+}
+
+#[test]
+fn swapping_pins_reorders_only_pinned_items() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let ids: Vec<i64> = (1..=4u8).map(|n| db.insert_item(text_item(&[n])).unwrap()).collect();
+    let order = || db.list_recent(10).unwrap().into_iter().map(|i| i.id).collect::<Vec<_>>();
+
+    db.pin_item(ids[0], true).unwrap();
+    db.pin_item(ids[1], true).unwrap();
+    db.pin_item(ids[2], true).unwrap();
+    // re-pinning keeps the place
+    db.pin_item(ids[0], true).unwrap();
+    assert_eq!(order(), vec![ids[2], ids[1], ids[0], ids[3]]);
+
+    db.swap_pin_order(ids[2], ids[0]).unwrap();
+    assert_eq!(order(), vec![ids[0], ids[1], ids[2], ids[3]]);
+
+    assert!(db.swap_pin_order(ids[0], ids[3]).is_err());
+    assert!(db.swap_pin_order(ids[0], 999).is_err());
+    assert_eq!(order(), vec![ids[0], ids[1], ids[2], ids[3]]);
+}
+
+#[test]
+fn item_blob_is_the_ciphertext_in_the_documented_layout() {
+    use base64::Engine;
+
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+    km.unlock().unwrap();
+    let secret = b"backup me";
+    let ct = km.encrypt(secret).unwrap();
+    let id = db.insert_item(NewItem { content_blob: Some(ct.clone()), ..text_item(secret) }).unwrap();
+
+    // no unlock needed
+    km.lock();
+    let blob = item_blob(&db, id).unwrap();
+    let raw = base64::engine::general_purpose::STANDARD.decode(blob.content_blob.unwrap()).unwrap();
+    assert_eq!(raw, ct);
+    assert_eq!(raw.len(), blob.format.version_prefix_len + blob.format.nonce_len + secret.len() + blob.format.tag_len);
+
+    km.unlock().unwrap();
+    assert_eq!(km.decrypt(&raw).unwrap(), secret);
+    assert!(item_blob(&db, 999).is_err());
+}
+
+#[test]
+fn file_items_flag_missing_paths_and_group_pinned() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle".into());
+
+    let present = tmp.path().join("present.txt");
+    std::fs::write(&present, b"hello").unwrap();
+    let gone = tmp.path().join("gone.txt");
+    let mut ids = Vec::new();
+    for path in [&present, &gone] {
+        let p = path.to_string_lossy().into_owned();
+        ids.push(
+            db.insert_item(NewItem {
+                kind: "file".into(),
+                size: 0,
+                sha256: Database::compute_sha256(p.as_bytes()),
+                file_path: Some(p),
+                file_path_blob: None,
+                content_blob: None,
+                preview_blob: None,
+                rtf_blob: None,
+                html_blob: None,
+                source_app: None,
+                uti: None,
+            })
+            .unwrap(),
+        );
+    }
+    db.insert_item(NewItem { content_blob: Some(vec![0; 31]), ..text_item(b"abc") }).unwrap();
+    db.pin_item(ids[0], true).unwrap();
+
+    let all = file_items(&db, &km, 10, false).unwrap();
+    assert_eq!(all.iter().map(|i| i.id).collect::<Vec<_>>(), vec![ids[0], ids[1]]);
+    assert_eq!(all[0].preview.as_deref(), Some("present.txt"));
+    assert_eq!(all[0].file_exists, Some(true));
+    assert_eq!(all[0].size, 5);
+    assert_eq!(all[1].file_exists, Some(false));
+
+    let existing = file_items(&db, &km, 10, true).unwrap();
+    assert_eq!(existing.iter().map(|i| i.id).collect::<Vec<_>>(), vec![ids[0]]);
+    assert_eq!(file_items(&db, &km, 1, false).unwrap().len(), 1);
+}
+
+#[test]
+fn list_since_returns_only_newer_ids_ascending() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let mut ids = Vec::new();
+    for i in 0..5u8 {
+        ids.push(
+            db.insert_item(NewItem { content_blob: Some(vec![i; 29]), ..text_item(&[i]) }).unwrap(),
+        );
+    }
+    // pinning does not affect delta order
+    db.pin_item(ids[4], true).unwrap();
+
+    let got: Vec<i64> = db.list_since(ids[1], 10).unwrap().iter().map(|i| i.id).collect();
+    assert_eq!(got, ids[2..].to_vec());
+    let got: Vec<i64> = db.list_since(0, 2).unwrap().iter().map(|i| i.id).collect();
+    assert_eq!(got, ids[..2].to_vec());
+    assert!(db.list_since(ids[4], 10).unwrap().is_empty());
+}
+
+#[test]
+fn list_large_orders_by_size_and_filters_kind() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let insert = |kind: &str, size: i64| {
+        db.insert_item(NewItem { kind: kind.into(), size, content_blob: Some(vec![0; 29]), ..text_item(&size.to_le_bytes()) }).unwrap()
+    };
+    let image = insert("image", 5_000_000);
+    let code = insert("code", 2_000_000);
+    let small = insert("text", 10);
+    let text = insert("text", 1_048_576);
+
+    let ids = |v: Vec<cliper_lib::db::ItemDto>| v.into_iter().map(|i| i.id).collect::<Vec<_>>();
+    assert_eq!(ids(db.list_large(1_048_576, None, 10).unwrap()), vec![image, code, text]);
+    assert_eq!(ids(db.list_large(1_048_576, None, 2).unwrap()), vec![image, code]);
+    assert_eq!(ids(db.list_large(1_048_576, Some("text"), 10).unwrap()), vec![code, text]);
+    assert_eq!(ids(db.list_large(0, Some("text"), 10).unwrap()), vec![code, text, small]);
+    assert_eq!(ids(db.list_large(0, Some("image"), 10).unwrap()), vec![image]);
+    assert!(db.list_large(10_000_000, None, 10).unwrap().is_empty());
+}
+
+#[test]
+fn relabel_item_only_moves_between_text_kinds() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let insert = |kind: &str, seed: u8| {
+        db.insert_item(NewItem { kind: kind.into(), content_blob: Some(vec![seed; 29]), ..text_item(&[seed]) }).unwrap()
+    };
+    let url = insert("url", 1);
+    let image = insert("image", 2);
+
+    db.relabel_item(url, "snippet").unwrap();
+    assert_eq!(db.get_item(url).unwrap().kind, "snippet");
+    db.relabel_item(url, "text").unwrap();
+    assert_eq!(db.get_item(url).unwrap().kind, "text");
+
+    assert!(db.relabel_item(url, "image").is_err());
+    assert!(db.relabel_item(url, "bogus").is_err());
+    assert!(db.relabel_item(image, "text").is_err());
+    assert_eq!(db.get_item(image).unwrap().kind, "image");
+    assert!(db.relabel_item(999, "text").is_err());
+}
+
+#[test]
+fn wipe_everything_clears_history_and_replaces_the_key() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.wipe".into());
+    km.unlock().unwrap();
+    let ct = km.encrypt(b"gone soon").unwrap();
+    db.insert_item(NewItem { content_blob: Some(ct.clone()), ..text_item(b"gone soon") }).unwrap();
+
+    wipe_everything(&db, &km, &LockedCaptures::default()).unwrap();
+    assert!(db.list_recent(10).unwrap().is_empty());
+    assert!(km.decrypt(&ct).is_err());
+}
+
+#[test]
+fn wipe_leaves_no_indexed_text_on_disk() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.wipe.disk".into());
+    km.unlock().unwrap();
+    let id = db.insert_item(text_item(b"1")).unwrap();
+    db.index_text(id, "correct horse battery staple").unwrap();
+    assert!(on_disk(tmp.path(), "correct horse battery staple"));
+
+    wipe_everything(&db, &km, &LockedCaptures::default()).unwrap();
+    assert!(!on_disk(tmp.path(), "correct horse battery staple"));
+}
+
+#[test]
+fn notes_are_encrypted_searchable_and_removable() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.notes".into());
+    km.unlock().unwrap();
+    let insert = |kind: &str, content: &[u8]| {
+        db.insert_item(NewItem { kind: kind.into(), content_blob: Some(km.encrypt(content).unwrap()), ..text_item(content) }).unwrap()
+    };
+    let text = insert("text", b"hunter2");
+    let image = insert("image", b"\x89PNG");
+
+    set_note(&db, &km, text, Some("  staging db password ")).unwrap();
+    set_note(&db, &km, image, Some("client X logo")).unwrap();
+    let blob = db.get_item(text).unwrap().note_blob.unwrap();
+    assert!(!blob.windows(7).any(|w| w == b"staging"));
+
+    let hits = search_items(&db, &km, "STAGING", None, None, 10, SEARCH_SCAN_LIMIT).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, text);
+    assert_eq!(hits[0].note.as_deref(), Some("staging db password"));
+    let hits = search_items(&db, &km, "logo", None, None, 10, SEARCH_SCAN_LIMIT).unwrap();
+    assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), vec![image]);
+    // content still matches alongside the note
+    assert_eq!(search_items(&db, &km, "hunter", None, None, 10, SEARCH_SCAN_LIMIT).unwrap().len(), 1);
+
+    assert!(set_note(&db, &km, text, Some(&"x".repeat(NOTE_MAX_CHARS + 1))).is_err());
+    assert!(set_note(&db, &km, 999, Some("nowhere")).is_err());
+    set_note(&db, &km, text, Some("   ")).unwrap();
+    assert!(db.get_item(text).unwrap().note_blob.is_none());
+    assert!(search_items(&db, &km, "staging", None, None, 10, SEARCH_SCAN_LIMIT).unwrap().is_empty());
+}
+
+#[test]
+fn file_search_matches_plain_paths_while_locked() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.locked_search".into());
+    km.unlock().unwrap();
+    let file = |path: &str, sealed: bool| {
+        db.insert_item(NewItem {
+            kind: "file".into(),
+            size: 0,
+            sha256: Database::compute_sha256(path.as_bytes()),
+            file_path: (!sealed).then(|| path.into()),
+            file_path_blob: sealed.then(|| km.encrypt(path.as_bytes()).unwrap()),
+            content_blob: None,
+            preview_blob: None,
+            rtf_blob: None,
+            html_blob: None,
+            source_app: None,
+            uti: None,
+        })
+        .unwrap()
+    };
+    let plain = file("/tmp/quarterly-report.pdf", false);
+    file("/tmp/quarterly-secret.pdf", true);
+    km.lock();
+
+    let hits = search_items(&db, &km, "quarterly", Some("file"), None, 10, SEARCH_SCAN_LIMIT).unwrap();
+    assert_eq!(hits.iter().map(|h| h.id).collect::<Vec<_>>(), vec![plain]);
+    assert_eq!(hits[0].preview.as_deref(), Some("quarterly-report.pdf"));
+}
+
+#[test]
+fn search_capabilities_report_lock_state_and_scan_size() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.search-caps".into());
+    let caps = search_capabilities_of(&db, &km, false).unwrap();
+    assert_eq!(caps, SearchCapabilities { content_search: false, fts_indexed: false, items_scanned: 0, total_items: 0 });
+
+    km.unlock().unwrap();
+    for i in 0..SEARCH_SCAN_LIMIT + 5 {
+        db.insert_item(NewItem { content_blob: Some(km.encrypt(&i.to_le_bytes()).unwrap()), ..text_item(&i.to_le_bytes()) }).unwrap();
+    }
+    let caps = search_capabilities_of(&db, &km, false).unwrap();
+    assert!(caps.content_search);
+    assert_eq!(caps.items_scanned, SEARCH_SCAN_LIMIT as i64);
+    assert_eq!(caps.total_items, SEARCH_SCAN_LIMIT as i64 + 5);
+}
+
+#[test]
+fn disk_space_reports_the_database_volume() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    let space = db.disk_space().unwrap().unwrap();
+    assert!(space.total_bytes > 0);
+    assert!(space.free_bytes <= space.total_bytes);
+    assert!(Database::in_memory().unwrap().disk_space().unwrap().is_none());
+}
+
+#[test]
+fn ndjson_export_writes_one_item_per_line() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.ndjson".into());
+    km.unlock().unwrap();
+    let insert = |kind: &str, content: &[u8], file_path: Option<&str>| {
+        db.insert_item(NewItem {
+            kind: kind.into(),
+            file_path: file_path.map(Into::into),
+            content_blob: Some(km.encrypt(content).unwrap()),
+            ..text_item(content)
+        })
+        .unwrap()
+    };
+    let first = insert("text", b"line one\nwith a break", None);
+    let secret = insert("text", b"hunter2", None);
+    insert("file", b"/tmp/report.pdf", Some("/tmp/report.pdf"));
+    db.set_sensitive(secret, true).unwrap();
+
+    let mut out = Vec::new();
+    assert_eq!(write_items_ndjson(&db, &km, &mut out).unwrap(), 3);
+    let lines: Vec<serde_json::Value> = String::from_utf8(out)
+        .unwrap()
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["id"], first);
+    assert_eq!(lines[0]["text"], "line one\nwith a break");
+    assert!(lines[1]["text"].is_null());
+    assert_eq!(lines[2]["preview"], "report.pdf");
+
+    km.lock();
+    let mut out = Vec::new();
+    write_items_ndjson(&db, &km, &mut out).unwrap();
+    let first_line: serde_json::Value = serde_json::from_str(String::from_utf8(out).unwrap().lines().next().unwrap()).unwrap();
+    assert!(first_line["text"].is_null());
+    assert!(first_line["preview"].is_null());
+}
+
+#[test]
+fn sticky_item_leads_recent_list_until_deleted() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let ids: Vec<i64> = (1..=4u8).map(|n| db.insert_item(text_item(&[n])).unwrap()).collect();
+    db.pin_item(ids[3], true).unwrap();
+    let order = |sticky: Option<i64>, limit: u32| {
+        let (items, found) = recent_items(&db, sticky, limit).unwrap();
+        (items.into_iter().map(|i| i.id).collect::<Vec<_>>(), found)
+    };
+
+    assert_eq!(order(None, 10), (vec![ids[3], ids[2], ids[1], ids[0]], true));
+    // ahead of the pinned item, and fetched even when outside the limit
+    assert_eq!(order(Some(ids[1]), 10), (vec![ids[1], ids[3], ids[2], ids[0]], true));
+    assert_eq!(order(Some(ids[0]), 2), (vec![ids[0], ids[3]], true));
+
+    db.delete_item(ids[0]).unwrap();
+    assert_eq!(order(Some(ids[0]), 10), (vec![ids[3], ids[2], ids[1]], false));
+}
+
+#[test]
+fn image_preview_side_is_clamped() {
+    // 0 and no value both mean the configured thumbnail size
+    assert_eq!(preview_side(None, 128), 128);
+    assert_eq!(preview_side(Some(0), 128), 128);
+    // anything smaller is served as the cached thumbnail
+    assert_eq!(preview_side(Some(1), 128), 128);
+    assert_eq!(preview_side(Some(64), 128), 128);
+    assert_eq!(preview_side(Some(1), 16), 16);
+    assert_eq!(preview_side(Some(300), 128), 300);
+    assert_eq!(preview_side(Some(u32::MAX), 128), 1024);
+    assert_eq!(preview_side(Some(5000), 1024), 1024);
+}
+
+#[test]
+fn unread_counts_items_after_the_last_seen_id() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    assert_eq!(db.max_id().unwrap(), 0);
+    assert_eq!(db.count_after(0).unwrap(), 0);
+    let ids: Vec<i64> = (1..=3u8).map(|n| db.insert_item(text_item(&[n])).unwrap()).collect();
+    assert_eq!(db.count_after(0).unwrap(), 3);
+
+    let seen = db.max_id().unwrap();
+    assert_eq!(seen, ids[2]);
+    assert_eq!(db.count_after(seen).unwrap(), 0);
+    assert_eq!(db.count_after(ids[0]).unwrap(), 2);
+}
+
+#[test]
+fn deeper_scan_limit_finds_older_items() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.search-depth".into());
+    km.unlock().unwrap();
+    let texts = std::iter::once("the old needle".to_string()).chain((0..SEARCH_SCAN_LIMIT + 5).map(|i| format!("filler {i}")));
+    for text in texts {
+        db.insert_item(NewItem { content_blob: Some(km.encrypt(text.as_bytes()).unwrap()), ..text_item(text.as_bytes()) }).unwrap();
+    }
+
+    // outside the default window
+    assert!(search_items(&db, &km, "needle", None, None, 10, SEARCH_SCAN_LIMIT).unwrap().is_empty());
+    assert_eq!(search_items(&db, &km, "needle", None, None, 10, SEARCH_SCAN_LIMIT + 10).unwrap().len(), 1);
+    // oversized and zero limits are clamped rather than rejected
+    assert_eq!(search_items(&db, &km, "needle", None, None, 10, u32::MAX).unwrap().len(), 1);
+    assert_eq!(search_items(&db, &km, "", None, None, 10, 0).unwrap().len(), 1);
+}
+
+#[test]
+fn search_index_covers_history_past_the_scan_window() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.search-index".into());
+    km.unlock().unwrap();
+    let insert = |text: &str| {
+        db.insert_item(NewItem { content_blob: Some(km.encrypt(text.as_bytes()).unwrap()), ..text_item(text.as_bytes()) }).unwrap()
+    };
+    let old = insert("the Needle from last year, Zq");
+    let secret = insert("needle in the vault");
+    db.set_sensitive(secret, true).unwrap();
+    for i in 0..SEARCH_SCAN_LIMIT {
+        insert(&format!("filler {i}"));
+    }
+    let ids = |hits: Vec<cliper_lib::api::UiItemDto>| hits.into_iter().map(|h| h.id).collect::<Vec<_>>();
+
+    assert!(search_items(&db, &km, "needle", None, None, 10, SEARCH_SCAN_LIMIT).unwrap().is_empty());
+    assert_eq!(ids(search_items_indexed(&db, &km, "NEEDLE", None, None, 10, SEARCH_SCAN_LIMIT).unwrap()), vec![old]);
+    // everything but the sensitive item was indexed on the way
+    assert!(db.unindexed_text_ids(1000).unwrap().is_empty());
+    assert_eq!(index_pending(&db, &km, 1000).unwrap(), 0);
+    assert_eq!(db.search_fts("zq", 10).unwrap(), vec![old]);
+    assert_eq!(db.search_fts("filler 1", 500).unwrap().len(), 111);
+    assert!(db.search_fts("vault", 10).unwrap().is_empty());
+
+    db.delete_item(old).unwrap();
+    assert!(db.search_fts("needle", 10).unwrap().is_empty());
+    db.clear_search_index().unwrap();
+    assert!(db.search_fts("filler", 10).unwrap().is_empty());
+    assert!(search_capabilities_of(&db, &km, true).unwrap().fts_indexed);
+}
+
+#[test]
+fn unindexed_text_leaves_no_plaintext_on_disk() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let on_disk = |needle: &str| on_disk(tmp.path(), needle);
+    let secret = db.insert_item(text_item(b"1")).unwrap();
+    let other = db.insert_item(text_item(b"2")).unwrap();
+    db.index_text(secret, "correct horse battery staple").unwrap();
+    db.index_text(other, "tortoise shell spectacles").unwrap();
+    assert!(on_disk("correct horse battery staple"));
+
+    db.set_sensitive(secret, true).unwrap();
+    assert!(!on_disk("correct horse battery staple"));
+    assert!(on_disk("tortoise shell spectacles"));
+
+    db.clear_search_index().unwrap();
+    assert!(!on_disk("tortoise shell spectacles"));
+}
+
+#[test]
+fn tags_label_items_and_narrow_search() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.tags".into());
+    km.unlock().unwrap();
+    let insert = |text: &str| {
+        db.insert_item(NewItem { content_blob: Some(km.encrypt(text.as_bytes()).unwrap()), ..text_item(text.as_bytes()) }).unwrap()
+    };
+    let quote = insert("a quote about proteins");
+    let other = insert("proteins again, untagged");
+
+    assert!(db.add_tag(quote, " research ").unwrap());
+    assert!(!db.add_tag(quote, "Research").unwrap());
+    assert!(db.add_tag(quote, "biology").unwrap());
+    assert!(db.add_tag(quote, "").is_err());
+    assert!(db.add_tag(quote, "a\tb").is_err());
+    assert!(db.add_tag(999, "research").is_err());
+    assert_eq!(db.list_tags(quote).unwrap(), vec!["biology", "research"]);
+    assert_eq!(db.get_item(quote).unwrap().tags, vec!["biology", "research"]);
+    assert!(db.get_item(other).unwrap().tags.is_empty());
+
+    let ids = |hits: Vec<cliper_lib::api::UiItemDto>| hits.into_iter().map(|h| h.id).collect::<Vec<_>>();
+    assert_eq!(ids(search_items(&db, &km, "proteins", None, None, 10, SEARCH_SCAN_LIMIT).unwrap()), vec![other, quote]);
+    assert_eq!(ids(search_items(&db, &km, "proteins", None, Some("RESEARCH"), 10, SEARCH_SCAN_LIMIT).unwrap()), vec![quote]);
+    assert_eq!(ids(search_items_indexed(&db, &km, "proteins", None, Some("research"), 10, SEARCH_SCAN_LIMIT).unwrap()), vec![quote]);
+    assert_eq!(search_items(&db, &km, "", None, Some("research"), 10, SEARCH_SCAN_LIMIT).unwrap()[0].tags, vec!["biology", "research"]);
+
+    assert!(db.remove_tag(quote, "research").unwrap());
+    assert!(!db.remove_tag(quote, "research").unwrap());
+    db.delete_item(quote).unwrap();
+    assert!(db.list_tags(quote).unwrap().is_empty());
+    assert!(db.list_tagged("biology", 10).unwrap().is_empty());
+}
+
+#[test]
+fn history_pages_follow_the_cursor_without_repeats() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let ids: Vec<i64> = (1..=6u8).map(|n| db.insert_item(text_item(&[n])).unwrap()).collect();
+    // the fifth capture happened while the clock ran an hour fast; pages
+    // still follow capture order, like list_recent
+    let raw = rusqlite::Connection::open(tmp.path().join("cliper.sqlite")).unwrap();
+    raw.execute("UPDATE items SET created_at = created_at + 3600000 WHERE id = ?1", [ids[4]]).unwrap();
+    db.pin_item(ids[1], true).unwrap();
+    let page = |cursor: Option<&str>| {
+        let (items, next) = history_page(&db, cursor, 2).unwrap();
+        (items.into_iter().map(|i| i.id).collect::<Vec<_>>(), next)
+    };
+
+    let (first, next) = page(None);
+    assert_eq!(first, vec![ids[1], ids[5], ids[4]]);
+    let recent: Vec<i64> = db.list_recent(3).unwrap().into_iter().map(|i| i.id).collect();
+    assert_eq!(first, recent);
+    let (second, next) = page(next.as_deref());
+    assert_eq!(second, vec![ids[3], ids[2]]);
+    let (last, next) = page(next.as_deref());
+    assert_eq!(last, vec![ids[0]]);
+    assert_eq!(next, None);
+    assert!(history_page(&db, Some("nope"), 2).is_err());
+}
+
+#[test]
+fn list_oldest_skips_or_trails_pinned_items() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let ids: Vec<i64> = (1..=4u8).map(|n| db.insert_item(text_item(&[n])).unwrap()).collect();
+    // the third item was captured long ago, the first is pinned
+    let raw = rusqlite::Connection::open(tmp.path().join("cliper.sqlite")).unwrap();
+    raw.execute("UPDATE items SET created_at = created_at - 86400000 WHERE id = ?1", [ids[2]]).unwrap();
+    db.pin_item(ids[0], true).unwrap();
+    let order = |limit, include_pinned| db.list_oldest(limit, include_pinned).unwrap().into_iter().map(|i| i.id).collect::<Vec<_>>();
+
+    assert_eq!(order(10, false), vec![ids[2], ids[1], ids[3]]);
+    assert_eq!(order(2, false), vec![ids[2], ids[1]]);
+    assert_eq!(order(10, true), vec![ids[2], ids[1], ids[3], ids[0]]);
+}
+
+#[test]
+fn get_items_keeps_the_requested_order() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let ids: Vec<i64> = (1..=3u8).map(|n| db.insert_item(text_item(&[n])).unwrap()).collect();
+    let fetch = |wanted: &[i64]| db.get_items(wanted).unwrap().into_iter().map(|i| i.id).collect::<Vec<_>>();
+
+    assert_eq!(fetch(&[ids[2], ids[0], ids[1]]), vec![ids[2], ids[0], ids[1]]);
+    // unknown ids are dropped, repeats collapse to the first
+    assert_eq!(fetch(&[ids[1], 9_999, ids[1], ids[0]]), vec![ids[1], ids[0]]);
+    assert!(fetch(&[]).is_empty());
+}
+
+#[test]
+fn encryption_overhead_compares_plaintext_and_stored_sizes() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.overhead".into());
+    km.unlock().unwrap();
+    assert_eq!(db.encryption_overhead().unwrap().total.items, 0);
+
+    let per_item = km.encrypt(b"").unwrap().len() as i64;
+    let items: [(&str, &[u8], bool); 4] = [
+        ("text", b"hello", true),
+        ("text", b"a somewhat longer line", true),
+        ("image", b"not really a png", true),
+        // a file reference stores no content and is left out
+        ("file", b"", false),
+    ];
+    for (n, (kind, data, stored)) in items.into_iter().enumerate() {
+        db.insert_item(NewItem {
+            kind: kind.into(),
+            size: data.len() as i64,
+            file_path: (!stored).then(|| "/tmp/x".into()),
+            content_blob: stored.then(|| km.encrypt(data).unwrap()),
+            ..text_item(&[n as u8])
+        })
+        .unwrap();
+    }
+
+    let report = db.encryption_overhead().unwrap();
+    assert_eq!(
+        report.kinds,
+        vec![
+            EncryptionOverhead { kind: "image".into(), items: 1, plaintext_bytes: 16, stored_bytes: 16 + per_item, overhead_bytes: per_item },
+            EncryptionOverhead { kind: "text".into(), items: 2, plaintext_bytes: 27, stored_bytes: 27 + 2 * per_item, overhead_bytes: 2 * per_item },
+        ]
+    );
+    assert_eq!(
+        report.total,
+        EncryptionOverhead { kind: "total".into(), items: 3, plaintext_bytes: 43, stored_bytes: 43 + 3 * per_item, overhead_bytes: 3 * per_item }
+    );
+}
+
+#[test]
+fn source_app_is_listed_and_may_be_unknown() {
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let insert = |text: &[u8], app: Option<&str>| {
+        db.insert_item(NewItem { source_app: app.map(Into::into), ..text_item(text) }).unwrap()
+    };
+    let known = insert(b"from safari", Some("com.apple.Safari"));
+    let unknown = insert(b"from somewhere", None);
+
+    assert_eq!(db.get_item(known).unwrap().source_app.as_deref(), Some("com.apple.Safari"));
+    assert_eq!(db.get_item(unknown).unwrap().source_app, None);
+    let listed: Vec<_> = db.list_recent(10).unwrap().into_iter().map(|i| (i.id, i.source_app)).collect();
+    assert_eq!(listed, vec![(unknown, None), (known, Some("com.apple.Safari".to_string()))]);
+}
+
+#[test]
+fn purge_removes_old_unpinned_items_only() {
+    let tmp = tempfile::tempdir().unwrap();
+    let db = Database::new(tmp.path().to_path_buf()).unwrap();
+    db.migrate().unwrap();
+    let ids: Vec<i64> = (0..120u32).map(|n| db.insert_item(text_item(&n.to_le_bytes())).unwrap()).collect();
+    // all but the last item are a month old, and the first of those is pinned
+    let raw = rusqlite::Connection::open(tmp.path().join("cliper.sqlite")).unwrap();
+    raw.execute("UPDATE items SET created_at = created_at - 30 * 86400000 WHERE id != ?1", [ids[119]]).unwrap();
+    db.pin_item(ids[0], true).unwrap();
+
+    let cutoff = now_millis() - 7 * 86_400_000;
+    assert_eq!(db.purge_older_than(cutoff).unwrap(), 118);
+    let left: Vec<i64> = db.list_recent(200).unwrap().into_iter().map(|i| i.id).collect();
+    assert_eq!(left, vec![ids[0], ids[119]]);
+    assert_eq!(db.purge_older_than(cutoff).unwrap(), 0);
+}
+
+#[test]
+fn rotating_the_key_reencrypts_history_or_changes_nothing() {
+    use cliper_lib::api::rotate_key_of;
+
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle.rotate".into());
+    km.unlock().unwrap();
+    let id = db
+        .insert_item(NewItem {
+            content_blob: Some(km.encrypt(b"hello").unwrap()),
+            rtf_blob: Some(km.encrypt(b"{\\rtf1 hello}").unwrap()),
+            ..text_item(b"hello")
+        })
+        .unwrap();
+    set_note(&db, &km, id, Some("greeting")).unwrap();
+    db.set_extra_types(id, &[("com.example.custom".into(), km.encrypt(b"extra").unwrap())]).unwrap();
+    let (_, old_content, _, _, _) = db.get_item_raw(id).unwrap();
+    let old_content = old_content.unwrap();
+
+    assert_eq!(rotate_key_of(&db, &km).unwrap(), 4);
+    let (_, content, _, rtf, _) = db.get_item_raw(id).unwrap();
+    assert_eq!(km.decrypt(&content.unwrap()).unwrap(), b"hello");
+    assert_eq!(km.decrypt(&rtf.unwrap()).unwrap(), b"{\\rtf1 hello}");
+    assert_eq!(km.decrypt(&db.get_item(id).unwrap().note_blob.unwrap()).unwrap(), b"greeting");
+    assert_eq!(km.decrypt(&db.get_extra_types(id).unwrap()[0].1).unwrap(), b"extra");
+    assert!(matches!(km.decrypt(&old_content), Err(CryptoError::Decrypt)));
+
+    // the new key is the one in the Keychain
+    let relaunched = KeyManager::new("test.bundle.rotate".into());
+    relaunched.unlock().unwrap();
+    let (_, content, _, _, _) = db.get_item_raw(id).unwrap();
+    assert_eq!(relaunched.decrypt(&content.unwrap()).unwrap(), b"hello");
+
+    // one blob that does not decrypt rolls everything back and keeps the key
+    db.insert_item(NewItem { content_blob: Some(vec![0u8; 40]), ..text_item(b"junk") }).unwrap();
+    let (_, before, _, _, _) = db.get_item_raw(id).unwrap();
+    assert!(rotate_key_of(&db, &km).is_err());
+    let (_, after, _, _, _) = db.get_item_raw(id).unwrap();
+    assert_eq!(before, after);
+    assert_eq!(km.decrypt(&after.unwrap()).unwrap(), b"hello");
+}
+
+#[test]
+fn captures_during_a_rotation_are_stored_under_the_new_key() {
+    use cliper_lib::api::rotate_key_of;
+    use cliper_lib::clipboard::{store_snapshot, Snapshot};
+    use cliper_lib::state::Settings;
+
+    let db = Database::in_memory().unwrap();
+    db.migrate().unwrap();
+    let km = KeyManager::new("test.bundle.rotate-race".into());
+    km.unlock().unwrap();
+    let settings = Settings::default();
+    let capture = |text: String| store_snapshot(&db, &km, &settings, Snapshot { text: Some(text), ..Default::default() }).unwrap();
+    // enough history that each rotation takes a while
+    for n in 0..300 {
+        capture(format!("before {n}"));
+    }
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            for n in 0..100 {
+                capture(format!("during {n}"));
+            }
+        });
+        for _ in 0..3 {
+            rotate_key_of(&db, &km).unwrap();
+        }
+    });
+
+    let items = db.list_recent(1000).unwrap();
+    assert_eq!(items.len(), 400);
+    for it in items {
+        let (_, content, _, _, _) = db.get_item_raw(it.id).unwrap();
+        assert!(km.decrypt(&content.unwrap()).is_ok(), "item {} is not readable", it.id);
+    }
+}
@@ -0,0 +1,119 @@
+use cliper_lib::state::{
+    load_filter_state, load_settings, save_filter_state, save_settings, session_path, settings_schema, FilterState, Settings,
+    clamp_poll_interval_ms, clamp_refresh_debounce_ms, Activity, WipeConfirm,
+};
+
+#[test]
+fn schema_describes_every_setting_with_its_default() {
+    let schema = settings_schema();
+    let defaults = serde_json::to_value(Settings::default()).unwrap();
+    let keys = defaults.as_object().unwrap();
+
+    assert_eq!(schema.len(), keys.len());
+    for field in &schema {
+        assert_eq!(Some(&field.default), keys.get(field.key), "{}", field.key);
+        if field.ty == "enum" {
+            let default = field.default.as_str().unwrap();
+            assert!(field.options.contains(&default), "{}", field.key);
+        }
+    }
+    let thumb = schema.iter().find(|f| f.key == "thumbnail_max").unwrap();
+    assert_eq!((thumb.min, thumb.max), (Some(16), Some(1024)));
+}
+
+#[test]
+fn old_and_newer_settings_files_keep_known_values() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = tmp.path().join("settings.json");
+
+    // written before most fields existed
+    std::fs::write(&path, r#"{"auto_lock_minutes": 15, "hotkey": "Cmd+Alt+V"}"#).unwrap();
+    let s = load_settings(&path).unwrap();
+    assert_eq!(s.auto_lock_minutes, 15);
+    assert_eq!(s.hotkey, "Cmd+Alt+V");
+    assert_eq!(s.thumbnail_max, Settings::default().thumbnail_max);
+
+    // from a newer version: unknown key, and an enum value we can't read
+    std::fs::write(
+        &path,
+        r#"{"version": 9, "hotkey": "Cmd+Alt+V", "nonce_mode": "xchacha", "future_flag": true, "peek_no_focus": true}"#,
+    )
+    .unwrap();
+    let s = load_settings(&path).unwrap();
+    assert_eq!(s.hotkey, "Cmd+Alt+V");
+    assert!(s.peek_no_focus);
+    assert_eq!(s.nonce_mode, Settings::default().nonce_mode);
+
+    save_settings(&path, &s);
+    assert_eq!(load_settings(&path).unwrap().hotkey, "Cmd+Alt+V");
+}
+
+#[test]
+fn filter_state_roundtrips_and_clears() {
+    let tmp = tempfile::tempdir().unwrap();
+    let path = session_path(tmp.path().to_path_buf());
+    assert_eq!(load_filter_state(&path), FilterState::default());
+
+    let f = FilterState { kind: Some("image".into()), query: "invoice".into() };
+    save_filter_state(&path, &f);
+    assert_eq!(load_filter_state(&path), f);
+
+    save_filter_state(&path, &FilterState::default());
+    assert!(!path.exists());
+}
+
+#[test]
+fn wipe_tokens_are_single_use() {
+    let confirm = WipeConfirm::default();
+    assert!(!confirm.redeem(""));
+
+    let token = confirm.issue().unwrap();
+    assert_eq!(token.len(), 32);
+    assert!(!confirm.redeem("wrong"));
+    // a wrong guess burns the token
+    assert!(!confirm.redeem(&token));
+
+    // a new request replaces the previous token
+    let replaced = confirm.issue().unwrap();
+    let _ = confirm.issue().unwrap();
+    assert!(!confirm.redeem(&replaced));
+
+    let token = confirm.issue().unwrap();
+    assert!(confirm.redeem(&token));
+    assert!(!confirm.redeem(&token));
+}
+
+#[test]
+fn auto_lock_countdown_follows_activity() {
+    let activity = Activity::default();
+    let start = activity.last();
+    assert_eq!(activity.remaining_at(start, 0), None);
+    assert_eq!(activity.remaining_at(start, 5), Some(300));
+    assert_eq!(activity.remaining_at(start + 60_500, 5), Some(240));
+    assert_eq!(activity.remaining_at(start + 300_000, 5), Some(0));
+    assert_eq!(activity.remaining_at(start + 900_000, 5), Some(0));
+    // an absurd setting counts as "a very long time", not an overflow
+    assert!(activity.remaining_at(start, u64::MAX).unwrap() > 0);
+
+    activity.touch_at(start + 120_000);
+    assert_eq!(activity.remaining_at(start + 120_000, 5), Some(300));
+    // an older timestamp never rewinds the countdown
+    activity.touch_at(start);
+    assert_eq!(activity.last(), start + 120_000);
+}
+
+#[test]
+fn refresh_debounce_is_clamped_and_defaults_to_100ms() {
+    assert_eq!(Settings::default().refresh_debounce_ms, 100);
+    assert_eq!(clamp_refresh_debounce_ms(0), 0);
+    assert_eq!(clamp_refresh_debounce_ms(250), 250);
+    assert_eq!(clamp_refresh_debounce_ms(60_000), 2_000);
+}
+
+#[test]
+fn poll_interval_defaults_to_250ms_and_is_clamped() {
+    assert_eq!(Settings::default().poll_interval_ms, 250);
+    assert_eq!(clamp_poll_interval_ms(0), 50);
+    assert_eq!(clamp_poll_interval_ms(1_000), 1_000);
+    assert_eq!(clamp_poll_interval_ms(60_000), 5_000);
+}
@@ -0,0 +1,42 @@
+use cliper_lib::timefmt::{format_timestamp, format_timestamp_at, TimestampStyle};
+use time::UtcOffset;
+
+const MIN: i64 = 60_000;
+const HOUR: i64 = 60 * MIN;
+const DAY: i64 = 24 * HOUR;
+
+#[test]
+fn formats_in_the_given_timezone() {
+    // 2026-10-16 12:05:00 UTC, 14:05 at +02:00
+    let at = 1_792_152_300_000;
+    let plus2 = UtcOffset::from_hms(2, 0, 0).unwrap();
+    let fmt = |ts: i64, style| format_timestamp_at(ts, at, plus2, style).unwrap();
+
+    assert_eq!(fmt(at, TimestampStyle::Short), "2026-10-16 14:05");
+    assert_eq!(fmt(at, TimestampStyle::Long), "Friday, 16 October 2026 14:05:00 UTC+02:00");
+    let minus330 = UtcOffset::from_hms(-3, -30, 0).unwrap();
+    assert_eq!(
+        format_timestamp_at(at, at, minus330, TimestampStyle::Long).unwrap(),
+        "Friday, 16 October 2026 08:35:00 UTC-03:30"
+    );
+
+    assert_eq!(fmt(at - 20_000, TimestampStyle::Relative), "just now");
+    assert_eq!(fmt(at - 5 * MIN, TimestampStyle::Relative), "5 min ago");
+    assert_eq!(fmt(at - 3 * HOUR, TimestampStyle::Relative), "3 h ago");
+    // 15 hours back is the previous local date, 13 is still today
+    assert_eq!(fmt(at - 15 * HOUR, TimestampStyle::Relative), "yesterday, 23:05");
+    assert_eq!(fmt(at - 13 * HOUR, TimestampStyle::Relative), "13 h ago");
+    assert_eq!(fmt(at - 3 * DAY, TimestampStyle::Relative), "3 days ago");
+    assert_eq!(fmt(at - 30 * DAY, TimestampStyle::Relative), "2026-09-16 14:05");
+}
+
+#[test]
+fn future_timestamps_do_not_read_as_countdowns() {
+    let at = 1_792_152_300_000;
+    let utc = UtcOffset::UTC;
+    // a little clock skew is still "just now"
+    assert_eq!(format_timestamp_at(at + 30_000, at, utc, TimestampStyle::Relative).unwrap(), "just now");
+    assert_eq!(format_timestamp_at(at + 2 * HOUR, at, utc, TimestampStyle::Relative).unwrap(), "2026-10-16 14:05");
+    assert!(format_timestamp_at(i64::MAX, at, utc, TimestampStyle::Short).is_err());
+    assert!(format_timestamp(at, TimestampStyle::Short).is_ok());
+}
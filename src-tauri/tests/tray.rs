@@ -0,0 +1,21 @@
+use cliper_lib::tray::{fallback_icon, is_usable};
+use tauri::Icon;
+
+#[test]
+fn fallback_icon_is_embedded_and_usable() {
+    let icon = fallback_icon().expect("embedded tray icon decodes");
+    assert!(is_usable(Some(&icon)));
+    match icon {
+        Icon::Rgba { width, height, .. } => assert!(width > 0 && height > 0),
+        _ => panic!("expected rgba"),
+    }
+
+    let tmp = tempfile::tempdir().unwrap();
+    let empty = tmp.path().join("empty.png");
+    std::fs::write(&empty, b"").unwrap();
+    assert!(!is_usable(None));
+    assert!(!is_usable(Some(&Icon::File(empty))));
+    assert!(!is_usable(Some(&Icon::File(tmp.path().join("missing.png")))));
+    assert!(!is_usable(Some(&Icon::Raw(Vec::new()))));
+    assert!(!is_usable(Some(&Icon::Rgba { rgba: vec![0; 3], width: 1, height: 1 })));
+}
@@ -7,12 +7,22 @@ import { appWindow } from '@tauri-apps/api/window'
 type ItemDto = {
   id: number
   created_at: number
-  kind: 'text' | 'image' | 'file'
+  kind: string // 'text' | 'url' | 'color' | 'code' | 'snippet' | 'image' | 'file' | 'blob'
   size: number
   sha256_hex: string
   file_path?: string | null
   is_pinned: boolean
+  plaintext_len: number
+  stored_len: number
+  is_sensitive: boolean
+  uti?: string | null
+  thumbnail_only?: boolean
+  image_corrupt?: boolean
   preview?: string | null
+  note?: string | null
+  is_sticky?: boolean
+  source_app?: string | null
+  tags?: string[]
 }
 
 type KindFilter = 'all' | 'text' | 'image' | 'file'
@@ -24,21 +34,42 @@ export default function App() {
   const [selected, setSelected] = useState<number | null>(null)
   const [showSettings, setShowSettings] = useState(false)
   const [hotkey, setHotkey] = useState('')
+  const [restored, setRestored] = useState(false)
+  // highest id shown so far, checked against the database on focus
+  const lastLoadedId = useRef(0)
 
   const filtered = useMemo(() => {
     let list = items
-    if (filter !== 'all') list = list.filter(i => i.kind === filter)
+    if (filter === 'text') list = list.filter(i => i.kind !== 'image' && i.kind !== 'file' && i.kind !== 'blob')
+    else if (filter !== 'all') list = list.filter(i => i.kind === filter)
     return list
   }, [items, filter])
 
+  // Content commands fail with 'locked' while the key is locked; unlock
+  // (Keychain prompt) and retry once.
+  async function invokeUnlocked<T>(cmd: string, args?: Record<string, unknown>): Promise<T> {
+    try {
+      return await invoke<T>(cmd, args)
+    } catch (e) {
+      if (e !== 'locked') throw e
+      await invoke('unlock')
+      return await invoke<T>(cmd, args)
+    }
+  }
+
   async function refreshRecent() {
     const list = await invoke<ItemDto[]>('list_recent', { limit: 100 })
+    lastLoadedId.current = list.reduce((max, it) => Math.max(max, it.id), lastLoadedId.current)
     setItems(list)
   }
 
   async function doSearch() {
     const k = filter === 'all' ? null : filter
-    const list = await invoke<ItemDto[]>('search', { query, kind: k, limit: 100 })
+    let list = await invokeUnlocked<ItemDto[]>('search', { query, kind: k, limit: 100 })
+    if (list.length === 0 && query.trim()) {
+      // nothing among the newest items; look further back once
+      list = await invokeUnlocked<ItemDto[]>('search', { query, kind: k, limit: 100, scanLimit: 5000 })
+    }
     setItems(list)
   }
 
@@ -51,7 +82,13 @@ export default function App() {
   }
 
   async function copyItem(id: number) {
-    await invoke('copy_item', { id })
+    try {
+      await invokeUnlocked('copy_item', { id })
+    } catch (e) {
+      if (e !== 'file missing') throw e
+      if (confirm('The original file was moved or deleted. Remove this entry?')) await deleteItem(id)
+      return
+    }
     window.close()
   }
 
@@ -67,6 +104,14 @@ export default function App() {
 
   useEffect(() => {
     unlock().then(refreshRecent)
+    ;(async () => {
+      try {
+        const last = await invoke<{ kind: string | null, query: string }>('get_last_filter_state')
+        if (last.kind) setFilter(last.kind as KindFilter)
+        if (last.query) setQuery(last.query)
+      } catch {}
+      setRestored(true)
+    })()
     ;(async () => {
       try {
         const s = await invoke<{ auto_lock_minutes: number, hotkey: string }>('get_settings')
@@ -75,11 +120,45 @@ export default function App() {
     })()
   }, [])
 
+  // bursts of items_updated become one refresh after the debounce
   useEffect(() => {
     let unlisten: UnlistenFn | undefined
+    let unlistenDebounce: UnlistenFn | undefined
+    let timer: ReturnType<typeof setTimeout> | undefined
+    let debounceMs = 100
     ;(async () => {
+      try { debounceMs = await invoke<number>('get_refresh_debounce_ms') } catch {}
+      unlistenDebounce = await listen<number>('refresh_debounce_changed', e => { debounceMs = e.payload })
       unlisten = await listen('items_updated', () => {
-        refreshRecent()
+        if (timer) clearTimeout(timer)
+        timer = setTimeout(refreshRecent, debounceMs)
+      })
+    })()
+    return () => {
+      if (timer) clearTimeout(timer)
+      if (unlisten) unlisten()
+      if (unlistenDebounce) unlistenDebounce()
+    }
+  }, [])
+
+  // Another process may have added items while the window was hidden.
+  useEffect(() => {
+    const onFocus = async () => {
+      try {
+        if (await invoke<boolean>('has_new_since', { afterId: lastLoadedId.current })) await refreshRecent()
+      } catch {}
+    }
+    window.addEventListener('focus', onFocus)
+    return () => window.removeEventListener('focus', onFocus)
+  }, [])
+
+  // The wipe hotkey only asks; nothing is erased until this is confirmed.
+  useEffect(() => {
+    let unlisten: UnlistenFn | undefined
+    ;(async () => {
+      unlisten = await listen<string>('panic_wipe_requested', async e => {
+        if (!confirm('Erase ALL clipboard history and reset the master key? This cannot be undone.')) return
+        try { await invoke('panic_wipe', { token: e.payload }) } catch (err) { alert(`Wipe failed: ${err}`) }
       })
     })()
     return () => {
@@ -87,6 +166,14 @@ export default function App() {
     }
   }, [])
 
+  useEffect(() => {
+    if (!restored) return
+    const t = setTimeout(() => {
+      invoke('set_last_filter_state', { kind: filter === 'all' ? null : filter, query }).catch(() => {})
+    }, 300)
+    return () => clearTimeout(t)
+  }, [filter, query, restored])
+
   // expose reveal helper
   useEffect(() => {
     ;(window as any).__REVEAL = (path: string) => invoke('reveal_in_finder', { path })
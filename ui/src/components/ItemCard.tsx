@@ -3,12 +3,22 @@ import React from 'react'
 type ItemDto = {
   id: number
   created_at: number
-  kind: 'text' | 'image' | 'file'
+  kind: string // 'text' | 'url' | 'color' | 'code' | 'snippet' | 'image' | 'file' | 'blob'
   size: number
   sha256_hex: string
   file_path?: string | null
   is_pinned: boolean
+  plaintext_len: number
+  stored_len: number
+  is_sensitive: boolean
+  uti?: string | null
+  thumbnail_only?: boolean
+  image_corrupt?: boolean
   preview?: string | null
+  note?: string | null
+  is_sticky?: boolean
+  source_app?: string | null
+  tags?: string[]
 }
 
 export default function ItemCard({ item, selected, onClick, onCopy, onPin, onDelete }: {
@@ -19,46 +29,119 @@ export default function ItemCard({ item, selected, onClick, onCopy, onPin, onDel
   onPin?: () => void
   onDelete?: () => void
 }) {
+  const isText = item.kind !== 'image' && item.kind !== 'file' && item.kind !== 'blob'
+  const [revealed, setRevealed] = React.useState<string | null>(null)
+  const [decoded, setDecoded] = React.useState<DecodedPreview | null>(null)
+  React.useEffect(() => { setRevealed(null); setDecoded(null) }, [item.id])
+  const encoded = isText && !item.is_sensitive && looksEncoded(item.preview)
   let title = ''
-  if (item.kind === 'text') title = item.preview || 'Text'
-  if (item.kind === 'image') title = 'Image'
+  if (isText) title = item.is_sensitive ? (revealed ?? '••••••••') : (item.preview || 'Text')
+  if (item.kind === 'image') title = item.image_corrupt ? 'Image (corrupt)' : item.thumbnail_only ? 'Image (thumbnail only)' : 'Image'
   if (item.kind === 'file') title = (item.preview || item.file_path || 'File')
+  if (item.kind === 'blob') title = item.uti || 'Data'
 
   const subtitle = item.kind === 'file'
     ? (item.file_path || '')
-    : `Size: ${formatSize(item.size)}  •  Hash: ${item.sha256_hex.slice(0, 12)}`
+    : `Size: ${formatSize(item.size)}${item.stored_len > 0 ? ` (${formatSize(item.stored_len)} on disk)` : ''}  •  Hash: ${item.sha256_hex.slice(0, 12)}`
 
   return (
     <div className={`card ${selected ? 'selected' : ''}`} onClick={onClick}>
       <div className="icon">
         {item.kind === 'text' && '🅣'}
+        {item.kind === 'url' && '🔗'}
+        {item.kind === 'color' && '🎨'}
+        {item.kind === 'code' && '⌨️'}
+        {item.kind === 'snippet' && '✂️'}
         {item.kind === 'image' && '🖼️'}
         {item.kind === 'file' && '📄'}
+        {item.kind === 'blob' && '📦'}
       </div>
       <div className="body">
         <div className="row1">
           <div className="title">{title}</div>
           <div className="spacer" />
+          {item.source_app && <div className="app-badge" title={item.source_app}>{item.source_app.split('.').pop()}</div>}
+          {item.tags?.map(t => <div key={t} className="tag-chip">{t}</div>)}
           {item.is_pinned && <div className="pin">📌</div>}
         </div>
         <div className="row2">{subtitle}</div>
-        {item.kind === 'image' && <PreviewImage id={item.id} />}
+        {item.note && <div className="row2" title="Note">📝 {item.note}</div>}
+        {item.kind === 'image' && !item.image_corrupt && <PreviewImage id={item.id} />}
+        {item.kind === 'file' && selected && <PreviewFile id={item.id} />}
+        {decoded && <DecodedView preview={decoded} />}
         <div className="actions">
           <button onClick={e => { e.stopPropagation(); onCopy?.() }}>Copy</button>
           <button onClick={e => { e.stopPropagation(); onPin?.() }}>{item.is_pinned ? 'Unpin' : 'Pin'}</button>
           <button onClick={e => { e.stopPropagation(); onDelete?.() }}>Delete</button>
+          <button onClick={e => {
+            e.stopPropagation()
+            const note = window.prompt('Note for this item (empty to remove)', item.note ?? '')
+            if (note !== null) tauriInvoke('set_item_note', { id: item.id, note }).catch(() => {})
+          }}>Note</button>
+          {isText && item.is_sensitive && revealed === null && (
+            <button onClick={async e => {
+              e.stopPropagation()
+              try {
+                const r = await tauriInvoke<{ content: string, mask_after_ms: number }>('reveal_item', { id: item.id })
+                setRevealed(r.content)
+                setTimeout(() => setRevealed(null), r.mask_after_ms)
+              } catch {}
+            }}>Reveal</button>
+          )}
+          {encoded && decoded === null && (
+            <button onClick={async e => {
+              e.stopPropagation()
+              try {
+                const r = await tauriInvoke<DecodedPreview | null>('decode_item', { id: item.id })
+                if (r) setDecoded(r)
+              } catch {}
+            }}>Decode</button>
+          )}
           {item.kind === 'file' && item.file_path && (
             <button onClick={e => {
               e.stopPropagation();
               window.__REVEAL?.(item.file_path!)
             }}>Reveal in Finder</button>
           )}
+          {item.kind === 'file' && item.stored_len === 0 && (
+            <button title="Store the file's contents so this entry survives the original being deleted" onClick={e => {
+              e.stopPropagation();
+              tauriInvoke('materialize_file', { id: item.id }).catch(() => {})
+            }}>Keep Copy</button>
+          )}
         </div>
       </div>
     </div>
   )
 }
 
+// Mirrors the backend check loosely; the preview is only the first 100 chars.
+function looksEncoded(preview?: string | null): boolean {
+  if (!preview) return false
+  const t = preview.trim()
+  if (/^data:[^,]*;base64,/.test(t)) return true
+  return t.length >= 64 && /^[A-Za-z0-9+/_=-]+$/.test(t) && !/^[0-9a-fA-F]+$/.test(t)
+}
+
+type DecodedPreview =
+  | { type: 'text', text: string, truncated: boolean }
+  | { type: 'image', data_url: string }
+  | { type: 'binary', bytes: number }
+
+function DecodedView({ preview }: { preview: DecodedPreview }) {
+  if (preview.type === 'image') {
+    return <img src={preview.data_url} alt="decoded" style={{ maxWidth: 128, maxHeight: 96, borderRadius: 6, marginTop: 6 }} />
+  }
+  if (preview.type === 'binary') {
+    return <div style={{ marginTop: 6, fontSize: 11, opacity: 0.8 }}>Binary data, {formatSize(preview.bytes)}</div>
+  }
+  return (
+    <pre style={{ marginTop: 6, maxHeight: 120, overflow: 'auto', fontSize: 11, opacity: 0.8, whiteSpace: 'pre-wrap' }}>
+      {preview.text}{preview.truncated ? '\n…' : ''}
+    </pre>
+  )
+}
+
 function formatSize(n: number): string {
   if (n <= 0) return '—'
   const units = ['B','KB','MB','GB','TB']
@@ -72,12 +155,36 @@ function PreviewImage({ id }: { id: number }) {
   const [src, setSrc] = React.useState<string | null>(null)
   React.useEffect(() => {
     let alive = true
-    tauriInvoke<string>('get_image_preview', { id, max: 128 }).then((data) => {
-      if (alive) setSrc(data)
+    tauriInvoke<{ data_url: string, downsized: boolean }>('get_image_preview', { id, max: 128 }).then((data) => {
+      if (alive) setSrc(data.data_url)
     }).catch(() => {})
     return () => { alive = false }
   }, [id])
   if (!src) return null
   return <img src={src} alt="preview" style={{ maxWidth: 128, maxHeight: 96, borderRadius: 6, marginTop: 6 }} />
 }
+type FilePreview =
+  | { type: 'text', snippet: string, truncated: boolean }
+  | { type: 'image', data_url: string }
+
+function PreviewFile({ id }: { id: number }) {
+  const [preview, setPreview] = React.useState<FilePreview | null>(null)
+  React.useEffect(() => {
+    let alive = true
+    tauriInvoke<FilePreview | null>('get_file_preview', { id }).then((data) => {
+      if (alive) setPreview(data)
+    }).catch(() => {})
+    return () => { alive = false }
+  }, [id])
+  if (!preview) return null
+  if (preview.type === 'image') {
+    return <img src={preview.data_url} alt="preview" style={{ maxWidth: 128, maxHeight: 96, borderRadius: 6, marginTop: 6 }} />
+  }
+  return (
+    <pre style={{ marginTop: 6, maxHeight: 120, overflow: 'hidden', fontSize: 11, opacity: 0.8, whiteSpace: 'pre-wrap' }}>
+      {preview.snippet}{preview.truncated ? '\n…' : ''}
+    </pre>
+  )
+}
+
 import { invoke as tauriInvoke } from '@tauri-apps/api/tauri'